@@ -0,0 +1,283 @@
+use cgmath::SquareMatrix;
+use winit::window::{CursorGrabMode, Window};
+
+use crate::{
+    core::{bounds::BoundingSphere, picking::Ray, texture::DepthMode},
+    input::{event::InputEvent, ActionMap},
+};
+
+use self::{fly::FlyController, orbit::OrbitController};
+
+pub mod fly;
+pub mod orbit;
+pub mod path;
+pub mod view;
+
+// maps OpenGL depth -1..1 to wgpu's 0..1, columns as arguments like every cgmath matrix
+#[rustfmt::skip]
+pub const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
+    1.0, 0.0, 0.0, 0.0,
+    0.0, 1.0, 0.0, 0.0,
+    0.0, 0.0, 0.5, 0.0,
+    0.0, 0.0, 0.5, 1.0,
+);
+
+// maps wgpu depth d to 1 - d
+#[rustfmt::skip]
+pub const REVERSE_Z_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
+    1.0, 0.0, 0.0, 0.0,
+    0.0, 1.0, 0.0, 0.0,
+    0.0, 0.0, -1.0, 0.0,
+    0.0, 0.0, 1.0, 1.0,
+);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Projection {
+    // vertical field of view in degrees
+    Perspective { fovy: f32 },
+    // vertical extent in world units, the width follows the aspect ratio
+    Orthographic { height: f32 },
+}
+
+pub struct Camera {
+    pub eye: cgmath::Point3<f32>,
+    pub target: cgmath::Point3<f32>,
+    pub up: cgmath::Vector3<f32>,
+    pub aspect: f32,
+    pub projection: Projection,
+    pub znear: f32,
+    // unused by reverse-Z perspective projections, their far plane is at infinity
+    pub zfar: f32,
+    pub depth_mode: DepthMode,
+}
+
+impl Camera {
+    pub fn build_view_projection_matrix(&self) -> cgmath::Matrix4<f32> {
+        // 1.
+        let view = cgmath::Matrix4::look_at_rh(self.eye, self.target, self.up);
+        // 2.
+        let proj = match self.projection {
+            Projection::Perspective { fovy } if self.depth_mode == DepthMode::ReverseZ => {
+                // already in wgpu clip space, depth = znear / distance
+                let f = 1.0 / (fovy * 0.5).to_radians().tan();
+
+                #[rustfmt::skip]
+                let proj = cgmath::Matrix4::new(
+                    f / self.aspect, 0.0, 0.0, 0.0,
+                    0.0, f, 0.0, 0.0,
+                    0.0, 0.0, 0.0, -1.0,
+                    0.0, 0.0, self.znear, 0.0,
+                );
+
+                return proj * view;
+            }
+            Projection::Perspective { fovy } => {
+                cgmath::perspective(cgmath::Deg(fovy), self.aspect, self.znear, self.zfar)
+            }
+            Projection::Orthographic { height } => {
+                let half_height = height * 0.5;
+                let half_width = half_height * self.aspect;
+
+                cgmath::ortho(
+                    -half_width,
+                    half_width,
+                    -half_height,
+                    half_height,
+                    self.znear,
+                    self.zfar,
+                )
+            }
+        };
+
+        // 3.
+        match self.depth_mode {
+            DepthMode::Standard => OPENGL_TO_WGPU_MATRIX * proj * view,
+            DepthMode::ReverseZ => REVERSE_Z_MATRIX * OPENGL_TO_WGPU_MATRIX * proj * view,
+        }
+    }
+
+    /// World space ray through a point in normalized device coordinates, x and y in -1..1
+    /// with +y up. Its direction is normalized.
+    pub fn ray(&self, ndc_x: f32, ndc_y: f32) -> Ray {
+        use cgmath::InnerSpace;
+
+        let inverse = self
+            .build_view_projection_matrix()
+            .invert()
+            .unwrap_or_else(cgmath::Matrix4::identity);
+
+        let unproject = |depth: f32| {
+            let point = inverse * cgmath::Vector4::new(ndc_x, ndc_y, depth, 1.0);
+            cgmath::Point3::new(point.x, point.y, point.z) / point.w
+        };
+        // the reverse-Z far plane is at infinity, any depth behind the near plane will do
+        let (near, far) = match self.depth_mode {
+            DepthMode::Standard => (unproject(0.0), unproject(1.0)),
+            DepthMode::ReverseZ => (unproject(1.0), unproject(0.5)),
+        };
+
+        Ray {
+            origin: near,
+            direction: (far - near).normalize(),
+        }
+    }
+
+    pub fn update_aspect(&mut self, aspect: f32) {
+        self.aspect = aspect;
+    }
+
+    /// Switches between perspective and orthographic projection, keeping objects at the
+    /// target distance the same size on screen.
+    pub fn toggle_projection(&mut self) {
+        use cgmath::MetricSpace;
+
+        let distance = self.eye.distance(self.target);
+
+        self.projection = match self.projection {
+            Projection::Perspective { fovy } => Projection::Orthographic {
+                height: 2.0 * distance * (fovy * 0.5).to_radians().tan(),
+            },
+            Projection::Orthographic { height } => Projection::Perspective {
+                fovy: (2.0 * (height * 0.5 / distance).atan()).to_degrees(),
+            },
+        };
+    }
+
+    /// Fraction of the viewport height the sphere covers, above 1 when it fills the view.
+    pub fn screen_size(&self, sphere: &BoundingSphere) -> f32 {
+        use cgmath::MetricSpace;
+
+        match self.projection {
+            Projection::Perspective { fovy } => {
+                let distance = self.eye.distance(sphere.center);
+                if distance <= sphere.radius {
+                    return f32::INFINITY;
+                }
+                sphere.radius / (distance * (fovy * 0.5).to_radians().tan())
+            }
+            Projection::Orthographic { height } => 2.0 * sphere.radius / height,
+        }
+    }
+
+    /// Looks at the sphere's center from just far enough away for all of it to fit the
+    /// view, keeping the viewing direction.
+    pub fn frame(&mut self, sphere: &BoundingSphere) {
+        use cgmath::{InnerSpace, MetricSpace};
+
+        let forward = (self.target - self.eye).normalize();
+
+        let distance = match &mut self.projection {
+            Projection::Perspective { fovy } => {
+                // the narrower of the vertical and horizontal field of view decides
+                let half_fovy = (*fovy * 0.5).to_radians();
+                let half_fovx = (half_fovy.tan() * self.aspect).atan();
+                sphere.radius / half_fovy.min(half_fovx).sin()
+            }
+            Projection::Orthographic { height } => {
+                *height = 2.0 * sphere.radius * (1.0 / self.aspect).max(1.0);
+                // the distance doesn't change the size, keep it
+                self.eye.distance(self.target)
+            }
+        }
+        // with the near plane clear of the sphere
+        .max(sphere.radius + self.znear);
+
+        self.target = sphere.center;
+        self.eye = sphere.center - forward * distance;
+    }
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Camera {
+            eye: (0.0, 1.3, 6.0).into(),
+            target: (0.0, 0.0, 0.0).into(),
+            up: cgmath::Vector3::unit_y(),
+            aspect: 4.0 / 3.0,
+            projection: Projection::Perspective { fovy: 45.0 },
+            znear: 0.1,
+            zfar: 100.0,
+            depth_mode: DepthMode::Standard,
+        }
+    }
+}
+
+pub enum CameraController {
+    Orbit(OrbitController),
+    Fly(FlyController),
+}
+
+impl CameraController {
+    pub fn process(&mut self, event: &InputEvent) {
+        match self {
+            CameraController::Orbit(controller) => controller.process(event),
+            CameraController::Fly(controller) => controller.process(event),
+        }
+    }
+
+    pub fn update(&mut self, camera: &mut Camera, dt: f32, input: &ActionMap) {
+        match self {
+            CameraController::Orbit(controller) => controller.update(camera, dt, input),
+            CameraController::Fly(controller) => controller.update(camera, dt, input),
+        }
+    }
+
+    /// Picks up the camera's current state, e.g. after it was moved by a `CameraPath`.
+    pub fn reset(&mut self, camera: &Camera) {
+        *self = match self {
+            CameraController::Orbit(_) => CameraController::Orbit(OrbitController::new(camera)),
+            CameraController::Fly(_) => CameraController::Fly(FlyController::new(camera)),
+        };
+    }
+
+    /// Switches between orbit and fly mode, grabbing the cursor of the window (if there is
+    /// one) while flying.
+    pub fn toggle(&mut self, camera: &Camera, window: Option<&Window>) {
+        *self = match self {
+            CameraController::Orbit(_) => {
+                if let Some(window) = window {
+                    // Locked isn't available everywhere (e.g. X11), Confined is the fallback
+                    let grabbed = window
+                        .set_cursor_grab(CursorGrabMode::Locked)
+                        .or_else(|_| window.set_cursor_grab(CursorGrabMode::Confined));
+                    if let Err(err) = grabbed {
+                        log::warn!("Failed to grab the cursor: {}", err);
+                    }
+                    window.set_cursor_visible(false);
+                }
+
+                CameraController::Fly(FlyController::new(camera))
+            }
+            CameraController::Fly(_) => {
+                if let Some(window) = window {
+                    let _ = window.set_cursor_grab(CursorGrabMode::None);
+                    window.set_cursor_visible(true);
+                }
+
+                CameraController::Orbit(OrbitController::new(camera))
+            }
+        };
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct CameraUniform {
+    pub view_proj: [[f32; 4]; 4],
+    // w is unused, vec3 would be padded to 16 bytes anyway
+    pub view_position: [f32; 4],
+}
+
+impl CameraUniform {
+    pub fn new() -> Self {
+        CameraUniform {
+            view_proj: cgmath::Matrix4::identity().into(),
+            view_position: [0.0; 4],
+        }
+    }
+
+    pub fn update_view_proj(&mut self, camera: &Camera) {
+        self.view_proj = camera.build_view_projection_matrix().into();
+        self.view_position = camera.eye.to_homogeneous().into();
+    }
+}
@@ -0,0 +1,69 @@
+use crate::input::{event::InputEvent, ActionMap};
+use cgmath::{InnerSpace, Vector3};
+
+use super::Camera;
+
+const PITCH_LIMIT: f32 = std::f32::consts::FRAC_PI_2 - 0.01;
+
+/// Free-fly camera: the `move_forward`, `move_right` and `move_up` axes move along the view
+/// direction (WASD and Q/E by default), `sprint` speeds up and raw mouse motion turns the view.
+pub struct FlyController {
+    yaw: f32,
+    pitch: f32,
+    // world units per second
+    pub speed: f32,
+    pub sprint_multiplier: f32,
+    // radians per pixel
+    pub sensitivity: f32,
+}
+
+impl FlyController {
+    pub fn new(camera: &Camera) -> Self {
+        let direction = (camera.target - camera.eye).normalize();
+
+        FlyController {
+            yaw: direction.x.atan2(direction.z),
+            pitch: direction.y.asin().clamp(-PITCH_LIMIT, PITCH_LIMIT),
+            speed: 4.0,
+            sprint_multiplier: 4.0,
+            sensitivity: 0.002,
+        }
+    }
+
+    fn direction(&self) -> Vector3<f32> {
+        Vector3::new(
+            self.pitch.cos() * self.yaw.sin(),
+            self.pitch.sin(),
+            self.pitch.cos() * self.yaw.cos(),
+        )
+    }
+
+    pub fn process(&mut self, event: &InputEvent) {
+        if let InputEvent::MouseMotion { dx, dy } = *event {
+            self.yaw -= dx as f32 * self.sensitivity;
+            self.pitch =
+                (self.pitch - dy as f32 * self.sensitivity).clamp(-PITCH_LIMIT, PITCH_LIMIT);
+        }
+    }
+
+    pub fn update(&mut self, camera: &mut Camera, dt: f32, input: &ActionMap) {
+        let forward = self.direction();
+        let right = forward.cross(Vector3::unit_y()).normalize();
+
+        let velocity = forward * input.axis("move_forward")
+            + right * input.axis("move_right")
+            + Vector3::unit_y() * input.axis("move_up");
+
+        if velocity.magnitude2() > 0.0 {
+            let speed = if input.held("sprint") {
+                self.speed * self.sprint_multiplier
+            } else {
+                self.speed
+            };
+
+            camera.eye += velocity.normalize() * speed * dt;
+        }
+
+        camera.target = camera.eye + forward;
+    }
+}
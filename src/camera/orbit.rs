@@ -0,0 +1,132 @@
+use crate::input::{event::InputEvent, ActionMap};
+use cgmath::{InnerSpace, Point3, Vector3};
+
+use super::{Camera, Projection};
+
+// keeps the camera from flipping over the poles
+const PITCH_LIMIT: f32 = std::f32::consts::FRAC_PI_2 - 0.01;
+
+#[derive(Clone, Copy)]
+struct Orbit {
+    target: Point3<f32>,
+    yaw: f32,
+    pitch: f32,
+    distance: f32,
+}
+
+impl Orbit {
+    fn eye(&self) -> Point3<f32> {
+        let offset = Vector3::new(
+            self.pitch.cos() * self.yaw.sin(),
+            self.pitch.sin(),
+            self.pitch.cos() * self.yaw.cos(),
+        );
+
+        self.target + offset * self.distance
+    }
+}
+
+/// Orbits `Camera::eye` around `Camera::target`: dragging with `orbit_rotate` rotates, with
+/// `orbit_pan` pans (left and middle button by default), the scroll wheel zooms.
+pub struct OrbitController {
+    current: Orbit,
+    goal: Orbit,
+    // mouse motion since the last update, in pixels
+    motion: (f32, f32),
+    // radians per pixel
+    pub rotate_speed: f32,
+    // world units per pixel at distance 1
+    pub pan_speed: f32,
+    // fraction of the distance per wheel line
+    pub zoom_speed: f32,
+    pub min_distance: f32,
+    pub max_distance: f32,
+    // higher values follow the input faster, 0 disables smoothing
+    pub smoothing: f32,
+}
+
+impl OrbitController {
+    pub fn new(camera: &Camera) -> Self {
+        let offset = camera.eye - camera.target;
+        let distance = offset.magnitude();
+
+        let orbit = Orbit {
+            target: camera.target,
+            yaw: offset.x.atan2(offset.z),
+            pitch: (offset.y / distance)
+                .asin()
+                .clamp(-PITCH_LIMIT, PITCH_LIMIT),
+            distance,
+        };
+
+        OrbitController {
+            current: orbit,
+            goal: orbit,
+            motion: (0.0, 0.0),
+            rotate_speed: 0.005,
+            pan_speed: 0.0015,
+            zoom_speed: 0.1,
+            min_distance: 0.5,
+            max_distance: 80.0,
+            smoothing: 15.0,
+        }
+    }
+
+    pub fn process(&mut self, event: &InputEvent) {
+        match *event {
+            InputEvent::Wheel { lines } => {
+                self.goal.distance = (self.goal.distance * (1.0 - lines * self.zoom_speed))
+                    .clamp(self.min_distance, self.max_distance);
+            }
+            InputEvent::MouseMotion { dx, dy } => {
+                self.motion.0 += dx as f32;
+                self.motion.1 += dy as f32;
+            }
+            _ => {}
+        }
+    }
+
+    pub fn update(&mut self, camera: &mut Camera, dt: f32, input: &ActionMap) {
+        let (dx, dy) = std::mem::take(&mut self.motion);
+
+        if input.held("orbit_rotate") {
+            self.goal.yaw -= dx * self.rotate_speed;
+            self.goal.pitch =
+                (self.goal.pitch + dy * self.rotate_speed).clamp(-PITCH_LIMIT, PITCH_LIMIT);
+        }
+
+        if input.held("orbit_pan") {
+            let forward = (self.goal.target - self.goal.eye()).normalize();
+            let right = forward.cross(Vector3::unit_y()).normalize();
+            let up = right.cross(forward);
+            let scale = self.pan_speed * self.goal.distance;
+
+            self.goal.target += (up * dy - right * dx) * scale;
+        }
+
+        let t = if self.smoothing > 0.0 {
+            1.0 - (-self.smoothing * dt).exp()
+        } else {
+            1.0
+        };
+
+        let lerp = |from: f32, to: f32| from + (to - from) * t;
+
+        let previous_distance = self.current.distance;
+
+        self.current = Orbit {
+            target: self.current.target + (self.goal.target - self.current.target) * t,
+            yaw: lerp(self.current.yaw, self.goal.yaw),
+            pitch: lerp(self.current.pitch, self.goal.pitch),
+            distance: lerp(self.current.distance, self.goal.distance),
+        };
+
+        // moving the eye doesn't change an orthographic view, so zoom scales the extent instead
+        if let Projection::Orthographic { height } = &mut camera.projection {
+            *height *= self.current.distance / previous_distance;
+        }
+
+        camera.eye = self.current.eye();
+        camera.target = self.current.target;
+    }
+}
@@ -0,0 +1,603 @@
+use std::{fs, path::Path};
+
+use anyhow::{anyhow, Context};
+use cgmath::{Point3, Vector3};
+
+use super::{Camera, Projection};
+
+#[derive(Clone, Copy, Debug)]
+pub struct Keyframe {
+    // seconds from the start of the path
+    pub time: f32,
+    pub eye: Point3<f32>,
+    pub target: Point3<f32>,
+    pub projection: Projection,
+}
+
+impl Keyframe {
+    pub fn from_camera(camera: &Camera, time: f32) -> Self {
+        Keyframe {
+            time,
+            eye: camera.eye,
+            target: camera.target,
+            projection: camera.projection,
+        }
+    }
+}
+
+// the field of view or the height, whichever the projection has
+fn projection_value(projection: Projection) -> f32 {
+    match projection {
+        Projection::Perspective { fovy } => fovy,
+        Projection::Orthographic { height } => height,
+    }
+}
+
+fn with_projection_value(projection: Projection, value: f32) -> Projection {
+    match projection {
+        Projection::Perspective { .. } => Projection::Perspective { fovy: value },
+        Projection::Orthographic { .. } => Projection::Orthographic { height: value },
+    }
+}
+
+fn same_kind(a: Projection, b: Projection) -> bool {
+    std::mem::discriminant(&a) == std::mem::discriminant(&b)
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Interpolation {
+    CatmullRom,
+    // cubic Bézier with control points placed along the Catmull-Rom tangents, a tension of 1
+    // gives the Catmull-Rom curve itself
+    Bezier { tension: f32 },
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Easing {
+    Linear,
+    EaseIn,
+    EaseOut,
+    EaseInOut,
+}
+
+impl Easing {
+    fn apply(&self, t: f32) -> f32 {
+        match self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t,
+            Easing::EaseOut => t * (2.0 - t),
+            Easing::EaseInOut => t * t * (3.0 - 2.0 * t),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Playback {
+    Once,
+    Loop,
+}
+
+/// Camera keyframes played back against the app clock.
+pub struct CameraPath {
+    keyframes: Vec<Keyframe>,
+    pub interpolation: Interpolation,
+    // applied to the whole path, so the camera only speeds up and slows down at its ends
+    pub easing: Easing,
+    pub playback: Playback,
+    // app time the playback started at, None while stopped
+    started_at: Option<f32>,
+}
+
+impl CameraPath {
+    pub fn new() -> Self {
+        CameraPath {
+            keyframes: Vec::new(),
+            interpolation: Interpolation::CatmullRom,
+            easing: Easing::Linear,
+            playback: Playback::Loop,
+            started_at: None,
+        }
+    }
+
+    pub fn keyframes(&self) -> &[Keyframe] {
+        &self.keyframes
+    }
+
+    pub fn duration(&self) -> f32 {
+        self.keyframes.last().map_or(0.0, |keyframe| keyframe.time)
+    }
+
+    /// Appends the camera's current state, `interval` seconds after the last keyframe.
+    pub fn record(&mut self, camera: &Camera, interval: f32) {
+        let time = match self.keyframes.last() {
+            Some(last) => last.time + interval,
+            None => 0.0,
+        };
+
+        self.keyframes.push(Keyframe::from_camera(camera, time));
+    }
+
+    pub fn clear(&mut self) {
+        self.keyframes.clear();
+        self.started_at = None;
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.started_at.is_some()
+    }
+
+    pub fn play(&mut self, now: f32) {
+        if self.keyframes.len() >= 2 && self.duration() > 0.0 {
+            self.started_at = Some(now);
+        }
+    }
+
+    pub fn stop(&mut self) {
+        self.started_at = None;
+    }
+
+    /// Moves the camera to the path position at app time `now`. Returns false once a
+    /// one-shot playback has finished or nothing is playing.
+    pub fn update(&mut self, camera: &mut Camera, now: f32) -> bool {
+        let Some(started_at) = self.started_at else {
+            return false;
+        };
+
+        let duration = self.duration();
+        let mut time = now - started_at;
+
+        if time >= duration {
+            match self.playback {
+                Playback::Once => {
+                    self.started_at = None;
+                    time = duration;
+                }
+                Playback::Loop => time %= duration,
+            }
+        }
+
+        let Some(keyframe) = self.sample(time) else {
+            self.started_at = None;
+            return false;
+        };
+
+        camera.eye = keyframe.eye;
+        camera.target = keyframe.target;
+        camera.projection = keyframe.projection;
+
+        self.started_at.is_some()
+    }
+
+    /// The path position at `time` seconds from its start, None for a path without keyframes.
+    pub fn sample(&self, time: f32) -> Option<Keyframe> {
+        let keyframes = &self.keyframes;
+        let last = keyframes.len().checked_sub(1)?;
+
+        let duration = self.duration();
+        let eased = if duration > 0.0 {
+            self.easing.apply((time / duration).clamp(0.0, 1.0)) * duration
+        } else {
+            time
+        };
+
+        let segment = keyframes
+            .iter()
+            .rposition(|keyframe| keyframe.time <= eased)
+            .unwrap_or(0)
+            .min(last.saturating_sub(1));
+
+        let p1 = &keyframes[segment];
+        let p2 = &keyframes[(segment + 1).min(last)];
+        let p0 = &keyframes[segment.saturating_sub(1)];
+        let p3 = &keyframes[(segment + 2).min(last)];
+
+        let length = p2.time - p1.time;
+        let t = if length > 0.0 {
+            ((eased - p1.time) / length).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+
+        let curve = |a: Vector3<f32>, b: Vector3<f32>, c: Vector3<f32>, d: Vector3<f32>| match self
+            .interpolation
+        {
+            Interpolation::CatmullRom => catmull_rom(a, b, c, d, t),
+            // the Catmull-Rom tangent at b is (c - a) / 2, and a third of it makes the handle
+            Interpolation::Bezier { tension } => {
+                let c1 = b + (c - a) * (tension / 6.0);
+                let c2 = c - (d - b) * (tension / 6.0);
+                bezier(b, c1, c2, c, t)
+            }
+        };
+
+        let vector = |point: Point3<f32>| Vector3::new(point.x, point.y, point.z);
+        let point = |vector: Vector3<f32>| Point3::new(vector.x, vector.y, vector.z);
+        let scalar = |value: f32| Vector3::new(value, 0.0, 0.0);
+
+        // the fov or height follows the curve between keyframes of the same projection,
+        // neighbours of the other kind stand in as the nearer end; a change of projection
+        // happens at the keyframe
+        let projection = if same_kind(p1.projection, p2.projection) {
+            let value = |keyframe: &Keyframe, nearer: &Keyframe| {
+                if same_kind(keyframe.projection, nearer.projection) {
+                    projection_value(keyframe.projection)
+                } else {
+                    projection_value(nearer.projection)
+                }
+            };
+            let value = curve(
+                scalar(value(p0, p1)),
+                scalar(value(p1, p1)),
+                scalar(value(p2, p2)),
+                scalar(value(p3, p2)),
+            )
+            .x;
+            with_projection_value(p1.projection, value)
+        } else if t < 1.0 {
+            p1.projection
+        } else {
+            p2.projection
+        };
+
+        Some(Keyframe {
+            time,
+            eye: point(curve(
+                vector(p0.eye),
+                vector(p1.eye),
+                vector(p2.eye),
+                vector(p3.eye),
+            )),
+            target: point(curve(
+                vector(p0.target),
+                vector(p1.target),
+                vector(p2.target),
+                vector(p3.target),
+            )),
+            projection,
+        })
+    }
+
+    /// Playback settings as `interpolation`, `easing` and `playback` lines, followed by one
+    /// keyframe per line: `time eye.x eye.y eye.z target.x target.y target.z fovy`, with
+    /// `ortho height` in place of `fovy` for orthographic keyframes.
+    pub fn save(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let interpolation = match self.interpolation {
+            Interpolation::CatmullRom => String::from("catmull-rom"),
+            Interpolation::Bezier { tension } => format!("bezier {}", tension),
+        };
+        let easing = match self.easing {
+            Easing::Linear => "linear",
+            Easing::EaseIn => "ease-in",
+            Easing::EaseOut => "ease-out",
+            Easing::EaseInOut => "ease-in-out",
+        };
+        let playback = match self.playback {
+            Playback::Once => "once",
+            Playback::Loop => "loop",
+        };
+
+        let mut text = format!(
+            "interpolation {}\neasing {}\nplayback {}\n",
+            interpolation, easing, playback
+        );
+        text.push_str("# time eye.x eye.y eye.z target.x target.y target.z fovy|ortho height\n");
+
+        for keyframe in &self.keyframes {
+            let projection = match keyframe.projection {
+                Projection::Perspective { fovy } => fovy.to_string(),
+                Projection::Orthographic { height } => format!("ortho {}", height),
+            };
+            text.push_str(&format!(
+                "{} {} {} {} {} {} {} {}\n",
+                keyframe.time,
+                keyframe.eye.x,
+                keyframe.eye.y,
+                keyframe.eye.z,
+                keyframe.target.x,
+                keyframe.target.y,
+                keyframe.target.z,
+                projection
+            ));
+        }
+
+        fs::write(path, text)?;
+
+        Ok(())
+    }
+
+    /// Replaces the keyframes and the settings the file has with what `save` wrote. On an
+    /// error the path is left as it was.
+    pub fn load(&mut self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read camera path {}", path.display()))?;
+
+        let mut keyframes = Vec::new();
+        let mut interpolation = self.interpolation;
+        let mut easing = self.easing;
+        let mut playback = self.playback;
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let invalid = || {
+                anyhow!(
+                    "{}:{}: invalid setting '{}'",
+                    path.display(),
+                    number + 1,
+                    line
+                )
+            };
+
+            let mut words = line.split_whitespace();
+            match words.next() {
+                Some("interpolation") => {
+                    interpolation = match (words.next(), words.next()) {
+                        (Some("catmull-rom"), None) => Interpolation::CatmullRom,
+                        (Some("bezier"), tension) => Interpolation::Bezier {
+                            tension: match tension {
+                                Some(tension) => tension.parse().map_err(|_| invalid())?,
+                                None => 1.0,
+                            },
+                        },
+                        _ => return Err(invalid()),
+                    };
+                    continue;
+                }
+                Some("easing") => {
+                    easing = match words.next() {
+                        Some("linear") => Easing::Linear,
+                        Some("ease-in") => Easing::EaseIn,
+                        Some("ease-out") => Easing::EaseOut,
+                        Some("ease-in-out") => Easing::EaseInOut,
+                        _ => return Err(invalid()),
+                    };
+                    continue;
+                }
+                Some("playback") => {
+                    playback = match words.next() {
+                        Some("once") => Playback::Once,
+                        Some("loop") => Playback::Loop,
+                        _ => return Err(invalid()),
+                    };
+                    continue;
+                }
+                _ => {}
+            }
+
+            let mut words = line.split_whitespace().collect::<Vec<_>>();
+            let orthographic = words.len() == 9 && words[7] == "ortho";
+            if orthographic {
+                words.remove(7);
+            }
+
+            let values = words
+                .into_iter()
+                .map(str::parse::<f32>)
+                .collect::<Result<Vec<_>, _>>()
+                .with_context(|| format!("{}:{}: invalid number", path.display(), number + 1))?;
+
+            let [time, ex, ey, ez, tx, ty, tz, projection] = values[..] else {
+                return Err(anyhow!(
+                    "{}:{}: expected 8 values, found {}",
+                    path.display(),
+                    number + 1,
+                    values.len()
+                ));
+            };
+
+            keyframes.push(Keyframe {
+                time,
+                eye: Point3::new(ex, ey, ez),
+                target: Point3::new(tx, ty, tz),
+                projection: if orthographic {
+                    Projection::Orthographic { height: projection }
+                } else {
+                    Projection::Perspective { fovy: projection }
+                },
+            });
+        }
+
+        keyframes.sort_by(|a, b| a.time.total_cmp(&b.time));
+
+        self.keyframes = keyframes;
+        self.interpolation = interpolation;
+        self.easing = easing;
+        self.playback = playback;
+        self.started_at = None;
+
+        Ok(())
+    }
+}
+
+fn catmull_rom(
+    p0: Vector3<f32>,
+    p1: Vector3<f32>,
+    p2: Vector3<f32>,
+    p3: Vector3<f32>,
+    t: f32,
+) -> Vector3<f32> {
+    let t2 = t * t;
+    let t3 = t2 * t;
+
+    (p1 * 2.0
+        + (p2 - p0) * t
+        + (p0 * 2.0 - p1 * 5.0 + p2 * 4.0 - p3) * t2
+        + (p1 * 3.0 - p0 - p2 * 3.0 + p3) * t3)
+        * 0.5
+}
+
+fn bezier(
+    p0: Vector3<f32>,
+    p1: Vector3<f32>,
+    p2: Vector3<f32>,
+    p3: Vector3<f32>,
+    t: f32,
+) -> Vector3<f32> {
+    let u = 1.0 - t;
+
+    p0 * (u * u * u) + p1 * (3.0 * u * u * t) + p2 * (3.0 * u * t * t) + p3 * (t * t * t)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keyframe(time: f32, x: f32) -> Keyframe {
+        Keyframe {
+            time,
+            eye: Point3::new(x, 0.0, 0.0),
+            target: Point3::new(x, 0.0, -1.0),
+            projection: Projection::Perspective { fovy: 45.0 + x },
+        }
+    }
+
+    fn path(keyframes: &[Keyframe]) -> CameraPath {
+        let mut path = CameraPath::new();
+        path.keyframes = keyframes.to_vec();
+        path
+    }
+
+    fn eye_x(path: &CameraPath, time: f32) -> f32 {
+        path.sample(time).unwrap().eye.x
+    }
+
+    #[test]
+    fn empty_path_has_no_samples() {
+        assert!(path(&[]).sample(0.0).is_none());
+        assert_eq!(eye_x(&path(&[keyframe(0.0, 3.0)]), 1.0), 3.0);
+    }
+
+    #[test]
+    fn passes_through_keyframes() {
+        let path = path(&[
+            keyframe(0.0, 0.0),
+            keyframe(1.0, 2.0),
+            keyframe(3.0, -1.0),
+            keyframe(4.0, 5.0),
+        ]);
+
+        for keyframe in path.keyframes() {
+            let sample = path.sample(keyframe.time).unwrap();
+            assert!((sample.eye.x - keyframe.eye.x).abs() < 1e-5);
+            assert!((sample.target.x - keyframe.target.x).abs() < 1e-5);
+            let fovy = projection_value(sample.projection);
+            assert!((fovy - projection_value(keyframe.projection)).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn unit_tension_bezier_is_catmull_rom() {
+        let keyframes = [
+            keyframe(0.0, 0.0),
+            keyframe(1.0, 2.0),
+            keyframe(2.0, -1.0),
+            keyframe(3.0, 5.0),
+        ];
+        let catmull_rom = path(&keyframes);
+        let mut bezier = path(&keyframes);
+        bezier.interpolation = Interpolation::Bezier { tension: 1.0 };
+
+        for step in 0..=30 {
+            let time = step as f32 * 0.1;
+            assert!((eye_x(&catmull_rom, time) - eye_x(&bezier, time)).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn easing_spans_the_whole_path() {
+        // evenly spaced points on a line, the spline alone moves at a constant speed
+        let mut path = path(&[keyframe(0.0, 0.0), keyframe(1.0, 1.0), keyframe(2.0, 2.0)]);
+        path.easing = Easing::EaseInOut;
+
+        // still at the ends, fastest halfway, not stopping at the middle keyframe
+        assert!(eye_x(&path, 0.01) < 0.01);
+        assert!((eye_x(&path, 1.0) - 1.0).abs() < 1e-5);
+        assert!(eye_x(&path, 1.01) - eye_x(&path, 0.99) > 0.02);
+    }
+
+    #[test]
+    fn failed_load_keeps_the_path() {
+        let file = std::env::temp_dir().join(format!("camera_path_{}.txt", std::process::id()));
+
+        let mut saved = path(&[keyframe(0.0, 0.0), keyframe(2.0, 1.0)]);
+        saved.interpolation = Interpolation::Bezier { tension: 0.5 };
+        saved.easing = Easing::EaseOut;
+        saved.playback = Playback::Once;
+        saved.save(&file).unwrap();
+
+        let mut loaded = CameraPath::new();
+        loaded.load(&file).unwrap();
+        assert_eq!(loaded.keyframes().len(), 2);
+        assert!(matches!(
+            loaded.interpolation,
+            Interpolation::Bezier { tension } if tension == 0.5
+        ));
+        assert!(matches!(loaded.easing, Easing::EaseOut));
+        assert_eq!(loaded.playback, Playback::Once);
+
+        fs::write(&file, "playback loop\neasing linear\n0 1 2 3 4 5 6\n").unwrap();
+        assert!(loaded.load(&file).is_err());
+        assert_eq!(loaded.playback, Playback::Once);
+        assert!(matches!(loaded.easing, Easing::EaseOut));
+        assert_eq!(loaded.keyframes().len(), 2);
+
+        fs::remove_file(&file).unwrap();
+    }
+
+    #[test]
+    fn orthographic_keyframes_keep_their_height() {
+        let camera = Camera {
+            projection: Projection::Orthographic { height: 12.0 },
+            ..Camera::default()
+        };
+        let mut saved = CameraPath::new();
+        saved.record(&camera, 1.0);
+        saved.record(
+            &Camera {
+                projection: Projection::Orthographic { height: 20.0 },
+                ..camera
+            },
+            2.0,
+        );
+        saved.record(&Camera::default(), 1.0);
+
+        let file = std::env::temp_dir().join(format!("ortho_path_{}.txt", std::process::id()));
+        saved.save(&file).unwrap();
+        let mut loaded = CameraPath::new();
+        loaded.load(&file).unwrap();
+        fs::remove_file(&file).unwrap();
+
+        let projections = loaded
+            .keyframes()
+            .iter()
+            .map(|keyframe| keyframe.projection)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            projections,
+            [
+                Projection::Orthographic { height: 12.0 },
+                Projection::Orthographic { height: 20.0 },
+                Projection::Perspective { fovy: 45.0 },
+            ]
+        );
+
+        // the height follows the curve between orthographic keyframes
+        match loaded.sample(1.0).unwrap().projection {
+            Projection::Orthographic { height } => assert!(height > 12.0 && height < 20.0),
+            projection => panic!("{:?}", projection),
+        }
+        // and switches to perspective at the keyframe that has it
+        let sample = |time| loaded.sample(time).unwrap().projection;
+        assert!(matches!(sample(2.5), Projection::Orthographic { .. }));
+        assert_eq!(sample(3.0), Projection::Perspective { fovy: 45.0 });
+
+        let mut camera = Camera::default();
+        loaded.play(0.0);
+        loaded.update(&mut camera, 0.0);
+        assert_eq!(camera.projection, Projection::Orthographic { height: 12.0 });
+    }
+}
@@ -0,0 +1,385 @@
+use wgpu::util::DeviceExt;
+
+use crate::core::{debug::label, picking::Ray};
+
+use super::{Camera, CameraUniform, Projection};
+
+/// Viewport rectangle in fractions of the surface, (0, 0) is the top left corner.
+#[derive(Clone, Copy, Debug)]
+pub struct Viewport {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Viewport {
+    pub const FULL: Viewport = Viewport {
+        x: 0.0,
+        y: 0.0,
+        width: 1.0,
+        height: 1.0,
+    };
+
+    pub const fn quadrant(column: u32, row: u32) -> Viewport {
+        Viewport {
+            x: column as f32 * 0.5,
+            y: row as f32 * 0.5,
+            width: 0.5,
+            height: 0.5,
+        }
+    }
+
+    // both edges rounded, so viewports sharing an edge share it in pixels too; at least a
+    // pixel, inside the surface even when it's empty like while minimized
+    fn to_pixels(self, surface_width: u32, surface_height: u32) -> (u32, u32, u32, u32) {
+        let span = |start: f32, size: f32, surface: u32| {
+            let edge = |fraction: f32| (fraction * surface as f32).round().max(0.0) as u32;
+            let start_pixel = edge(start).min(surface.saturating_sub(1));
+            let end_pixel = edge(start + size).min(surface);
+
+            (start_pixel, end_pixel.saturating_sub(start_pixel).max(1))
+        };
+        let (x, width) = span(self.x, self.width, surface_width);
+        let (y, height) = span(self.y, self.height, surface_height);
+
+        (x, y, width, height)
+    }
+
+    // a surface pixel in the viewport's normalized device coordinates, None outside it
+    fn ndc(self, x: f64, y: f64, surface_width: u32, surface_height: u32) -> Option<(f32, f32)> {
+        let (left, top, width, height) = self.to_pixels(surface_width, surface_height);
+        let x = (x as f32 - left as f32) / width as f32;
+        let y = (y as f32 - top as f32) / height as f32;
+
+        if !(0.0..1.0).contains(&x) || !(0.0..1.0).contains(&y) {
+            return None;
+        }
+
+        Some((x * 2.0 - 1.0, 1.0 - y * 2.0))
+    }
+}
+
+// where the perspective view goes in the quad layout
+const QUAD_PRIMARY: Viewport = Viewport::quadrant(1, 1);
+
+// the axis-aligned views of the quad layout: name, viewport, direction from the target to
+// the eye and up
+const QUAD_AXES: [(&str, Viewport, [f32; 3], [f32; 3]); 3] = [
+    (
+        "Top",
+        Viewport::quadrant(0, 0),
+        [0.0, 1.0, 0.0],
+        [0.0, 0.0, -1.0],
+    ),
+    (
+        "Front",
+        Viewport::quadrant(1, 0),
+        [0.0, 0.0, 1.0],
+        [0.0, 1.0, 0.0],
+    ),
+    (
+        "Side",
+        Viewport::quadrant(0, 1),
+        [1.0, 0.0, 0.0],
+        [0.0, 1.0, 0.0],
+    ),
+];
+
+// the first viewport containing a surface pixel and the pixel in its device coordinates
+fn hit(
+    viewports: impl IntoIterator<Item = Viewport>,
+    x: f64,
+    y: f64,
+    (surface_width, surface_height): (u32, u32),
+) -> Option<(usize, (f32, f32))> {
+    viewports
+        .into_iter()
+        .enumerate()
+        .find_map(|(index, viewport)| {
+            Some((index, viewport.ndc(x, y, surface_width, surface_height)?))
+        })
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ViewLayout {
+    Single,
+    // top, front, side and the perspective camera in the four quadrants
+    Quad,
+}
+
+/// A named camera drawn into its own part of the surface with its own uniform buffer.
+pub struct View {
+    pub name: String,
+    pub camera: Camera,
+    pub viewport: Viewport,
+    // direction from the target to the eye and the up vector of an axis-aligned view
+    axis: Option<(cgmath::Vector3<f32>, cgmath::Vector3<f32>)>,
+    uniform: CameraUniform,
+    buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+}
+
+impl View {
+    pub fn new(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        name: &str,
+        camera: Camera,
+        viewport: Viewport,
+    ) -> Self {
+        let mut uniform = CameraUniform::new();
+        uniform.update_view_proj(&camera);
+
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&label(name, "camera buffer")),
+            contents: bytemuck::bytes_of(&uniform),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            }],
+            label: Some(&label(name, "camera bind group")),
+        });
+
+        View {
+            name: name.to_string(),
+            camera,
+            viewport,
+            axis: None,
+            uniform,
+            buffer,
+            bind_group,
+        }
+    }
+
+    fn update_aspect(&mut self, surface_width: u32, surface_height: u32) {
+        let (_, _, width, height) = self.viewport.to_pixels(surface_width, surface_height);
+        self.camera.update_aspect(width as f32 / height as f32);
+    }
+
+    fn write_uniform(&mut self, queue: &wgpu::Queue) {
+        self.uniform.update_view_proj(&self.camera);
+        queue.write_buffer(&self.buffer, 0, bytemuck::bytes_of(&self.uniform));
+    }
+
+    /// Restricts drawing to the view's rectangle and binds its camera to group 1.
+    pub fn bind<'rpass>(
+        &'rpass self,
+        render_pass: &mut wgpu::RenderPass<'rpass>,
+        surface_width: u32,
+        surface_height: u32,
+    ) {
+        let (x, y, width, height) = self.viewport.to_pixels(surface_width, surface_height);
+
+        render_pass.set_viewport(x as f32, y as f32, width as f32, height as f32, 0.0, 1.0);
+        render_pass.set_scissor_rect(x, y, width, height);
+        render_pass.set_bind_group(1, &self.bind_group, &[]);
+    }
+}
+
+pub struct Views {
+    layout: wgpu::BindGroupLayout,
+    views: Vec<View>,
+    view_layout: ViewLayout,
+    surface_size: (u32, u32),
+}
+
+impl Views {
+    pub fn new(
+        device: &wgpu::Device,
+        camera: Camera,
+        surface_width: u32,
+        surface_height: u32,
+    ) -> Self {
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+            label: Some(&label("Views", "camera bind group layout")),
+        });
+
+        let perspective = View::new(device, &layout, "Perspective", camera, Viewport::FULL);
+
+        let mut views = Views {
+            layout,
+            views: vec![perspective],
+            view_layout: ViewLayout::Single,
+            surface_size: (surface_width, surface_height),
+        };
+        views.resize(surface_width, surface_height);
+
+        views
+    }
+
+    /// Camera bind group layout shared by every pipeline that is drawn through a view.
+    pub fn bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.layout
+    }
+
+    /// The perspective view, driven by the camera controller.
+    pub fn primary(&self) -> &View {
+        &self.views[0]
+    }
+
+    pub fn primary_mut(&mut self) -> &mut View {
+        &mut self.views[0]
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &View> {
+        self.views.iter()
+    }
+
+    /// Ray through a surface pixel from whichever view it falls in.
+    pub fn ray(&self, x: f64, y: f64) -> Option<Ray> {
+        let viewports = self.views.iter().map(|view| view.viewport);
+        let (index, (ndc_x, ndc_y)) = hit(viewports, x, y, self.surface_size)?;
+
+        Some(self.views[index].camera.ray(ndc_x, ndc_y))
+    }
+
+    pub fn view_layout(&self) -> ViewLayout {
+        self.view_layout
+    }
+
+    pub fn set_view_layout(&mut self, device: &wgpu::Device, view_layout: ViewLayout) {
+        self.views.truncate(1);
+
+        match view_layout {
+            ViewLayout::Single => {
+                self.views[0].viewport = Viewport::FULL;
+            }
+            ViewLayout::Quad => {
+                self.views[0].viewport = QUAD_PRIMARY;
+
+                let depth_mode = self.views[0].camera.depth_mode;
+
+                for (name, viewport, direction, up) in QUAD_AXES {
+                    let (direction, up) = (direction.into(), up.into());
+                    let camera = Camera {
+                        projection: Projection::Orthographic { height: 40.0 },
+                        up,
+                        depth_mode,
+                        ..Camera::default()
+                    };
+                    let mut view = View::new(device, &self.layout, name, camera, viewport);
+                    view.axis = Some((direction, up));
+                    self.views.push(view);
+                }
+            }
+        }
+
+        self.view_layout = view_layout;
+        self.resize(self.surface_size.0, self.surface_size.1);
+    }
+
+    pub fn resize(&mut self, surface_width: u32, surface_height: u32) {
+        self.surface_size = (surface_width, surface_height);
+
+        for view in &mut self.views {
+            view.update_aspect(surface_width, surface_height);
+        }
+    }
+
+    /// Keeps the axis-aligned views looking at the primary camera's target and uploads
+    /// every camera uniform.
+    pub fn update(&mut self, queue: &wgpu::Queue) {
+        let target = self.views[0].camera.target;
+        let distance = self.views[0].camera.zfar * 0.5;
+
+        for view in &mut self.views {
+            if let Some((direction, up)) = view.axis {
+                view.camera.target = target;
+                view.camera.eye = target + direction * distance;
+                view.camera.up = up;
+            }
+        }
+
+        for view in &mut self.views {
+            view.write_uniform(queue);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quad() -> Vec<Viewport> {
+        std::iter::once(QUAD_PRIMARY)
+            .chain(QUAD_AXES.map(|(_, viewport, _, _)| viewport))
+            .collect()
+    }
+
+    #[test]
+    fn full_viewport_covers_the_surface() {
+        assert_eq!(Viewport::FULL.to_pixels(800, 600), (0, 0, 800, 600));
+        assert_eq!(
+            Viewport::quadrant(1, 0).to_pixels(800, 600),
+            (400, 0, 400, 300)
+        );
+    }
+
+    #[test]
+    fn viewports_stay_inside_the_surface() {
+        let edge = Viewport {
+            x: 1.0,
+            y: 1.0,
+            width: 0.5,
+            height: 0.5,
+        };
+        assert_eq!(edge.to_pixels(800, 600), (799, 599, 1, 1));
+
+        // minimized
+        assert_eq!(Viewport::FULL.to_pixels(0, 0), (0, 0, 1, 1));
+        assert_eq!(Viewport::quadrant(1, 1).to_pixels(0, 0), (0, 0, 1, 1));
+    }
+
+    #[test]
+    fn quad_layout_tiles_the_surface() {
+        for (surface_width, surface_height) in [(800, 600), (801, 601), (3, 5)] {
+            let mut covered = vec![0; (surface_width * surface_height) as usize];
+            for viewport in quad() {
+                let (x, y, width, height) = viewport.to_pixels(surface_width, surface_height);
+                for row in y..y + height {
+                    for column in x..x + width {
+                        covered[(row * surface_width + column) as usize] += 1;
+                    }
+                }
+            }
+
+            assert!(covered.iter().all(|&count| count == 1));
+        }
+    }
+
+    #[test]
+    fn pixels_hit_the_view_they_fall_in() {
+        let surface = (800, 600);
+
+        // the perspective view is the bottom right quadrant, the axis views follow in order
+        assert_eq!(hit(quad(), 600.0, 450.0, surface), Some((0, (0.0, 0.0))));
+        assert_eq!(hit(quad(), 0.0, 0.0, surface), Some((1, (-1.0, 1.0))));
+        assert_eq!(
+            hit(quad(), 799.5, 0.0, surface).map(|(index, _)| index),
+            Some(2)
+        );
+        assert_eq!(
+            hit(quad(), 200.0, 599.0, surface).map(|(index, _)| index),
+            Some(3)
+        );
+
+        assert_eq!(hit(quad(), 800.0, 300.0, surface), None);
+        assert_eq!(hit(quad(), -1.0, 300.0, surface), None);
+    }
+}
@@ -1,4 +1,20 @@
+use wgpu::{BindGroupLayout, ShaderModule, TextureFormat};
+
+use crate::core::{model::MaterialLayout, texture::DepthMode};
+
 pub mod char;
 pub mod cube;
 pub mod pentagon;
+pub mod scene;
 pub mod text;
+
+/// What the components' render pipelines are built from, the same for all of them.
+#[derive(Clone, Copy)]
+pub struct PipelineInputs<'a> {
+    pub shader: &'a ShaderModule,
+    pub swapchain_format: TextureFormat,
+    pub material_layout: &'a MaterialLayout,
+    pub camera_bind_group_layout: &'a BindGroupLayout,
+    pub light_bind_group_layout: &'a BindGroupLayout,
+    pub depth_mode: DepthMode,
+}
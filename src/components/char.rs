@@ -1,86 +1,85 @@
-use wgpu::util::DeviceExt;
-
 use crate::core::{
-    model::ModelVertex,
-    model::{Material, Mesh, Model, Vertex},
-    texture::Texture,
+    debug::label,
+    error::{ErrorScope, Operation},
+    model::{Mesh, ModelVertex, Vertex, NO_MATERIAL},
+    primitives,
+    texture::{ColorSpace, SamplerOptions, Texture},
 };
 
 use super::pentagon::Renderable;
 
+const NAME: &str = "Char";
+
+/// A glyph quad drawn over the scene by `texture.wgsl`, which only takes a texture and a
+/// sampler, so it keeps its own texture instead of a model material.
 pub struct Char {
     pub render_pipeline: wgpu::RenderPipeline,
     pub diffuse_bind_group: wgpu::BindGroup,
-    pub model: Model,
+    pub glyph: Texture,
+    pub mesh: Mesh,
 }
 
 impl Char {
-    pub fn new(
+    pub async fn new(
         device: &wgpu::Device,
         queue: &wgpu::Queue,
         shader: &wgpu::ShaderModule,
         pipeline_layout: &wgpu::PipelineLayout,
         texture_bind_group_layout: &wgpu::BindGroupLayout,
         config: &wgpu::SurfaceConfiguration,
-    ) -> Self {
-        let model = Char::prepare_model(device, queue);
+    ) -> anyhow::Result<Self> {
+        let scope = ErrorScope::push(device, NAME, None, Operation::LoadModel);
+        let glyph = Char::prepare_glyph(device, queue)?;
+        // no model around it, the glyph texture is bound for it directly
+        let mesh = primitives::quad(1.8, 1.8).into_mesh(device, "char", NO_MATERIAL);
+        scope.pop().await?;
+
         let (render_pipeline, diffuse_bind_group) = Char::prepare_pipeline_and_bind(
             device,
             shader,
-            &model.materials[0].diffuse_texture.view,
-            &model.materials[0].diffuse_texture.sampler,
+            &glyph.view,
+            &glyph.sampler,
             pipeline_layout,
             texture_bind_group_layout,
             config,
-        );
+        )
+        .await?;
 
-        Char {
-            model,
+        Ok(Char {
             render_pipeline,
             diffuse_bind_group,
-        }
+            glyph,
+            mesh,
+        })
     }
 }
 
 impl Renderable for Char {
-    fn prepare(&mut self, queue: &wgpu::Queue, camera: Option<&crate::Camera>, elapsed_time: f32) {}
+    fn prepare(
+        &mut self,
+        queue: &wgpu::Queue,
+        camera: Option<&crate::camera::Camera>,
+        elapsed_time: f32,
+    ) {
+    }
 
     fn render<'rpass>(&'rpass self, render_pass: &mut wgpu::RenderPass<'rpass>) {
+        render_pass.push_debug_group(NAME);
         render_pass.set_pipeline(&self.render_pipeline);
         render_pass.set_bind_group(0, &self.diffuse_bind_group, &[]);
 
-        for mesh in &self.model.meshes {
-            render_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
-            render_pass.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
-            render_pass.draw_indexed(0..mesh.num_elements, 0, 0..1);
-        }
+        render_pass.insert_debug_marker(&self.mesh.name);
+        render_pass.set_vertex_buffer(0, self.mesh.vertex_buffer.slice(..));
+        render_pass.set_index_buffer(self.mesh.index_buffer.slice(..), self.mesh.index_format);
+        render_pass.draw_indexed(0..self.mesh.num_elements, 0, 0..1);
+        render_pass.pop_debug_group();
     }
 }
 
 impl Char {
-    fn prepare_model(device: &wgpu::Device, queue: &wgpu::Queue) -> Model {
+    fn prepare_glyph(device: &wgpu::Device, queue: &wgpu::Queue) -> anyhow::Result<Texture> {
         let data = [128, 128, 128, 255].to_vec();
 
-        let vertices = [
-            ModelVertex {
-                pos: [-0.9, 0.9, 0.0],
-                tex_coords: [0.0, 0.0],
-            }, // A
-            ModelVertex {
-                pos: [-0.9, -0.9, 0.0],
-                tex_coords: [0.0, 1.0],
-            }, // B
-            ModelVertex {
-                pos: [0.9, -0.9, 0.0],
-                tex_coords: [1.0, 1.0],
-            }, // C
-            ModelVertex {
-                pos: [0.9, 0.9, 0.0],
-                tex_coords: [1.0, 0.0],
-            }, // D
-        ]
-        .to_vec();
-
         let mut buffer = image::RgbaImage::new(5, 5);
 
         for x in 0..buffer.width() {
@@ -115,94 +114,26 @@ impl Char {
 
         let text_data = include_bytes!("../happy-tree.png").to_vec();
 
-        let diffuse_texture = Texture::from_bytes(
+        let glyph = Texture::from_bytes(
             device,
             queue,
             text_data,
-            "char",
+            &label(NAME, "glyph texture"),
+            ColorSpace::Srgb,
             Some(buffer),
-            Texture::create_sampler(device, Some(wgpu::FilterMode::Nearest)),
-        )
-        .unwrap();
-
-        let texture_bind_group_layout =
-            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                label: Some("texture_bind_group_layout"),
-                entries: &[
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Texture {
-                            multisampled: false,
-                            view_dimension: wgpu::TextureViewDimension::D2,
-                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                        },
-                        count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 1,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        // This should match the filterable field of the
-                        // corresponding Texture entry above.
-                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-                        count: None,
-                    },
-                ],
-            });
-
-        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &texture_bind_group_layout,
-            label: None,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&diffuse_texture.view),
+            Texture::create_sampler(
+                device,
+                SamplerOptions {
+                    mag_filter: wgpu::FilterMode::Nearest,
+                    ..Default::default()
                 },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&diffuse_texture.sampler),
-                },
-            ],
-        });
-
-        let mut materials = Vec::with_capacity(1);
-
-        materials.push(Material {
-            name: String::from("Char"),
-            diffuse_texture,
-            bind_group,
-        });
-
-        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Char vertex buffer"),
-            contents: bytemuck::cast_slice(&vertices),
-            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
-        });
-
-        // let indices = [0, 1, 4, 1, 2, 4, 2, 3, 4, /* padding */ 0];
-
-        let indices = [0, 1, 3, 1, 2, 3];
-
-        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Char index buffer"),
-            contents: bytemuck::cast_slice(&indices),
-            usage: wgpu::BufferUsages::INDEX | wgpu::BufferUsages::COPY_DST,
-        });
-
-        let mut meshes = Vec::with_capacity(1);
-
-        meshes.push(Mesh {
-            name: String::from("char"),
-            vertex_buffer,
-            index_buffer,
-            num_elements: indices.len() as u32,
-            materials: materials.len() as usize,
-        });
+            ),
+        )?;
 
-        Model { meshes, materials }
+        Ok(glyph)
     }
 
-    fn prepare_pipeline_and_bind(
+    async fn prepare_pipeline_and_bind(
         device: &wgpu::Device,
         shader: &wgpu::ShaderModule,
         texture_view: &wgpu::TextureView,
@@ -210,11 +141,12 @@ impl Char {
         pipeline_layout: &wgpu::PipelineLayout,
         texture_bind_group_layout: &wgpu::BindGroupLayout,
         config: &wgpu::SurfaceConfiguration,
-    ) -> (wgpu::RenderPipeline, wgpu::BindGroup) {
+    ) -> anyhow::Result<(wgpu::RenderPipeline, wgpu::BindGroup)> {
         let vertex_buffers = [ModelVertex::desc()];
 
+        let scope = ErrorScope::push(device, NAME, None, Operation::CreateRenderPipeline);
         let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: None,
+            label: Some(&label(NAME, "render pipeline")),
             layout: Some(&pipeline_layout),
             vertex: wgpu::VertexState {
                 module: &shader,
@@ -247,7 +179,9 @@ impl Char {
             multisample: wgpu::MultisampleState::default(),
             multiview: None,
         });
+        scope.pop().await?;
 
+        let scope = ErrorScope::push(device, NAME, None, Operation::CreateBindGroup);
         let diffuse_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
             layout: &texture_bind_group_layout,
             entries: &[
@@ -260,9 +194,10 @@ impl Char {
                     resource: wgpu::BindingResource::Sampler(texture_sampler),
                 },
             ],
-            label: Some("diffuse_bind_group"),
+            label: Some(&label(NAME, "diffuse bind group")),
         });
+        scope.pop().await?;
 
-        return (render_pipeline, diffuse_bind_group);
+        Ok((render_pipeline, diffuse_bind_group))
     }
 }
@@ -1,150 +1,69 @@
-use std::{mem, slice};
-
-use wgpu::{
-    util::DeviceExt, BindGroup, BindGroupLayout, Buffer, ColorTargetState, Queue, RenderPipeline,
-    ShaderModule, TextureFormat,
-};
+use wgpu::{util::DeviceExt, BindGroup, Buffer, ColorTargetState, Queue, RenderPipeline};
 
 use crate::{
+    camera::Camera,
     core::{
-        model::{Model, ModelVertex, Vertex},
-        texture,
+        bounds::{BoundingSphere, Frustum},
+        debug::label,
+        error::{ErrorScope, Operation},
+        instance::{Instance, InstanceBuffer, InstanceRaw},
+        model::{LodThreshold, Model, ModelVertex, Vertex},
+        picking::{pick_instances, Hit, Pickable, Ray},
     },
-    resources::{load_model, load_texture},
-    Camera, CameraUniform,
+    resources::{load_model, LodSource, ModelOptions},
 };
 
-use super::pentagon::Renderable;
+use super::{pentagon::Renderable, PipelineInputs};
 
-#[repr(C)]
-#[derive(Clone)]
-struct Instance {
-    position: cgmath::Vector3<f32>,
-    rotation: cgmath::Quaternion<f32>,
-}
-
-#[repr(C)]
-struct InstanceRaw {
-    model: [[f32; 4]; 4],
-}
+const NAME: &str = "Cube";
+const MODEL: &str = "cube.obj";
 
-impl Instance {
-    fn to_raw(&self) -> InstanceRaw {
-        InstanceRaw {
-            model: (cgmath::Matrix4::from_translation(self.position)
-                * cgmath::Matrix4::from(self.rotation))
-            .into(),
-        }
+// the far rows of the grid are a few pixels tall, they can do with a lot less
+fn model_options() -> ModelOptions {
+    ModelOptions {
+        lods: vec![
+            (LodSource::Simplify(0.5), LodThreshold::ScreenSize(0.1)),
+            (LodSource::Simplify(0.2), LodThreshold::Distance(30.0)),
+        ],
+        optimize: true,
     }
 }
 
 pub struct Cube {
-    camera_uniform: CameraUniform,
     render_pipeline: RenderPipeline,
-    diffuse_bind_group: BindGroup,
-    camera_buffer: Buffer,
-    camera_bind_group: BindGroup,
+    translucent_pipeline: RenderPipeline,
     elapsed_time_buffer: Buffer,
     elapsed_time_bind_group: BindGroup,
-    instance_buffer: Buffer,
+    instance_buffer: InstanceBuffer,
     instances: Vec<Instance>,
+    // index into `instances`
+    highlighted: Option<usize>,
     model: Model,
 }
 
 impl Cube {
     pub async fn new(
         device: &wgpu::Device,
-        shader: &ShaderModule,
-        swapchain_format: &TextureFormat,
-        camera: &Camera,
+        inputs: &PipelineInputs<'_>,
         queue: &Queue,
-    ) -> Self {
-        let diffuse_texture = load_texture("cube-diffuse.jpg", &device, &queue)
-            .await
-            .unwrap();
-
-        let texture_bind_group_layout =
-            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                entries: &[
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Texture {
-                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                            view_dimension: wgpu::TextureViewDimension::D2,
-                            multisampled: false,
-                        },
-                        count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 1,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        // This should match the filterable field of the
-                        // corresponding Texture entry above.
-                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-                        count: None,
-                    },
-                ],
-                label: Some("texture_bind_group_layout"),
-            });
-
-        let vertex_buffers = [
-            ModelVertex::desc(),
-            wgpu::VertexBufferLayout {
-                // TODO: move out
-                array_stride: mem::size_of::<InstanceRaw>() as wgpu::BufferAddress,
-                step_mode: wgpu::VertexStepMode::Instance,
-                attributes: &[
-                    wgpu::VertexAttribute {
-                        offset: 0,
-                        shader_location: 5,
-                        format: wgpu::VertexFormat::Float32x4,
-                    },
-                    wgpu::VertexAttribute {
-                        offset: mem::size_of::<[f32; 4]>() as wgpu::BufferAddress,
-                        shader_location: 6,
-                        format: wgpu::VertexFormat::Float32x4,
-                    },
-                    wgpu::VertexAttribute {
-                        offset: mem::size_of::<[f32; 8]>() as wgpu::BufferAddress,
-                        shader_location: 7,
-                        format: wgpu::VertexFormat::Float32x4,
-                    },
-                    wgpu::VertexAttribute {
-                        offset: mem::size_of::<[f32; 12]>() as wgpu::BufferAddress,
-                        shader_location: 8,
-                        format: wgpu::VertexFormat::Float32x4,
-                    },
-                ],
-            },
-        ];
-
-        let mut camera_uniform = CameraUniform::new();
-
-        // load model
-        let model = load_model("cube.obj", device, queue, &texture_bind_group_layout)
-            .await
-            .unwrap();
-
-        camera_uniform.update_view_proj(&camera);
+    ) -> anyhow::Result<Self> {
+        let PipelineInputs {
+            shader,
+            swapchain_format,
+            material_layout,
+            camera_bind_group_layout,
+            light_bind_group_layout,
+            depth_mode,
+        } = *inputs;
+        let vertex_buffers = [ModelVertex::desc(), InstanceRaw::desc()];
+
+        // load model, cube.mtl brings the diffuse and normal textures
+        let scope = ErrorScope::push(device, NAME, Some(MODEL), Operation::LoadModel);
+        let model = load_model(MODEL, device, queue, material_layout, &model_options()).await?;
+        scope.pop().await?;
 
         let instances = Cube::create_instances(10);
 
-        let camera_bind_group_layout =
-            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                entries: &[wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::VERTEX,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                }],
-                label: Some("camera_bind_group_layout"),
-            });
-
         let elapsed_time_bind_group_layout =
             device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
                 entries: &[wgpu::BindGroupLayoutEntry {
@@ -157,93 +76,67 @@ impl Cube {
                     },
                     count: None,
                 }],
-                label: Some("elapsed_time_bind_group_layout"),
+                label: Some(&label(NAME, "elapsed time bind group layout")),
             });
 
         let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-            label: None,
+            label: Some(&label(NAME, "pipeline layout")),
             bind_group_layouts: &[
-                &texture_bind_group_layout,
-                &camera_bind_group_layout,
+                &material_layout.layout,
+                camera_bind_group_layout,
                 &elapsed_time_bind_group_layout,
+                light_bind_group_layout,
             ],
             push_constant_ranges: &[],
         });
 
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: None,
-            layout: Some(&pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: "vs_main",
-                buffers: &vertex_buffers,
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &shader,
-                entry_point: "fs_main",
-                targets: &[Some(ColorTargetState::from(*swapchain_format))],
-            }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                cull_mode: Some(wgpu::Face::Back),
-                ..Default::default()
-            },
-            depth_stencil: Some(wgpu::DepthStencilState {
-                format: crate::core::texture::Texture::DEPTH_FORMAT,
-                depth_write_enabled: true,
-                depth_compare: wgpu::CompareFunction::Less,
-                stencil: wgpu::StencilState::default(),
-                bias: wgpu::DepthBiasState::default(),
-            }),
-            multisample: wgpu::MultisampleState::default(),
-            multiview: None,
-        });
-
-        let diffuse_texture_view = diffuse_texture.view;
-        let diffuse_sampler = diffuse_texture.sampler;
-
-        let diffuse_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &texture_bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&diffuse_texture_view),
+        let create_pipeline = |name: &str, blend, depth_write_enabled| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(&label(NAME, name)),
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: shader,
+                    entry_point: "vs_main",
+                    buffers: &vertex_buffers,
                 },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&diffuse_sampler),
+                fragment: Some(wgpu::FragmentState {
+                    module: shader,
+                    entry_point: "fs_main",
+                    targets: &[Some(ColorTargetState {
+                        blend,
+                        ..ColorTargetState::from(swapchain_format)
+                    })],
+                }),
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::TriangleList,
+                    cull_mode: Some(wgpu::Face::Back),
+                    ..Default::default()
                 },
-            ],
-            label: Some("diffuse_bind_group"),
-        });
-
-        // TODO: it can be a serious issue
-        let camera_raw = unsafe {
-            slice::from_raw_parts(
-                camera_uniform.view_proj.as_ptr() as *const u8,
-                mem::size_of::<CameraUniform>(),
-            )
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    depth_write_enabled,
+                    ..depth_mode.depth_stencil_state()
+                }),
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+            })
         };
 
-        let camera_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Camera buffer"),
-            contents: camera_raw,
-            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-        });
-
-        let camera_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &camera_bind_group_layout,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: camera_buffer.as_entire_binding(),
-            }],
-            label: Some("camera_bind_group"),
-        });
+        let scope = ErrorScope::push(device, NAME, None, Operation::CreateRenderPipeline);
+        let render_pipeline = create_pipeline("render pipeline", None, true);
+        // translucent meshes are tested against the depth buffer but leave it alone, so
+        // whatever is drawn after them still shows through
+        let translucent_pipeline = create_pipeline(
+            "translucent render pipeline",
+            Some(wgpu::BlendState::ALPHA_BLENDING),
+            false,
+        );
+        scope.pop().await?;
 
+        let scope = ErrorScope::push(device, NAME, None, Operation::CreateBindGroup);
         let start_time: [u8; 4] = [0, 0, 0, 0];
 
         let elapsed_time_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Elapsed time buffer"),
+            label: Some(&label(NAME, "elapsed time buffer")),
             contents: &start_time,
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
         });
@@ -254,36 +147,23 @@ impl Cube {
                 binding: 0,
                 resource: elapsed_time_buffer.as_entire_binding(),
             }],
-            label: Some("Elapsed time bind group"),
+            label: Some(&label(NAME, "elapsed time bind group")),
         });
+        scope.pop().await?;
 
-        let instance_raw = instances.iter().map(Instance::to_raw).collect::<Vec<_>>();
-
-        let instance_raw = unsafe {
-            slice::from_raw_parts(
-                instance_raw.as_ptr() as *const u8,
-                mem::size_of::<InstanceRaw>() * instance_raw.len(),
-            )
-        };
-
-        let instance_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Insatnce buffer"),
-            contents: instance_raw,
-            usage: wgpu::BufferUsages::VERTEX,
-        });
+        let instance_buffer =
+            InstanceBuffer::new(device, &label(NAME, "instance buffer"), &instances);
 
-        Cube {
-            camera_uniform,
+        Ok(Cube {
             render_pipeline,
-            diffuse_bind_group,
-            camera_bind_group,
-            camera_buffer,
+            translucent_pipeline,
             elapsed_time_buffer,
             elapsed_time_bind_group,
             instances,
             instance_buffer,
+            highlighted: None,
             model,
-        }
+        })
     }
 
     fn create_instances(num_row_instances: usize) -> Vec<Instance> {
@@ -325,40 +205,88 @@ impl Cube {
 }
 
 impl Renderable for Cube {
-    fn prepare(&mut self, queue: &wgpu::Queue, camera: Option<&crate::Camera>, elapsed_time: f32) {
-        match camera {
+    fn prepare(&mut self, queue: &wgpu::Queue, camera: Option<&Camera>, elapsed_time: f32) {
+        use cgmath::MetricSpace;
+
+        let frustum =
+            camera.map(|camera| Frustum::from_matrix(&camera.build_view_projection_matrix()));
+        // without a camera to measure against everything is drawn at full detail
+        let model = &self.model;
+        let lod = |instance: &Instance| match camera {
             Some(camera) => {
-                let _ = &self.camera_uniform.update_view_proj(camera);
-
-                let camera_raw = unsafe {
-                    slice::from_raw_parts(
-                        self.camera_uniform.view_proj.as_ptr() as *const u8,
-                        mem::size_of::<CameraUniform>(),
-                    )
-                };
-
-                queue.write_buffer(&self.camera_buffer, 0, camera_raw);
+                let sphere = model.sphere.transform(&instance.model_matrix());
+                model.lod_level(
+                    camera.eye.distance(sphere.center),
+                    camera.screen_size(&sphere),
+                )
             }
-            _ => {}
-        }
+            None => 0,
+        };
+        self.instance_buffer.update(
+            queue,
+            &self.instances,
+            &self.model.bounds,
+            frustum.as_ref(),
+            self.highlighted,
+            lod,
+        );
 
         // TODO: elapsed writing
         queue.write_buffer(&self.elapsed_time_buffer, 0, &elapsed_time.to_ne_bytes());
     }
 
     fn render<'rpass>(&'rpass self, render_pass: &mut wgpu::RenderPass<'rpass>) {
-        render_pass.set_pipeline(&self.render_pipeline);
-        render_pass.set_bind_group(0, &self.diffuse_bind_group, &[]);
+        render_pass.push_debug_group(NAME);
+        render_pass.set_bind_group(2, &self.elapsed_time_bind_group, &[]);
+        render_pass.set_vertex_buffer(1, self.instance_buffer.slice());
+
+        // translucent meshes after the opaque ones so there's something to blend over,
+        // unsorted among themselves
+        for (translucent, pipeline) in [
+            (false, &self.render_pipeline),
+            (true, &self.translucent_pipeline),
+        ] {
+            render_pass.set_pipeline(pipeline);
+
+            // one instanced draw per mesh and level
+            for (level, instances) in self.instance_buffer.levels().iter().enumerate() {
+                if instances.is_empty() {
+                    continue;
+                }
+
+                for mesh in self.model.lod_meshes(level) {
+                    let material = self.model.material(mesh);
+                    if material.translucent != translucent {
+                        continue;
+                    }
+
+                    render_pass.insert_debug_marker(&mesh.name);
+                    render_pass.set_bind_group(0, &material.bind_group, &[]);
+                    render_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+                    render_pass.set_index_buffer(mesh.index_buffer.slice(..), mesh.index_format);
+                    render_pass.draw_indexed(0..mesh.num_elements, 0, instances.clone());
+                }
+            }
+        }
+        render_pass.pop_debug_group();
+    }
+}
 
-        render_pass.set_bind_group(1, &self.camera_bind_group, &[]);
+impl Pickable for Cube {
+    fn name(&self) -> &'static str {
+        NAME
+    }
 
-        render_pass.set_bind_group(2, &self.elapsed_time_bind_group, &[]);
+    fn pick(&self, ray: &Ray) -> Option<Hit> {
+        pick_instances(ray, &self.instances, &self.model.bounds, &self.model.meshes)
+    }
 
-        render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
-        for mesh in &self.model.meshes {
-            render_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
-            render_pass.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
-            render_pass.draw_indexed(0..mesh.num_elements, 0, 0..self.instances.len() as _);
-        }
+    fn set_highlight(&mut self, instance: Option<usize>) {
+        self.highlighted = instance;
+    }
+
+    fn bounding_sphere(&self, instance: usize) -> Option<BoundingSphere> {
+        let instance = self.instances.get(instance)?;
+        Some(self.model.sphere.transform(&instance.model_matrix()))
     }
 }
@@ -0,0 +1,508 @@
+use std::{mem, num::NonZeroU64};
+
+use cgmath::{Matrix4, SquareMatrix};
+use wgpu::{util::DeviceExt, BindGroup, Buffer, ColorTargetState, Queue, RenderPipeline};
+
+use crate::{
+    camera::Camera,
+    core::{
+        animation::{Animator, MAX_JOINTS},
+        debug::label,
+        error::{ErrorScope, Operation},
+        instance::InstanceRaw,
+        model::{ModelVertex, SkinnedVertex, Vertex},
+    },
+    resources::load_gltf,
+    scene::Scene,
+};
+
+use super::{pentagon::Renderable, PipelineInputs};
+
+const NAME: &str = "Scene";
+
+// room for a full skin's joint matrices per skinned mesh, a multiple of the 256 byte uniform
+// offset alignment
+const JOINTS_STRIDE: wgpu::BufferAddress =
+    (MAX_JOINTS * mem::size_of::<[[f32; 4]; 4]>()) as wgpu::BufferAddress;
+
+// seconds to cross-fade between clips
+const CLIP_FADE: f32 = 0.3;
+
+// a mesh with the world matrices of every node that uses it
+struct MeshInstances {
+    buffer: Buffer,
+    count: u32,
+    // the last of them, whose transforms mirror the mesh
+    mirrored: u32,
+}
+
+// which faces a draw culls, indexing the pipelines
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Culling {
+    Back,
+    Disabled,
+    // back faces of an instance mirrored by its transform, whose triangles wind clockwise
+    Flipped,
+    // a mirrored double-sided instance, clockwise so its front faces still read as front
+    FlippedDisabled,
+}
+
+impl Culling {
+    const ALL: [Culling; 4] = [
+        Culling::Back,
+        Culling::Disabled,
+        Culling::Flipped,
+        Culling::FlippedDisabled,
+    ];
+
+    fn new(double_sided: bool, mirrored: bool) -> Self {
+        match (double_sided, mirrored) {
+            (false, false) => Culling::Back,
+            (true, false) => Culling::Disabled,
+            (false, true) => Culling::Flipped,
+            (true, true) => Culling::FlippedDisabled,
+        }
+    }
+
+    fn primitive(self) -> wgpu::PrimitiveState {
+        wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            front_face: match self {
+                Culling::Flipped | Culling::FlippedDisabled => wgpu::FrontFace::Cw,
+                Culling::Back | Culling::Disabled => wgpu::FrontFace::Ccw,
+            },
+            cull_mode: match self {
+                Culling::Disabled | Culling::FlippedDisabled => None,
+                Culling::Back | Culling::Flipped => Some(wgpu::Face::Back),
+            },
+            ..Default::default()
+        }
+    }
+}
+
+// instance data with the mirrored instances last, and how many of them there are
+fn mirrored_last(mut transforms: Vec<Matrix4<f32>>) -> (Vec<InstanceRaw>, u32) {
+    // stable, the rest keep their node order
+    transforms.sort_by_key(|transform| transform.determinant() < 0.0);
+    let mirrored = transforms
+        .iter()
+        .filter(|transform| transform.determinant() < 0.0)
+        .count();
+
+    let raw = transforms
+        .into_iter()
+        .map(InstanceRaw::from_matrix)
+        .collect();
+    (raw, mirrored as u32)
+}
+
+// whether the joints all mirror what they place, None when only some of them do
+fn mirrored_joints(joints: &[Matrix4<f32>]) -> Option<bool> {
+    let mirrored = joints
+        .iter()
+        .filter(|joint| joint.determinant() < 0.0)
+        .count();
+
+    match mirrored {
+        0 => Some(false),
+        count if count == joints.len() => Some(true),
+        _ => None,
+    }
+}
+
+/// A glTF scene, each mesh instanced once per node referencing it and skinned meshes drawn
+/// once with their joints. The first animation plays on a loop.
+pub struct SceneModel {
+    // by `Culling`
+    render_pipelines: Vec<RenderPipeline>,
+    // by `Culling`, empty when nothing is skinned
+    skinned_pipelines: Vec<RenderPipeline>,
+    elapsed_time_buffer: Buffer,
+    // joint matrices of every skinned mesh, `JOINTS_STRIDE` apart
+    joints_buffer: Buffer,
+    elapsed_time_bind_group: BindGroup,
+    // by mesh index
+    instances: Vec<MeshInstances>,
+    // (mesh, skin) in joint buffer order
+    skinned_meshes: Vec<(usize, usize)>,
+    // by skinned mesh, whether its joints mirror it as of the last `write_joints`
+    skinned_mirrored: Vec<Option<bool>>,
+    animator: Animator,
+    elapsed_time: f32,
+    pub scene: Scene,
+}
+
+impl SceneModel {
+    pub async fn new(
+        file_name: &str,
+        device: &wgpu::Device,
+        inputs: &PipelineInputs<'_>,
+        queue: &Queue,
+    ) -> anyhow::Result<Self> {
+        let PipelineInputs {
+            shader,
+            swapchain_format,
+            material_layout,
+            camera_bind_group_layout,
+            light_bind_group_layout,
+            depth_mode,
+        } = *inputs;
+        let scope = ErrorScope::push(device, NAME, Some(file_name), Operation::LoadModel);
+        let scene = load_gltf(file_name, device, queue, material_layout).await?;
+        scope.pop().await?;
+
+        let skinned_meshes = scene.skinned_meshes();
+
+        // the joints share the elapsed time's group, offset to each skinned mesh's matrices
+        let elapsed_time_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::VERTEX,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::VERTEX,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: true,
+                            min_binding_size: NonZeroU64::new(JOINTS_STRIDE),
+                        },
+                        count: None,
+                    },
+                ],
+                label: Some(&label(NAME, "elapsed time bind group layout")),
+            });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some(&label(NAME, "pipeline layout")),
+            bind_group_layouts: &[
+                &material_layout.layout,
+                camera_bind_group_layout,
+                &elapsed_time_bind_group_layout,
+                light_bind_group_layout,
+            ],
+            push_constant_ranges: &[],
+        });
+
+        let create_pipeline =
+            |name: &str, entry_point, buffers: &[wgpu::VertexBufferLayout], culling: Culling| {
+                device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                    label: Some(&label(NAME, &format!("{} ({:?})", name, culling))),
+                    layout: Some(&pipeline_layout),
+                    vertex: wgpu::VertexState {
+                        module: shader,
+                        entry_point,
+                        buffers,
+                    },
+                    fragment: Some(wgpu::FragmentState {
+                        module: shader,
+                        entry_point: "fs_main",
+                        targets: &[Some(ColorTargetState::from(swapchain_format))],
+                    }),
+                    primitive: culling.primitive(),
+                    depth_stencil: Some(depth_mode.depth_stencil_state()),
+                    multisample: wgpu::MultisampleState::default(),
+                    multiview: None,
+                })
+            };
+
+        let scope = ErrorScope::push(device, NAME, None, Operation::CreateRenderPipeline);
+        let render_pipelines = Culling::ALL
+            .into_iter()
+            .map(|culling| {
+                create_pipeline(
+                    "render pipeline",
+                    "vs_main",
+                    &[ModelVertex::desc(), InstanceRaw::desc()],
+                    culling,
+                )
+            })
+            .collect();
+        let skinned_pipelines = if skinned_meshes.is_empty() {
+            Vec::new()
+        } else {
+            Culling::ALL
+                .into_iter()
+                .map(|culling| {
+                    create_pipeline(
+                        "skinned render pipeline",
+                        "vs_skinned",
+                        &[ModelVertex::desc(), SkinnedVertex::desc()],
+                        culling,
+                    )
+                })
+                .collect()
+        };
+        scope.pop().await?;
+
+        let scope = ErrorScope::push(device, NAME, None, Operation::CreateBindGroup);
+        let start_time: [u8; 4] = [0, 0, 0, 0];
+
+        let elapsed_time_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&label(NAME, "elapsed time buffer")),
+            contents: &start_time,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        // bound even when nothing is skinned, the layout asks for it
+        let joints_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(&label(NAME, "joints buffer")),
+            size: JOINTS_STRIDE * skinned_meshes.len().max(1) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let elapsed_time_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &elapsed_time_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: elapsed_time_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                        buffer: &joints_buffer,
+                        offset: 0,
+                        size: NonZeroU64::new(JOINTS_STRIDE),
+                    }),
+                },
+            ],
+            label: Some(&label(NAME, "elapsed time bind group")),
+        });
+        scope.pop().await?;
+
+        // rewritten every frame when the scene is animated, the set of instances never changes
+        let world = scene.world_transforms();
+        let instances = scene
+            .mesh_transforms(&world)
+            .into_iter()
+            .zip(&scene.model.meshes)
+            .map(|(transforms, mesh)| {
+                let (raw, mirrored) = mirrored_last(transforms);
+
+                MeshInstances {
+                    buffer: device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                        label: Some(&label(&mesh.name, "instance buffer")),
+                        contents: bytemuck::cast_slice(&raw),
+                        usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+                    }),
+                    count: raw.len() as u32,
+                    mirrored,
+                }
+            })
+            .collect();
+
+        let mut animator = Animator::new();
+        if let Some(clip) = scene.animations.first() {
+            log::info!("{}: playing {}", NAME, clip.name);
+            animator.play(0, 0.0);
+        }
+
+        let mut scene_model = SceneModel {
+            render_pipelines,
+            skinned_pipelines,
+            elapsed_time_buffer,
+            joints_buffer,
+            elapsed_time_bind_group,
+            instances,
+            skinned_mirrored: vec![Some(false); skinned_meshes.len()],
+            skinned_meshes,
+            animator,
+            elapsed_time: 0.0,
+            scene,
+        };
+        scene_model.write_joints(queue, &world);
+
+        Ok(scene_model)
+    }
+
+    /// Cross-fades to the scene's next animation, wrapping around after the last.
+    pub fn next_animation(&mut self) {
+        let count = self.scene.animations.len();
+        if count == 0 {
+            return;
+        }
+
+        let clip = self
+            .animator
+            .current()
+            .map_or(0, |current| (current + 1) % count);
+        log::info!("{}: playing {}", NAME, self.scene.animations[clip].name);
+        self.animator.play(clip, CLIP_FADE);
+    }
+
+    fn write_joints(&mut self, queue: &Queue, world: &[Matrix4<f32>]) {
+        for (index, &(mesh, skin)) in self.skinned_meshes.iter().enumerate() {
+            let joints = self.scene.skins[skin].joint_matrices(world);
+
+            // a negative scale on a joint or above it turns the mesh inside out
+            let mirrored = mirrored_joints(&joints);
+            if mirrored.is_none() && self.skinned_mirrored[index].is_some() {
+                log::warn!(
+                    "{}: some joints mirror the mesh and some don't, culling it as unmirrored",
+                    self.scene.model.meshes[mesh].name
+                );
+            }
+            self.skinned_mirrored[index] = mirrored;
+
+            let matrices = joints
+                .into_iter()
+                .map(Into::into)
+                .collect::<Vec<[[f32; 4]; 4]>>();
+            queue.write_buffer(
+                &self.joints_buffer,
+                index as wgpu::BufferAddress * JOINTS_STRIDE,
+                bytemuck::cast_slice(&matrices),
+            );
+        }
+    }
+}
+
+impl Renderable for SceneModel {
+    fn prepare(&mut self, queue: &wgpu::Queue, _camera: Option<&Camera>, elapsed_time: f32) {
+        queue.write_buffer(&self.elapsed_time_buffer, 0, &elapsed_time.to_ne_bytes());
+
+        let dt = (elapsed_time - self.elapsed_time).max(0.0);
+        self.elapsed_time = elapsed_time;
+        if self.scene.animations.is_empty() {
+            return;
+        }
+
+        self.animator.update(dt, &self.scene.animations);
+        let pose = self
+            .animator
+            .pose(&self.scene.animations, &self.scene.rest_pose());
+        let world = self.scene.posed_world_transforms(&pose);
+
+        for (transforms, instances) in self
+            .scene
+            .mesh_transforms(&world)
+            .into_iter()
+            .zip(&mut self.instances)
+        {
+            // an animated scale can turn an instance inside out
+            let (raw, mirrored) = mirrored_last(transforms);
+            queue.write_buffer(&instances.buffer, 0, bytemuck::cast_slice(&raw));
+            instances.mirrored = mirrored;
+        }
+        self.write_joints(queue, &world);
+    }
+
+    fn render<'rpass>(&'rpass self, render_pass: &mut wgpu::RenderPass<'rpass>) {
+        render_pass.push_debug_group(NAME);
+        render_pass.set_bind_group(2, &self.elapsed_time_bind_group, &[0]);
+
+        for (mesh, instances) in self.scene.model.meshes.iter().zip(&self.instances) {
+            // meshes no node references
+            if instances.count == 0 {
+                continue;
+            }
+
+            let material = self.scene.model.material(mesh);
+            let unmirrored = instances.count - instances.mirrored;
+
+            render_pass.insert_debug_marker(&mesh.name);
+            render_pass.set_bind_group(0, &material.bind_group, &[]);
+            render_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+            render_pass.set_vertex_buffer(1, instances.buffer.slice(..));
+            render_pass.set_index_buffer(mesh.index_buffer.slice(..), mesh.index_format);
+            for (range, mirrored) in [(0..unmirrored, false), (unmirrored..instances.count, true)] {
+                if range.is_empty() {
+                    continue;
+                }
+
+                let culling = Culling::new(material.double_sided, mirrored);
+                render_pass.set_pipeline(&self.render_pipelines[culling as usize]);
+                render_pass.draw_indexed(0..mesh.num_elements, 0, range);
+            }
+        }
+
+        for (index, &(mesh, _)) in self.skinned_meshes.iter().enumerate() {
+            let mesh = &self.scene.model.meshes[mesh];
+            let Some(skin_buffer) = &mesh.skin_buffer else {
+                continue;
+            };
+
+            let material = self.scene.model.material(mesh);
+            let mirrored = self.skinned_mirrored[index] == Some(true);
+            let culling = Culling::new(material.double_sided, mirrored);
+
+            render_pass.insert_debug_marker(&mesh.name);
+            render_pass.set_pipeline(&self.skinned_pipelines[culling as usize]);
+            render_pass.set_bind_group(
+                2,
+                &self.elapsed_time_bind_group,
+                &[index as u32 * JOINTS_STRIDE as u32],
+            );
+            render_pass.set_bind_group(0, &material.bind_group, &[]);
+            render_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+            render_pass.set_vertex_buffer(1, skin_buffer.slice(..));
+            render_pass.set_index_buffer(mesh.index_buffer.slice(..), mesh.index_format);
+            render_pass.draw_indexed(0..mesh.num_elements, 0, 0..1);
+        }
+        render_pass.pop_debug_group();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::Vector3;
+
+    use super::*;
+
+    #[test]
+    fn double_sided_materials_cull_nothing() {
+        assert_eq!(Culling::new(false, false), Culling::Back);
+        assert_eq!(Culling::new(false, true), Culling::Flipped);
+        assert_eq!(Culling::new(true, false), Culling::Disabled);
+        assert_eq!(Culling::new(true, true), Culling::FlippedDisabled);
+
+        assert_eq!(Culling::Disabled.primitive().cull_mode, None);
+        assert_eq!(Culling::Flipped.primitive().front_face, wgpu::FrontFace::Cw);
+        // mirrored ones keep their front faces lit from the front
+        let flipped_disabled = Culling::FlippedDisabled.primitive();
+        assert_eq!(flipped_disabled.cull_mode, None);
+        assert_eq!(flipped_disabled.front_face, wgpu::FrontFace::Cw);
+    }
+
+    #[test]
+    fn mirrored_joints_need_every_joint_mirrored() {
+        let mirror = Matrix4::from_nonuniform_scale(1.0, -1.0, 1.0);
+        let identity = Matrix4::identity();
+
+        assert_eq!(mirrored_joints(&[identity, identity]), Some(false));
+        assert_eq!(mirrored_joints(&[mirror, mirror]), Some(true));
+        assert_eq!(mirrored_joints(&[mirror, identity]), None);
+    }
+
+    #[test]
+    fn mirrored_instances_go_last() {
+        let at = |x: f32| Matrix4::from_translation(Vector3::new(x, 0.0, 0.0));
+        let mirror = Matrix4::from_nonuniform_scale(-1.0, 1.0, 1.0);
+        // mirrored twice is a rotation, not a mirror
+        let transforms = vec![
+            at(0.0) * mirror,
+            at(1.0),
+            at(2.0) * mirror,
+            at(3.0) * mirror * mirror,
+        ];
+
+        let (raw, mirrored) = mirrored_last(transforms.clone());
+
+        assert_eq!(mirrored, 2);
+        let order = [1, 3, 0, 2].map(|index| InstanceRaw::from_matrix(transforms[index]));
+        assert_eq!(
+            bytemuck::cast_slice::<_, u8>(&raw),
+            bytemuck::cast_slice::<_, u8>(&order)
+        );
+    }
+}
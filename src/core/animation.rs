@@ -0,0 +1,476 @@
+use cgmath::{InnerSpace, Matrix4, One, Quaternion, Vector3, Vector4, VectorSpace};
+
+/// Joints per skin the skinning shader has room for, `MAX_JOINTS` in shader.wgsl.
+pub const MAX_JOINTS: usize = 128;
+
+/// Translation, rotation and scale of a node relative to its parent, the parts animations
+/// drive separately.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NodeTransform {
+    pub translation: Vector3<f32>,
+    pub rotation: Quaternion<f32>,
+    pub scale: Vector3<f32>,
+}
+
+impl NodeTransform {
+    pub fn matrix(&self) -> Matrix4<f32> {
+        Matrix4::from_translation(self.translation)
+            * Matrix4::from(self.rotation)
+            * Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, self.scale.z)
+    }
+
+    /// Blends towards `other`, rotating along the shorter arc.
+    pub fn lerp(&self, other: &NodeTransform, amount: f32) -> NodeTransform {
+        NodeTransform {
+            translation: self.translation.lerp(other.translation, amount),
+            rotation: nlerp(self.rotation, other.rotation, amount),
+            scale: self.scale.lerp(other.scale, amount),
+        }
+    }
+}
+
+impl Default for NodeTransform {
+    fn default() -> Self {
+        NodeTransform {
+            translation: Vector3::new(0.0, 0.0, 0.0),
+            rotation: Quaternion::one(),
+            scale: Vector3::new(1.0, 1.0, 1.0),
+        }
+    }
+}
+
+// close enough to slerp between keyframes and blend weights, and never unstable
+fn nlerp(from: Quaternion<f32>, to: Quaternion<f32>, amount: f32) -> Quaternion<f32> {
+    let to = if from.dot(to) < 0.0 { -to } else { to };
+    (from * (1.0 - amount) + to * amount).normalize()
+}
+
+/// The joints deforming a skinned mesh and their bind pose.
+pub struct Skin {
+    // the node each joint index of the skinned vertices refers to
+    pub joints: Vec<usize>,
+    // per joint, from the mesh's bind pose into the joint's space
+    pub inverse_bind_matrices: Vec<Matrix4<f32>>,
+}
+
+impl Skin {
+    /// The matrices the vertex shader blends, taking bind pose vertices straight to world
+    /// space given the world matrices of every node.
+    pub fn joint_matrices(&self, world: &[Matrix4<f32>]) -> Vec<Matrix4<f32>> {
+        self.joints
+            .iter()
+            .zip(&self.inverse_bind_matrices)
+            .map(|(&joint, inverse_bind)| world[joint] * inverse_bind)
+            .collect()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Property {
+    Translation,
+    Rotation,
+    Scale,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Interpolation {
+    Step,
+    Linear,
+    // Hermite spline, every keyframe has an in and out tangent around its value
+    CubicSpline,
+}
+
+/// The keyframes of one property of one node.
+pub struct Channel {
+    pub node: usize,
+    pub property: Property,
+    pub interpolation: Interpolation,
+    // seconds, increasing
+    pub times: Vec<f32>,
+    // xyz for translations and scales, xyzw quaternions for rotations; cubic splines store
+    // in tangent, value and out tangent for each time
+    pub values: Vec<Vector4<f32>>,
+}
+
+impl Channel {
+    fn value(&self, key: usize) -> Vector4<f32> {
+        match self.interpolation {
+            Interpolation::CubicSpline => self.values[key * 3 + 1],
+            _ => self.values[key],
+        }
+    }
+
+    // before the first and after the last keyframe the value holds
+    fn sample(&self, time: f32) -> Option<Vector4<f32>> {
+        let last = self.times.len().checked_sub(1)?;
+        let next = self.times.partition_point(|&key_time| key_time <= time);
+        if next == 0 {
+            return Some(self.value(0));
+        }
+        if next > last {
+            return Some(self.value(last));
+        }
+
+        let key = next - 1;
+        let span = self.times[next] - self.times[key];
+        let amount = (time - self.times[key]) / span;
+
+        let value = match self.interpolation {
+            Interpolation::Step => self.value(key),
+            Interpolation::Linear if self.property == Property::Rotation => {
+                let quaternion =
+                    |value: Vector4<f32>| Quaternion::new(value.w, value.x, value.y, value.z);
+                let rotation = nlerp(
+                    quaternion(self.value(key)),
+                    quaternion(self.value(next)),
+                    amount,
+                );
+                Vector4::new(rotation.v.x, rotation.v.y, rotation.v.z, rotation.s)
+            }
+            Interpolation::Linear => self.value(key).lerp(self.value(next), amount),
+            Interpolation::CubicSpline => {
+                let (t, t2, t3) = (amount, amount * amount, amount * amount * amount);
+                let out_tangent = self.values[key * 3 + 2] * span;
+                let in_tangent = self.values[next * 3] * span;
+
+                self.value(key) * (2.0 * t3 - 3.0 * t2 + 1.0)
+                    + out_tangent * (t3 - 2.0 * t2 + t)
+                    + self.value(next) * (-2.0 * t3 + 3.0 * t2)
+                    + in_tangent * (t3 - t2)
+            }
+        };
+
+        Some(value)
+    }
+}
+
+/// Channels played together, like a walk cycle.
+pub struct AnimationClip {
+    pub name: String,
+    pub channels: Vec<Channel>,
+    // the last keyframe's time
+    pub duration: f32,
+}
+
+impl AnimationClip {
+    pub fn new(name: String, channels: Vec<Channel>) -> Self {
+        let duration = channels
+            .iter()
+            .filter_map(|channel| channel.times.last().copied())
+            .fold(0.0, f32::max);
+
+        AnimationClip {
+            name,
+            channels,
+            duration,
+        }
+    }
+
+    /// Overwrites what the clip animates in `pose`, node transforms by node index, with the
+    /// values at `time`. Everything else is left as it is.
+    pub fn sample(&self, time: f32, pose: &mut [NodeTransform]) {
+        for channel in &self.channels {
+            let (Some(value), Some(transform)) = (channel.sample(time), pose.get_mut(channel.node))
+            else {
+                continue;
+            };
+
+            match channel.property {
+                Property::Translation => transform.translation = value.truncate(),
+                Property::Rotation => {
+                    transform.rotation =
+                        Quaternion::new(value.w, value.x, value.y, value.z).normalize()
+                }
+                Property::Scale => transform.scale = value.truncate(),
+            }
+        }
+    }
+}
+
+// one clip playing, fading towards `target_weight`
+struct Playback {
+    clip: usize,
+    time: f32,
+    weight: f32,
+    target_weight: f32,
+    // weight change per second
+    fade_speed: f32,
+}
+
+/// Plays clips looping and blends them by weight, so switching clips can cross-fade.
+pub struct Animator {
+    playbacks: Vec<Playback>,
+    pub speed: f32,
+}
+
+impl Animator {
+    pub fn new() -> Self {
+        Animator {
+            playbacks: Vec::new(),
+            speed: 1.0,
+        }
+    }
+
+    /// Starts `clip` from the beginning, fading it in and everything else out over `fade`
+    /// seconds. With 0 it replaces the others right away.
+    pub fn play(&mut self, clip: usize, fade: f32) {
+        if fade <= 0.0 {
+            self.playbacks.clear();
+        }
+
+        let fade_speed = if fade > 0.0 {
+            1.0 / fade
+        } else {
+            f32::INFINITY
+        };
+        for playback in &mut self.playbacks {
+            playback.target_weight = 0.0;
+            playback.fade_speed = fade_speed;
+        }
+
+        self.playbacks.push(Playback {
+            clip,
+            time: 0.0,
+            weight: if fade > 0.0 { 0.0 } else { 1.0 },
+            target_weight: 1.0,
+            fade_speed,
+        });
+    }
+
+    /// The clip played last, the one fading in or fully there.
+    pub fn current(&self) -> Option<usize> {
+        self.playbacks.last().map(|playback| playback.clip)
+    }
+
+    pub fn update(&mut self, dt: f32, clips: &[AnimationClip]) {
+        for playback in &mut self.playbacks {
+            let duration = clips[playback.clip].duration;
+            playback.time += dt * self.speed;
+            if duration > 0.0 {
+                playback.time = playback.time.rem_euclid(duration);
+            }
+
+            let step = playback.fade_speed * dt;
+            playback.weight = if playback.weight < playback.target_weight {
+                (playback.weight + step).min(playback.target_weight)
+            } else {
+                (playback.weight - step).max(playback.target_weight)
+            };
+        }
+
+        self.playbacks
+            .retain(|playback| playback.weight > 0.0 || playback.target_weight > 0.0);
+    }
+
+    /// The blended pose, starting from `rest` for anything no clip animates.
+    pub fn pose(&self, clips: &[AnimationClip], rest: &[NodeTransform]) -> Vec<NodeTransform> {
+        let mut pose = rest.to_vec();
+        let mut total_weight = 0.0;
+
+        // a running weighted average, the first clip with any weight sets the pose outright
+        for playback in &self.playbacks {
+            if playback.weight <= 0.0 {
+                continue;
+            }
+
+            let mut sampled = rest.to_vec();
+            clips[playback.clip].sample(playback.time, &mut sampled);
+
+            total_weight += playback.weight;
+            let amount = playback.weight / total_weight;
+            for (transform, sampled) in pose.iter_mut().zip(&sampled) {
+                *transform = transform.lerp(sampled, amount);
+            }
+        }
+
+        pose
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{Rad, Rotation3, SquareMatrix};
+
+    use super::*;
+
+    fn channel(property: Property, interpolation: Interpolation, values: Vec<[f32; 4]>) -> Channel {
+        Channel {
+            node: 0,
+            property,
+            interpolation,
+            times: vec![1.0, 3.0],
+            values: values.into_iter().map(Vector4::from).collect(),
+        }
+    }
+
+    fn translation(interpolation: Interpolation) -> Channel {
+        channel(
+            Property::Translation,
+            interpolation,
+            vec![[0.0, 0.0, 0.0, 0.0], [4.0, 2.0, 0.0, 0.0]],
+        )
+    }
+
+    fn close(a: Vector4<f32>, b: Vector4<f32>) -> bool {
+        (a - b).magnitude() < 1e-5
+    }
+
+    #[test]
+    fn channels_hold_outside_their_keyframes() {
+        let channel = translation(Interpolation::Linear);
+
+        assert_eq!(channel.sample(0.0), Some(Vector4::new(0.0, 0.0, 0.0, 0.0)));
+        assert_eq!(channel.sample(5.0), Some(Vector4::new(4.0, 2.0, 0.0, 0.0)));
+        assert_eq!(
+            Channel {
+                times: Vec::new(),
+                ..channel
+            }
+            .sample(1.0),
+            None
+        );
+    }
+
+    #[test]
+    fn step_and_linear_sampling() {
+        let step = translation(Interpolation::Step);
+        assert_eq!(step.sample(2.9), Some(Vector4::new(0.0, 0.0, 0.0, 0.0)));
+        assert_eq!(step.sample(3.0), Some(Vector4::new(4.0, 2.0, 0.0, 0.0)));
+
+        let linear = translation(Interpolation::Linear);
+        assert!(close(
+            linear.sample(1.5).unwrap(),
+            Vector4::new(1.0, 0.5, 0.0, 0.0)
+        ));
+    }
+
+    #[test]
+    fn cubic_splines_follow_their_tangents() {
+        // flat tangents ease in and out, halfway is still halfway
+        let flat = channel(
+            Property::Translation,
+            Interpolation::CubicSpline,
+            vec![
+                [0.0; 4],
+                [0.0, 0.0, 0.0, 0.0],
+                [0.0; 4],
+                [0.0; 4],
+                [4.0, 0.0, 0.0, 0.0],
+                [0.0; 4],
+            ],
+        );
+        assert!(close(
+            flat.sample(2.0).unwrap(),
+            Vector4::new(2.0, 0.0, 0.0, 0.0)
+        ));
+        assert!(flat.sample(1.5).unwrap().x < 1.0);
+
+        // tangents matching the slope, per second, reproduce the line
+        let slope = [2.0, 0.0, 0.0, 0.0];
+        let straight = channel(
+            Property::Translation,
+            Interpolation::CubicSpline,
+            vec![slope, [0.0; 4], slope, slope, [4.0, 0.0, 0.0, 0.0], slope],
+        );
+        assert!(close(
+            straight.sample(1.5).unwrap(),
+            Vector4::new(1.0, 0.0, 0.0, 0.0)
+        ));
+    }
+
+    #[test]
+    fn rotations_take_the_shorter_arc() {
+        let quarter = Quaternion::from_angle_y(Rad(std::f32::consts::FRAC_PI_2));
+        let xyzw =
+            |rotation: Quaternion<f32>| [rotation.v.x, rotation.v.y, rotation.v.z, rotation.s];
+        // the same end rotation with the opposite sign
+        let rotation = channel(
+            Property::Rotation,
+            Interpolation::Linear,
+            vec![xyzw(Quaternion::one()), xyzw(-quarter)],
+        );
+
+        let halfway = Quaternion::from_angle_y(Rad(std::f32::consts::FRAC_PI_4));
+        assert!(close(
+            rotation.sample(2.0).unwrap(),
+            Vector4::from(xyzw(halfway))
+        ));
+    }
+
+    #[test]
+    fn clips_only_touch_what_they_animate() {
+        let clip = AnimationClip::new(
+            String::from("slide"),
+            vec![translation(Interpolation::Linear)],
+        );
+        assert_eq!(clip.duration, 3.0);
+
+        let rest = NodeTransform {
+            scale: Vector3::new(2.0, 2.0, 2.0),
+            ..NodeTransform::default()
+        };
+        let mut pose = [rest, rest];
+        clip.sample(3.0, &mut pose);
+
+        assert_eq!(pose[0].translation, Vector3::new(4.0, 2.0, 0.0));
+        assert_eq!(pose[0].scale, rest.scale);
+        assert_eq!(pose[1], rest);
+    }
+
+    #[test]
+    fn playing_cross_fades() {
+        // two clips holding node 0 at x 0 and x 4
+        let hold = |x: f32| {
+            AnimationClip::new(
+                String::from("hold"),
+                vec![Channel {
+                    node: 0,
+                    property: Property::Translation,
+                    interpolation: Interpolation::Step,
+                    times: vec![0.0, 1.0],
+                    values: vec![Vector4::new(x, 0.0, 0.0, 0.0); 2],
+                }],
+            )
+        };
+        let clips = [hold(0.0), hold(4.0)];
+        let rest = [NodeTransform::default()];
+        let x = |animator: &Animator| animator.pose(&clips, &rest)[0].translation.x;
+
+        let mut animator = Animator::new();
+        animator.play(0, 0.0);
+        animator.update(0.1, &clips);
+        assert_eq!(x(&animator), 0.0);
+
+        animator.play(1, 0.5);
+        assert_eq!(animator.current(), Some(1));
+        animator.update(0.25, &clips);
+        assert!((x(&animator) - 2.0).abs() < 1e-5);
+
+        // once faded out, the old clip is dropped
+        animator.update(0.5, &clips);
+        assert_eq!(x(&animator), 4.0);
+        assert_eq!(animator.playbacks.len(), 1);
+
+        animator.play(0, 0.0);
+        assert_eq!(animator.playbacks.len(), 1);
+        assert_eq!(x(&animator), 0.0);
+    }
+
+    #[test]
+    fn joint_matrices_undo_the_bind_pose() {
+        let bind = Matrix4::from_translation(Vector3::new(0.0, 1.0, 0.0));
+        let skin = Skin {
+            joints: vec![1],
+            inverse_bind_matrices: vec![bind.invert().unwrap()],
+        };
+
+        // a joint still in its bind pose leaves vertices where they are
+        let world = [Matrix4::one(), bind];
+        assert_eq!(skin.joint_matrices(&world), [Matrix4::one()]);
+
+        let moved = Matrix4::from_translation(Vector3::new(2.0, 0.0, 0.0)) * bind;
+        let world = [Matrix4::one(), moved];
+        let point = skin.joint_matrices(&world)[0] * Vector4::new(0.0, 1.5, 0.0, 1.0);
+        assert_eq!(point, Vector4::new(2.0, 1.5, 0.0, 1.0));
+    }
+}
@@ -0,0 +1,303 @@
+use cgmath::{InnerSpace, Matrix4, MetricSpace, Point3, Transform, Vector3, Vector4};
+
+/// Axis-aligned bounding box.
+#[derive(Clone, Copy, Debug)]
+pub struct Aabb {
+    pub min: Point3<f32>,
+    pub max: Point3<f32>,
+}
+
+impl Aabb {
+    pub fn from_points(points: impl IntoIterator<Item = [f32; 3]>) -> Self {
+        let mut min = Point3::new(f32::MAX, f32::MAX, f32::MAX);
+        let mut max = Point3::new(f32::MIN, f32::MIN, f32::MIN);
+
+        for [x, y, z] in points {
+            min = Point3::new(min.x.min(x), min.y.min(y), min.z.min(z));
+            max = Point3::new(max.x.max(x), max.y.max(y), max.z.max(z));
+        }
+
+        // an empty mesh collapses to the origin instead of an inverted box
+        if min.x > max.x {
+            return Aabb {
+                min: Point3::new(0.0, 0.0, 0.0),
+                max: Point3::new(0.0, 0.0, 0.0),
+            };
+        }
+
+        Aabb { min, max }
+    }
+
+    pub fn union(self, other: Aabb) -> Aabb {
+        Aabb {
+            min: Point3::new(
+                self.min.x.min(other.min.x),
+                self.min.y.min(other.min.y),
+                self.min.z.min(other.min.z),
+            ),
+            max: Point3::new(
+                self.max.x.max(other.max.x),
+                self.max.y.max(other.max.y),
+                self.max.z.max(other.max.z),
+            ),
+        }
+    }
+
+    pub fn center(&self) -> Point3<f32> {
+        self.min + (self.max - self.min) * 0.5
+    }
+
+    pub fn extents(&self) -> Vector3<f32> {
+        (self.max - self.min) * 0.5
+    }
+
+    /// Bounds of the box after transforming it, still axis-aligned.
+    pub fn transform(&self, matrix: &Matrix4<f32>) -> Aabb {
+        let center = matrix * self.center().to_homogeneous();
+        let extents = self.extents();
+
+        // every world axis gets the absolute contribution of each local axis
+        let world_extents = Vector3::new(
+            matrix.x.x.abs() * extents.x
+                + matrix.y.x.abs() * extents.y
+                + matrix.z.x.abs() * extents.z,
+            matrix.x.y.abs() * extents.x
+                + matrix.y.y.abs() * extents.y
+                + matrix.z.y.abs() * extents.z,
+            matrix.x.z.abs() * extents.x
+                + matrix.y.z.abs() * extents.y
+                + matrix.z.z.abs() * extents.z,
+        );
+
+        let center = Point3::new(center.x, center.y, center.z);
+
+        Aabb {
+            min: center - world_extents,
+            max: center + world_extents,
+        }
+    }
+}
+
+/// Bounding sphere, cheaper to transform and test than a box and the same size from every
+/// direction, which is what framing and LOD selection want.
+#[derive(Clone, Copy, Debug)]
+pub struct BoundingSphere {
+    pub center: Point3<f32>,
+    pub radius: f32,
+}
+
+impl BoundingSphere {
+    /// Centered on the points' bounding box, not the smallest sphere but close for the
+    /// shapes we load and found in a single pass over the box.
+    pub fn from_points(points: impl IntoIterator<Item = [f32; 3]> + Clone) -> Self {
+        let center = Aabb::from_points(points.clone()).center();
+        let radius = points
+            .into_iter()
+            .map(|point| center.distance2(Point3::from(point)))
+            .fold(0.0, f32::max)
+            .sqrt();
+
+        BoundingSphere { center, radius }
+    }
+
+    /// The smallest sphere around both.
+    pub fn union(self, other: BoundingSphere) -> BoundingSphere {
+        let offset = other.center - self.center;
+        let distance = offset.magnitude();
+
+        // one already contains the other
+        if distance + other.radius <= self.radius {
+            return self;
+        }
+        if distance + self.radius <= other.radius {
+            return other;
+        }
+
+        let radius = (distance + self.radius + other.radius) * 0.5;
+        BoundingSphere {
+            center: self.center + offset * ((radius - self.radius) / distance),
+            radius,
+        }
+    }
+
+    /// Bounds of the sphere after transforming it, non-uniform scales grow it by the
+    /// largest axis.
+    pub fn transform(&self, matrix: &Matrix4<f32>) -> BoundingSphere {
+        let scale = [matrix.x, matrix.y, matrix.z]
+            .iter()
+            .map(|axis| axis.truncate().magnitude())
+            .fold(0.0, f32::max);
+
+        BoundingSphere {
+            center: matrix.transform_point(self.center),
+            radius: self.radius * scale,
+        }
+    }
+}
+
+/// The six clip planes of a view-projection matrix, normals pointing inwards.
+pub struct Frustum {
+    planes: [Vector4<f32>; 6],
+}
+
+impl Frustum {
+    /// Expects wgpu clip space, i.e. depth in 0..1 after `OPENGL_TO_WGPU_MATRIX`.
+    pub fn from_matrix(view_proj: &Matrix4<f32>) -> Self {
+        let row = |i: usize| {
+            Vector4::new(
+                view_proj.x[i],
+                view_proj.y[i],
+                view_proj.z[i],
+                view_proj.w[i],
+            )
+        };
+        let (r0, r1, r2, r3) = (row(0), row(1), row(2), row(3));
+
+        let planes = [r3 + r0, r3 - r0, r3 + r1, r3 - r1, r2, r3 - r2].map(|plane| {
+            let length = plane.truncate().magnitude();
+
+            // an infinite far plane has no normal and never culls anything
+            if length < f32::EPSILON {
+                Vector4::new(0.0, 0.0, 0.0, 1.0)
+            } else {
+                plane / length
+            }
+        });
+
+        Frustum { planes }
+    }
+
+    pub fn intersects(&self, aabb: &Aabb) -> bool {
+        self.planes.iter().all(|plane| {
+            // the corner furthest along the plane normal
+            let pick = |normal: f32, min: f32, max: f32| if normal >= 0.0 { max } else { min };
+            let corner = Vector3::new(
+                pick(plane.x, aabb.min.x, aabb.max.x),
+                pick(plane.y, aabb.min.y, aabb.max.y),
+                pick(plane.z, aabb.min.z, aabb.max.z),
+            );
+
+            plane.truncate().dot(corner) + plane.w >= 0.0
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{camera::Camera, core::texture::DepthMode};
+
+    // a unit box around a point
+    fn cube(x: f32, y: f32, z: f32) -> Aabb {
+        Aabb {
+            min: Point3::new(x - 0.5, y - 0.5, z - 0.5),
+            max: Point3::new(x + 0.5, y + 0.5, z + 0.5),
+        }
+    }
+
+    // at the origin looking down -Z, znear 0.1 and zfar 100
+    fn frustum(depth_mode: DepthMode) -> Frustum {
+        let camera = Camera {
+            eye: Point3::new(0.0, 0.0, 0.0),
+            target: Point3::new(0.0, 0.0, -1.0),
+            depth_mode,
+            ..Camera::default()
+        };
+
+        Frustum::from_matrix(&camera.build_view_projection_matrix())
+    }
+
+    #[test]
+    fn frustum_keeps_what_is_in_view() {
+        for depth_mode in [DepthMode::Standard, DepthMode::ReverseZ] {
+            let frustum = frustum(depth_mode);
+
+            assert!(
+                frustum.intersects(&cube(0.0, 0.0, -5.0)),
+                "{:?}",
+                depth_mode
+            );
+            assert!(
+                frustum.intersects(&cube(0.0, 0.0, -99.0)),
+                "{:?}",
+                depth_mode
+            );
+            // straddling an edge of the view
+            assert!(
+                frustum.intersects(&cube(2.3, 0.0, -5.0)),
+                "{:?}",
+                depth_mode
+            );
+        }
+    }
+
+    #[test]
+    fn frustum_culls_behind_and_beside() {
+        for depth_mode in [DepthMode::Standard, DepthMode::ReverseZ] {
+            let frustum = frustum(depth_mode);
+
+            assert!(
+                !frustum.intersects(&cube(0.0, 0.0, 5.0)),
+                "{:?}",
+                depth_mode
+            );
+            assert!(
+                !frustum.intersects(&cube(20.0, 0.0, -5.0)),
+                "{:?}",
+                depth_mode
+            );
+            assert!(
+                !frustum.intersects(&cube(-20.0, 0.0, -5.0)),
+                "{:?}",
+                depth_mode
+            );
+            assert!(
+                !frustum.intersects(&cube(0.0, 20.0, -5.0)),
+                "{:?}",
+                depth_mode
+            );
+            assert!(
+                !frustum.intersects(&cube(0.0, -20.0, -5.0)),
+                "{:?}",
+                depth_mode
+            );
+        }
+    }
+
+    #[test]
+    fn far_plane_only_culls_with_standard_depth() {
+        // reverse Z projects to an infinite far plane
+        assert!(!frustum(DepthMode::Standard).intersects(&cube(0.0, 0.0, -200.0)));
+        assert!(frustum(DepthMode::ReverseZ).intersects(&cube(0.0, 0.0, -200.0)));
+    }
+
+    #[test]
+    fn sphere_union_contains_both() {
+        let a = BoundingSphere {
+            center: Point3::new(0.0, 0.0, 0.0),
+            radius: 1.0,
+        };
+        let b = BoundingSphere {
+            center: Point3::new(4.0, 0.0, 0.0),
+            radius: 1.0,
+        };
+
+        let union = a.union(b);
+        assert!((union.radius - 3.0).abs() < 1e-5);
+        assert!((union.center.x - 2.0).abs() < 1e-5);
+        // already inside
+        assert_eq!(union.union(a).radius, union.radius);
+    }
+
+    #[test]
+    fn transformed_box_stays_around_the_corners() {
+        let matrix = Matrix4::from_translation(Vector3::new(1.0, 2.0, 3.0))
+            * Matrix4::from_angle_y(cgmath::Deg(45.0));
+        let aabb = cube(0.0, 0.0, 0.0).transform(&matrix);
+
+        let half_diagonal = 0.5 * 2.0_f32.sqrt();
+        assert!((aabb.max.x - (1.0 + half_diagonal)).abs() < 1e-5);
+        assert!((aabb.min.z - (3.0 - half_diagonal)).abs() < 1e-5);
+        assert!((aabb.max.y - 2.5).abs() < 1e-5);
+    }
+}
@@ -0,0 +1,53 @@
+use std::time::Instant;
+
+enum Source {
+    Real(Instant),
+    // seconds per frame
+    Fixed(f32),
+}
+
+/// App time in seconds since start, advanced once per frame. A fixed step makes a run
+/// independent of frame rate, e.g. for recordings that should replay the same anywhere.
+pub struct Clock {
+    source: Source,
+    now: f32,
+}
+
+impl Clock {
+    pub fn real() -> Self {
+        Clock {
+            source: Source::Real(Instant::now()),
+            now: 0.0,
+        }
+    }
+
+    pub fn fixed(step: f32) -> Self {
+        Clock {
+            source: Source::Fixed(step),
+            now: 0.0,
+        }
+    }
+
+    /// Time of the current frame, the same until the next `tick`.
+    pub fn now(&self) -> f32 {
+        self.now
+    }
+
+    /// Starts a new frame and returns the time since the last one.
+    pub fn tick(&mut self) -> f32 {
+        let time = match self.source {
+            Source::Real(start) => start.elapsed().as_secs_f32(),
+            Source::Fixed(step) => self.now + step,
+        };
+
+        self.advance_to(time)
+    }
+
+    /// Starts a new frame at a given time, e.g. one read from a recording.
+    pub fn advance_to(&mut self, time: f32) -> f32 {
+        let dt = (time - self.now).max(0.0);
+        self.now = time;
+
+        dt
+    }
+}
@@ -0,0 +1,10 @@
+/// GPU object labels follow "<scope>: <object>", where the scope is the component name,
+/// optionally followed by the asset path, e.g. "Cube: render pipeline" or
+/// "cube.obj/Cube_Finished_Cube.001: vertex buffer".
+pub fn label(scope: &str, object: &str) -> String {
+    format!("{}: {}", scope, object)
+}
+
+pub fn asset_scope(asset: &str, name: &str) -> String {
+    format!("{}/{}", asset, name)
+}
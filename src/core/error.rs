@@ -0,0 +1,191 @@
+use std::{fmt, io};
+
+#[derive(Clone, Copy, Debug)]
+pub enum Operation {
+    CreateTexture,
+    CreateBindGroup,
+    CreateRenderPipeline,
+    LoadModel,
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Operation::CreateTexture => "create texture",
+            Operation::CreateBindGroup => "create bind group",
+            Operation::CreateRenderPipeline => "create render pipeline",
+            Operation::LoadModel => "load model",
+        };
+
+        f.write_str(name)
+    }
+}
+
+/// A wgpu validation error caught by an `ErrorScope`, tagged with where it happened.
+#[derive(Debug)]
+pub struct GpuError {
+    pub component: &'static str,
+    pub asset: Option<String>,
+    pub operation: Operation,
+    pub message: String,
+}
+
+impl fmt::Display for GpuError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.asset {
+            Some(asset) => write!(
+                f,
+                "{}: failed to {} ({}): {}",
+                self.component, self.operation, asset, self.message
+            ),
+            None => write!(
+                f,
+                "{}: failed to {}: {}",
+                self.component, self.operation, self.message
+            ),
+        }
+    }
+}
+
+impl std::error::Error for GpuError {}
+
+/// Why an asset couldn't be loaded. Paths are relative to `res/`, like the file names the
+/// loaders take.
+#[derive(Debug)]
+pub enum ResourceError {
+    NotFound {
+        path: String,
+    },
+    Io {
+        path: String,
+        source: io::Error,
+    },
+    Decode {
+        path: String,
+        source: image::ImageError,
+    },
+    // a texture a material refers to, usually downgraded to a warning and a fallback
+    MissingTexture {
+        material: String,
+        path: String,
+        cause: Box<ResourceError>,
+    },
+    MalformedObj {
+        path: String,
+        source: tobj::LoadError,
+    },
+    // anything else wrong with the contents, e.g. a glTF buffer that's too short
+    Malformed {
+        path: String,
+        reason: String,
+    },
+}
+
+impl ResourceError {
+    pub fn read(path: &str, source: io::Error) -> Self {
+        match source.kind() {
+            io::ErrorKind::NotFound => ResourceError::NotFound {
+                path: path.to_string(),
+            },
+            _ => ResourceError::Io {
+                path: path.to_string(),
+                source,
+            },
+        }
+    }
+
+    pub fn malformed(path: &str, reason: impl Into<String>) -> Self {
+        ResourceError::Malformed {
+            path: path.to_string(),
+            reason: reason.into(),
+        }
+    }
+}
+
+impl fmt::Display for ResourceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ResourceError::NotFound { path } => write!(f, "{}: not found", path),
+            ResourceError::Io { path, source } => write!(f, "{}: can't read: {}", path, source),
+            ResourceError::Decode { path, source } => {
+                write!(f, "{}: can't decode image: {}", path, source)
+            }
+            ResourceError::MissingTexture {
+                material,
+                path,
+                cause,
+            } => write!(f, "{}: unusable texture {} ({})", material, path, cause),
+            ResourceError::MalformedObj { path, source } => {
+                write!(f, "{}: malformed OBJ: {}", path, source)
+            }
+            ResourceError::Malformed { path, reason } => write!(f, "{}: {}", path, reason),
+        }
+    }
+}
+
+impl std::error::Error for ResourceError {}
+
+/// Captures validation errors raised between `push` and `pop`.
+///
+/// Scopes nest, so they have to be popped in the reverse order they were pushed. A scope
+/// dropped without `pop`, like on an early `?` return, is popped on drop and its error
+/// discarded, so it can't swallow the validation errors of everything after it.
+pub struct ErrorScope<'a> {
+    device: &'a wgpu::Device,
+    component: &'static str,
+    asset: Option<String>,
+    operation: Operation,
+    popped: bool,
+}
+
+impl<'a> ErrorScope<'a> {
+    pub fn push(
+        device: &'a wgpu::Device,
+        component: &'static str,
+        asset: Option<&str>,
+        operation: Operation,
+    ) -> Self {
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+
+        ErrorScope {
+            device,
+            component,
+            asset: asset.map(String::from),
+            operation,
+            popped: false,
+        }
+    }
+
+    pub async fn pop(mut self) -> Result<(), GpuError> {
+        // the scope comes off the device's stack when popping starts, not when it resolves
+        let error = self.device.pop_error_scope();
+        self.popped = true;
+
+        match error.await {
+            Some(error) => Err(GpuError {
+                component: self.component,
+                asset: self.asset.take(),
+                operation: self.operation,
+                message: error.to_string(),
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for ErrorScope<'_> {
+    fn drop(&mut self) {
+        if !self.popped {
+            drop(self.device.pop_error_scope());
+        }
+    }
+}
+
+/// Errors outside of any scope are logged in debug builds instead of aborting the app.
+pub fn install_uncaptured_error_handler(device: &wgpu::Device) {
+    if cfg!(debug_assertions) {
+        device.on_uncaptured_error(Box::new(|error| {
+            log::error!("Uncaptured wgpu error: {}", error);
+        }));
+    }
+}
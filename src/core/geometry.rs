@@ -0,0 +1,878 @@
+use std::collections::HashMap;
+
+use cgmath::{InnerSpace, Vector2, Vector3};
+
+use super::model::ModelVertex;
+
+// positions compared bit for bit, so vertices split at UV seams still share normals
+fn position_key(position: [f32; 3]) -> [u32; 3] {
+    position.map(f32::to_bits)
+}
+
+/// Replaces the normals of an indexed triangle list. Faces meeting at an angle below
+/// `crease_angle` (degrees) are smoothed together, sharper edges stay hard: 180 gives
+/// fully smooth, 0 flat shading. Vertices on hard edges are split, so both the vertices
+/// and the indices are rewritten. Returns the old index of every new vertex, to carry other
+/// per-vertex data along.
+pub fn generate_normals(
+    vertices: &mut Vec<ModelVertex>,
+    indices: &mut Vec<u32>,
+    crease_angle: f32,
+) -> Vec<u32> {
+    let position = |index: u32| Vector3::from(vertices[index as usize].pos);
+
+    // unnormalized, so larger faces weigh more
+    let face_normals = indices
+        .chunks_exact(3)
+        .map(|face| {
+            let (a, b, c) = (position(face[0]), position(face[1]), position(face[2]));
+            (b - a).cross(c - a)
+        })
+        .collect::<Vec<_>>();
+
+    let mut faces_at = HashMap::<[u32; 3], Vec<usize>>::new();
+    for (face, corners) in indices.chunks_exact(3).enumerate() {
+        for &index in corners {
+            faces_at
+                .entry(position_key(vertices[index as usize].pos))
+                .or_default()
+                .push(face);
+        }
+    }
+
+    let unit = |normal: Vector3<f32>| {
+        if normal.magnitude2() > 0.0 {
+            normal.normalize()
+        } else {
+            Vector3::unit_y()
+        }
+    };
+    // small tolerance so coplanar faces still count as flat neighbours
+    let min_cos = crease_angle.to_radians().cos() - 1e-4;
+
+    let mut new_vertices = Vec::with_capacity(vertices.len());
+    let mut new_indices = Vec::with_capacity(indices.len());
+    let mut origins = Vec::with_capacity(vertices.len());
+    let mut remap = HashMap::<(u32, [u32; 3]), u32>::new();
+
+    for (face, corners) in indices.chunks_exact(3).enumerate() {
+        let face_normal = unit(face_normals[face]);
+
+        for &index in corners {
+            let vertex = vertices[index as usize];
+
+            let normal = unit(
+                faces_at[&position_key(vertex.pos)]
+                    .iter()
+                    .map(|&other| face_normals[other])
+                    .filter(|&other| unit(other).dot(face_normal) >= min_cos)
+                    .sum::<Vector3<f32>>(),
+            );
+            let normal: [f32; 3] = normal.into();
+
+            let new_index = *remap
+                .entry((index, position_key(normal)))
+                .or_insert_with(|| {
+                    new_vertices.push(ModelVertex { normal, ..vertex });
+                    origins.push(index);
+                    (new_vertices.len() - 1) as u32
+                });
+            new_indices.push(new_index);
+        }
+    }
+
+    *vertices = new_vertices;
+    *indices = new_indices;
+    origins
+}
+
+/// Fills in the tangents of an indexed triangle list from its normals and texture
+/// coordinates, the way MikkTSpace builds them: each face's tangent is projected into the
+/// tangent plane of every corner's normal, normalized, and summed per vertex weighted by the
+/// corner's angle. The tangent points along +u and `w` is the sign to apply to
+/// `cross(normal, tangent)` to get the bitangent along +v. Vertices shared by faces with
+/// mirrored texture coordinates are split, so both the vertices and the indices are
+/// rewritten; returns the old index of every new vertex like `generate_normals`. Unlike the
+/// reference it doesn't also split vertices where the faces' tangents diverge sharply, so
+/// results can differ at such UV seams. Normals have to be set first.
+pub fn generate_tangents(vertices: &mut Vec<ModelVertex>, indices: &mut Vec<u32>) -> Vec<u32> {
+    // per face the direction of +u and whether its UVs keep the winding, None when degenerate
+    let faces = indices
+        .chunks_exact(3)
+        .map(|face| {
+            let corner = |i: usize| {
+                let vertex = &vertices[face[i] as usize];
+                (Vector3::from(vertex.pos), Vector2::from(vertex.tex_coords))
+            };
+            let (p0, uv0) = corner(0);
+            let (p1, uv1) = corner(1);
+            let (p2, uv2) = corner(2);
+
+            let (e1, e2) = (p1 - p0, p2 - p0);
+            let (d1, d2) = (uv1 - uv0, uv2 - uv0);
+
+            // twice the signed area in texture space
+            let det = d1.x * d2.y - d2.x * d1.y;
+            if det.abs() < f32::EPSILON {
+                return None;
+            }
+
+            Some(((e1 * d2.y - e2 * d1.y) / det, det > 0.0))
+        })
+        .collect::<Vec<_>>();
+
+    let project = |normal: Vector3<f32>, tangent: Vector3<f32>| {
+        let tangent = tangent - normal * normal.dot(tangent);
+        (tangent.magnitude2() > 0.0).then(|| tangent.normalize())
+    };
+
+    // per vertex and UV orientation
+    let mut sums = HashMap::<(u32, bool), Vector3<f32>>::new();
+    for (corners, face) in indices.chunks_exact(3).zip(&faces) {
+        let Some((tangent, preserves)) = *face else {
+            continue;
+        };
+
+        for (i, &index) in corners.iter().enumerate() {
+            let position = |i: usize| Vector3::from(vertices[corners[i % 3] as usize].pos);
+            let (a, b) = (position(i + 1) - position(i), position(i + 2) - position(i));
+            let sum = sums
+                .entry((index, preserves))
+                .or_insert(Vector3::new(0.0, 0.0, 0.0));
+            if a.magnitude2() == 0.0 || b.magnitude2() == 0.0 {
+                continue;
+            }
+            let angle = a.normalize().dot(b.normalize()).clamp(-1.0, 1.0).acos();
+
+            if let Some(tangent) = project(Vector3::from(vertices[index as usize].normal), tangent)
+            {
+                *sum += tangent * angle;
+            }
+        }
+    }
+
+    let mut new_vertices = Vec::with_capacity(vertices.len());
+    let mut new_indices = Vec::with_capacity(indices.len());
+    let mut origins = Vec::with_capacity(vertices.len());
+    let mut remap = HashMap::<(u32, bool), u32>::new();
+
+    for (corners, face) in indices.chunks_exact(3).zip(&faces) {
+        for &index in corners {
+            // degenerate faces take whatever their vertices got from the others
+            let preserves = match face {
+                Some((_, preserves)) => *preserves,
+                None => !sums.contains_key(&(index, false)),
+            };
+            let key = (index, preserves);
+
+            let new_index = *remap.entry(key).or_insert_with(|| {
+                let vertex = vertices[index as usize];
+                let normal = Vector3::from(vertex.normal);
+                let sum = sums
+                    .get(&key)
+                    .copied()
+                    .unwrap_or(Vector3::new(0.0, 0.0, 0.0));
+
+                // any direction orthogonal to the normal when nothing says otherwise
+                let tangent = project(normal, sum).unwrap_or_else(|| {
+                    let axis = if normal.x.abs() < 0.9 {
+                        Vector3::unit_x()
+                    } else {
+                        Vector3::unit_y()
+                    };
+                    project(normal, axis).unwrap_or(axis)
+                });
+                let sign = if preserves { 1.0 } else { -1.0 };
+
+                new_vertices.push(ModelVertex {
+                    tangent: [tangent.x, tangent.y, tangent.z, sign],
+                    ..vertex
+                });
+                origins.push(index);
+                (new_vertices.len() - 1) as u32
+            });
+            new_indices.push(new_index);
+        }
+    }
+
+    *vertices = new_vertices;
+    *indices = new_indices;
+    origins
+}
+
+// symmetric 4x4 matrix summing squared distances to planes, upper triangle row by row
+#[derive(Clone, Copy, Default)]
+struct Quadric([f64; 10]);
+
+impl Quadric {
+    fn from_plane(normal: Vector3<f32>, point: Vector3<f32>, weight: f32) -> Self {
+        let [a, b, c] = [normal.x as f64, normal.y as f64, normal.z as f64];
+        let d = -normal.dot(point) as f64;
+        let w = weight as f64;
+
+        Quadric(
+            [
+                a * a,
+                a * b,
+                a * c,
+                a * d,
+                b * b,
+                b * c,
+                b * d,
+                c * c,
+                c * d,
+                d * d,
+            ]
+            .map(|term| term * w),
+        )
+    }
+
+    fn add(&self, other: &Quadric) -> Quadric {
+        let mut sum = *self;
+        for (term, other) in sum.0.iter_mut().zip(other.0) {
+            *term += other;
+        }
+        sum
+    }
+
+    fn error(&self, point: [f32; 3]) -> f64 {
+        let [x, y, z] = point.map(|coordinate| coordinate as f64);
+        let q = &self.0;
+
+        q[0] * x * x
+            + 2.0 * q[1] * x * y
+            + 2.0 * q[2] * x * z
+            + 2.0 * q[3] * x
+            + q[4] * y * y
+            + 2.0 * q[5] * y * z
+            + 2.0 * q[6] * y
+            + q[7] * z * z
+            + 2.0 * q[8] * z
+            + q[9]
+    }
+}
+
+/// Indices of a coarser version of an indexed triangle list, with about `target_ratio` of
+/// its triangles. Edges collapse cheapest first by quadric error, and never if that would
+/// flip a face or pinch the surface. Vertices only ever move onto other vertices, so the
+/// result indexes the same vertices. Vertices on open borders or attribute seams (one
+/// position, several vertices) stay put, which keeps textures from tearing but limits how
+/// far meshes with many hard edges simplify.
+pub fn simplify(vertices: &[ModelVertex], indices: &[u32], target_ratio: f32) -> Vec<u32> {
+    let position = |index: u32| Vector3::from(vertices[index as usize].pos);
+    let target = (indices.len() / 3) as f32 * target_ratio.clamp(0.0, 1.0);
+
+    let mut locked = vec![false; vertices.len()];
+
+    let mut at_position = HashMap::<[u32; 3], u32>::new();
+    for vertex in vertices {
+        *at_position.entry(position_key(vertex.pos)).or_default() += 1;
+    }
+    for (locked, vertex) in locked.iter_mut().zip(vertices) {
+        *locked = at_position[&position_key(vertex.pos)] > 1;
+    }
+
+    let mut edge_faces = HashMap::<(u32, u32), u32>::new();
+    for face in indices.chunks_exact(3) {
+        for i in 0..3 {
+            let (a, b) = (face[i], face[(i + 1) % 3]);
+            *edge_faces.entry((a.min(b), a.max(b))).or_default() += 1;
+        }
+    }
+    for (&(a, b), &count) in &edge_faces {
+        if count == 1 {
+            locked[a as usize] = true;
+            locked[b as usize] = true;
+        }
+    }
+
+    // area weighted, so slivers barely count
+    let mut quadrics = vec![Quadric::default(); vertices.len()];
+    for face in indices.chunks_exact(3) {
+        let (a, b, c) = (position(face[0]), position(face[1]), position(face[2]));
+        let normal = (b - a).cross(c - a);
+        let area = normal.magnitude();
+        if area <= f32::EPSILON {
+            continue;
+        }
+
+        let plane = Quadric::from_plane(normal / area, a, area);
+        for &index in face {
+            quadrics[index as usize] = quadrics[index as usize].add(&plane);
+        }
+    }
+
+    let mut indices = indices.to_vec();
+
+    // each pass collapses edges far enough apart not to affect each other's checks
+    while (indices.len() / 3) as f32 > target {
+        let mut faces_at = vec![Vec::new(); vertices.len()];
+        for (face, corners) in indices.chunks_exact(3).enumerate() {
+            for &index in corners {
+                faces_at[index as usize].push(face);
+            }
+        }
+        let neighbours = |index: u32| {
+            let mut neighbours = faces_at[index as usize]
+                .iter()
+                .flat_map(|&face| &indices[face * 3..face * 3 + 3])
+                .copied()
+                .filter(|&other| other != index)
+                .collect::<Vec<_>>();
+            neighbours.sort_unstable();
+            neighbours.dedup();
+            neighbours
+        };
+
+        let mut candidates = indices
+            .chunks_exact(3)
+            .flat_map(|face| (0..3).map(move |i| (face[i], face[(i + 1) % 3])))
+            .flat_map(|(a, b)| [(a, b), (b, a)])
+            .filter(|&(from, _)| !locked[from as usize])
+            .map(|(from, to)| {
+                let quadric = quadrics[from as usize].add(&quadrics[to as usize]);
+                (quadric.error(vertices[to as usize].pos), from, to)
+            })
+            .collect::<Vec<_>>();
+        candidates.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let mut remap = (0..vertices.len() as u32).collect::<Vec<_>>();
+        let mut touched = vec![false; vertices.len()];
+        let mut removed = 0;
+        let budget = (indices.len() / 3) as f32 - target;
+
+        for (_, from, to) in candidates {
+            if removed as f32 >= budget {
+                break;
+            }
+            if touched[from as usize] || touched[to as usize] {
+                continue;
+            }
+
+            // the edge's faces disappear, the rest around `from` are stretched to `to`
+            let (shared, moved): (Vec<usize>, Vec<usize>) = faces_at[from as usize]
+                .iter()
+                .copied()
+                .partition(|&face| indices[face * 3..face * 3 + 3].contains(&to));
+
+            // only the edge's own faces may connect both ends, anything else pinches
+            let from_neighbours = neighbours(from);
+            let common = neighbours(to)
+                .into_iter()
+                .filter(|other| from_neighbours.binary_search(other).is_ok())
+                .count();
+            if common != shared.len() {
+                continue;
+            }
+
+            let flips = moved.iter().any(|&face| {
+                let corners = [
+                    indices[face * 3],
+                    indices[face * 3 + 1],
+                    indices[face * 3 + 2],
+                ];
+                let before = (position(corners[1]) - position(corners[0]))
+                    .cross(position(corners[2]) - position(corners[0]));
+                let corners = corners.map(|index| if index == from { to } else { index });
+                let after = (position(corners[1]) - position(corners[0]))
+                    .cross(position(corners[2]) - position(corners[0]));
+
+                // against the normals too, or flips add up over several collapses
+                after.dot(before) <= 0.0
+                    || corners.iter().any(|&index| {
+                        after.dot(Vector3::from(vertices[index as usize].normal)) <= 0.0
+                    })
+            });
+            if flips {
+                continue;
+            }
+
+            remap[from as usize] = to;
+            quadrics[to as usize] = quadrics[to as usize].add(&quadrics[from as usize]);
+            for &face in &faces_at[from as usize] {
+                for &index in &indices[face * 3..face * 3 + 3] {
+                    touched[index as usize] = true;
+                }
+            }
+            removed += shared.len();
+        }
+
+        if removed == 0 {
+            break;
+        }
+
+        indices = indices
+            .chunks_exact(3)
+            .map(|face| [face[0], face[1], face[2]].map(|index| remap[index as usize]))
+            .filter(|face| face[0] != face[1] && face[1] != face[2] && face[2] != face[0])
+            .flatten()
+            .collect();
+    }
+
+    indices
+}
+
+/// Drops the vertices no index refers to, renumbering the rest in the order the indices
+/// first use them.
+pub fn compact(vertices: &[ModelVertex], indices: &[u32]) -> (Vec<ModelVertex>, Vec<u32>) {
+    let mut remap = vec![u32::MAX; vertices.len()];
+    let mut compacted = Vec::new();
+
+    let indices = indices
+        .iter()
+        .map(|&index| {
+            if remap[index as usize] == u32::MAX {
+                remap[index as usize] = compacted.len() as u32;
+                compacted.push(vertices[index as usize]);
+            }
+            remap[index as usize]
+        })
+        .collect();
+
+    (compacted, indices)
+}
+
+// post-transform cache modelled by the vertex cache optimization, bigger than most real ones
+// but orders that suit it suit smaller caches too
+const CACHE_SIZE: usize = 32;
+
+// Forsyth's score: vertices high in the cache and with few triangles left rank first
+fn vertex_score(cache_position: Option<usize>, remaining: u32) -> f32 {
+    if remaining == 0 {
+        return -1.0;
+    }
+
+    let cache = match cache_position {
+        // the last triangle's own vertices, a little lower so it doesn't fan out forever
+        Some(position) if position < 3 => 0.75,
+        Some(position) => (1.0 - (position - 3) as f32 / (CACHE_SIZE - 3) as f32).powf(1.5),
+        None => 0.0,
+    };
+
+    cache + 2.0 * (remaining as f32).powf(-0.5)
+}
+
+// triangle order for the vertex cache, after Tom Forsyth's linear-speed optimization
+fn optimize_vertex_cache(indices: &[u32], vertex_count: usize) -> Vec<u32> {
+    let triangle_count = indices.len() / 3;
+    let corners = |triangle: usize| {
+        [
+            indices[triangle * 3],
+            indices[triangle * 3 + 1],
+            indices[triangle * 3 + 2],
+        ]
+    };
+
+    let mut triangles_of = vec![Vec::new(); vertex_count];
+    for triangle in 0..triangle_count {
+        for index in corners(triangle) {
+            triangles_of[index as usize].push(triangle);
+        }
+    }
+
+    let mut remaining = triangles_of
+        .iter()
+        .map(|triangles| triangles.len() as u32)
+        .collect::<Vec<_>>();
+    let mut cache_position = vec![None; vertex_count];
+    let mut scores = remaining
+        .iter()
+        .map(|&remaining| vertex_score(None, remaining))
+        .collect::<Vec<_>>();
+    let mut triangle_scores = (0..triangle_count)
+        .map(|triangle| {
+            corners(triangle)
+                .iter()
+                .map(|&index| scores[index as usize])
+                .sum::<f32>()
+        })
+        .collect::<Vec<_>>();
+
+    let mut emitted = vec![false; triangle_count];
+    let mut cache = Vec::<u32>::with_capacity(CACHE_SIZE + 3);
+    let mut next_unemitted = 0;
+    let mut optimized = Vec::with_capacity(indices.len());
+
+    for _ in 0..triangle_count {
+        // the best triangle using a cached vertex, or when none is left the next one in order
+        let best = cache
+            .iter()
+            .flat_map(|&index| &triangles_of[index as usize])
+            .copied()
+            .filter(|&triangle| !emitted[triangle])
+            .max_by(|&a, &b| triangle_scores[a].total_cmp(&triangle_scores[b]));
+        let best = best.unwrap_or_else(|| {
+            while emitted[next_unemitted] {
+                next_unemitted += 1;
+            }
+            next_unemitted
+        });
+
+        emitted[best] = true;
+        let face = corners(best);
+        optimized.extend_from_slice(&face);
+        for index in face {
+            remaining[index as usize] -= 1;
+        }
+
+        // the triangle's vertices move to the front, whatever falls off the end is evicted
+        let mut touched = face.to_vec();
+        touched.extend(cache.iter().copied().filter(|index| !face.contains(index)));
+        for (position, &index) in touched.iter().enumerate() {
+            cache_position[index as usize] = (position < CACHE_SIZE).then_some(position);
+        }
+
+        for &index in &touched {
+            scores[index as usize] =
+                vertex_score(cache_position[index as usize], remaining[index as usize]);
+        }
+        for &index in &touched {
+            for &triangle in &triangles_of[index as usize] {
+                if !emitted[triangle] {
+                    triangle_scores[triangle] = corners(triangle)
+                        .iter()
+                        .map(|&index| scores[index as usize])
+                        .sum();
+                }
+            }
+        }
+
+        touched.truncate(CACHE_SIZE);
+        cache = touched;
+    }
+
+    optimized
+}
+
+/// Rewrites an indexed triangle list to draw the same triangles with less work: identical
+/// vertices are merged, triangles ordered so consecutive ones share vertices still in the
+/// post-transform cache, and vertices ordered by first use so fetches mostly run forwards.
+pub fn optimize(vertices: &mut Vec<ModelVertex>, indices: &mut Vec<u32>) {
+    // bit for bit, the first of each set of duplicates stands in for all of them
+    let mut first = HashMap::<&[u8], u32>::new();
+    let welded = vertices
+        .iter()
+        .enumerate()
+        .map(|(index, vertex)| {
+            *first
+                .entry(bytemuck::bytes_of(vertex))
+                .or_insert(index as u32)
+        })
+        .collect::<Vec<_>>();
+    for index in indices.iter_mut() {
+        *index = welded[*index as usize];
+    }
+
+    let ordered = optimize_vertex_cache(indices, vertices.len());
+    // unused duplicates are dropped here
+    (*vertices, *indices) = compact(vertices, &ordered);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::primitives;
+
+    fn vertex(pos: [f32; 3]) -> ModelVertex {
+        ModelVertex {
+            pos,
+            tex_coords: [0.0; 2],
+            normal: [0.0; 3],
+            tangent: [0.0; 4],
+        }
+    }
+
+    // a unit cube sharing its 8 corners between faces, wound counter-clockwise from outside
+    fn cube() -> (Vec<ModelVertex>, Vec<u32>) {
+        let vertices = (0..8)
+            .map(|corner| vertex([0, 1, 2].map(|axis| ((corner >> axis) & 1) as f32 - 0.5)))
+            .collect::<Vec<_>>();
+        let quads = [
+            [1, 3, 7, 5],
+            [0, 4, 6, 2],
+            [2, 6, 7, 3],
+            [0, 1, 5, 4],
+            [4, 5, 7, 6],
+            [0, 2, 3, 1],
+        ];
+
+        let mut indices = Vec::new();
+        for [a, b, c, d] in quads {
+            for mut face in [[a, b, c], [a, c, d]] {
+                let [p0, p1, p2] =
+                    face.map(|index: u32| Vector3::from(vertices[index as usize].pos));
+                if (p1 - p0).cross(p2 - p0).dot(p0 + p1 + p2) < 0.0 {
+                    face.swap(1, 2);
+                }
+                indices.extend(face);
+            }
+        }
+
+        (vertices, indices)
+    }
+
+    fn close(a: Vector3<f32>, b: Vector3<f32>) -> bool {
+        (a - b).magnitude() < 1e-5
+    }
+
+    #[test]
+    fn smooth_normals_point_out_of_the_corners() {
+        let (mut vertices, mut indices) = cube();
+        let origins = generate_normals(&mut vertices, &mut indices, 180.0);
+
+        assert_eq!(vertices.len(), 8);
+        assert_eq!(origins.len(), 8);
+        // area weighted, how the diagonals split the faces tilts them a little
+        for vertex in &vertices {
+            let normal = Vector3::from(vertex.normal);
+            assert!((normal.magnitude() - 1.0).abs() < 1e-5);
+            assert!(normal.dot(Vector3::from(vertex.pos).normalize()) > 0.9);
+        }
+    }
+
+    #[test]
+    fn hard_edges_split_the_corners() {
+        let (original, _) = cube();
+        let (mut vertices, mut indices) = cube();
+        let origins = generate_normals(&mut vertices, &mut indices, 30.0);
+
+        // one vertex per corner and face
+        assert_eq!(vertices.len(), 24);
+        assert_eq!(indices.len(), 36);
+        for (vertex, &origin) in vertices.iter().zip(&origins) {
+            assert_eq!(vertex.pos, original[origin as usize].pos);
+        }
+        for face in indices.chunks_exact(3) {
+            let [a, b, c] =
+                [0, 1, 2].map(|corner| Vector3::from(vertices[face[corner] as usize].pos));
+            let face_normal = (b - a).cross(c - a).normalize();
+            for &index in face {
+                assert!(close(
+                    Vector3::from(vertices[index as usize].normal),
+                    face_normal
+                ));
+            }
+        }
+    }
+
+    #[test]
+    fn seams_share_normals() {
+        // two triangles folded 90 degrees, split along the fold like a UV seam
+        let mut vertices = [
+            [0.0, 0.0, 0.0],
+            [1.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0],
+            [0.0, 0.0, 1.0],
+        ]
+        .map(vertex)
+        .to_vec();
+        let mut indices = vec![0, 2, 1, 3, 5, 4];
+        generate_normals(&mut vertices, &mut indices, 180.0);
+
+        let normal_at = |pos: [f32; 3]| {
+            let normals = vertices
+                .iter()
+                .filter(|vertex| vertex.pos == pos)
+                .map(|vertex| vertex.normal)
+                .collect::<Vec<_>>();
+            assert!(normals.windows(2).all(|pair| pair[0] == pair[1]));
+            Vector3::from(normals[0])
+        };
+        // the two faces face -z and -x, the fold averages them
+        let fold = Vector3::new(-1.0, 0.0, -1.0).normalize();
+        assert!(close(normal_at([0.0, 0.0, 0.0]), fold));
+        assert!(close(normal_at([0.0, 1.0, 0.0]), fold));
+        assert!(close(normal_at([1.0, 0.0, 0.0]), -Vector3::unit_z()));
+    }
+
+    // a unit quad facing +z, u along +x and v along +y unless mirrored
+    fn quad(mirrored_right: bool) -> (Vec<ModelVertex>, Vec<u32>) {
+        let corners = [
+            ([0.0, 0.0], [0.0, 0.0]),
+            ([1.0, 0.0], [1.0, 0.0]),
+            ([1.0, 1.0], [1.0, 1.0]),
+            ([0.0, 1.0], [0.0, 1.0]),
+        ];
+        let vertices = corners
+            .iter()
+            .map(|&([x, y], [u, v])| ModelVertex {
+                tex_coords: [if mirrored_right { 1.0 - u } else { u }, v],
+                normal: [0.0, 0.0, 1.0],
+                ..vertex([x, y, 0.0])
+            })
+            .collect();
+
+        (vertices, vec![0, 1, 2, 0, 2, 3])
+    }
+
+    #[test]
+    fn tangents_follow_u() {
+        let (mut vertices, mut indices) = quad(false);
+        let origins = generate_tangents(&mut vertices, &mut indices);
+
+        assert_eq!(origins, [0, 1, 2, 3]);
+        for vertex in &vertices {
+            assert_eq!(vertex.tangent, [1.0, 0.0, 0.0, 1.0]);
+        }
+
+        let (mut vertices, mut indices) = quad(true);
+        generate_tangents(&mut vertices, &mut indices);
+        for vertex in &vertices {
+            assert_eq!(vertex.tangent, [-1.0, 0.0, 0.0, -1.0]);
+        }
+    }
+
+    #[test]
+    fn tangents_are_orthogonal_to_the_normals() {
+        let (mut vertices, mut indices) = quad(false);
+        for vertex in &mut vertices {
+            vertex.normal = Vector3::new(vertex.pos[0] - 0.5, 0.3, 1.0)
+                .normalize()
+                .into();
+        }
+        generate_tangents(&mut vertices, &mut indices);
+
+        for vertex in &vertices {
+            let tangent = Vector3::new(vertex.tangent[0], vertex.tangent[1], vertex.tangent[2]);
+            assert!((tangent.magnitude() - 1.0).abs() < 1e-5);
+            assert!(tangent.dot(Vector3::from(vertex.normal)).abs() < 1e-5);
+            assert!(tangent.x > 0.0);
+        }
+    }
+
+    #[test]
+    fn mirrored_uvs_split_vertices() {
+        // the right triangle's UVs mirrored, the two share the diagonal
+        let (mut vertices, mut indices) = quad(false);
+        vertices[1].tex_coords = [-1.0, 0.0];
+        let origins = generate_tangents(&mut vertices, &mut indices);
+
+        // the diagonal's corners once per side
+        assert_eq!(vertices.len(), 6);
+        assert_eq!(origins.iter().filter(|&&origin| origin == 0).count(), 2);
+        assert_eq!(origins.iter().filter(|&&origin| origin == 2).count(), 2);
+
+        let (right, left) = indices.split_at(3);
+        for &index in right {
+            assert_eq!(vertices[index as usize].tangent, [-1.0, 0.0, 0.0, -1.0]);
+        }
+        for &index in left {
+            assert_eq!(vertices[index as usize].tangent, [1.0, 0.0, 0.0, 1.0]);
+        }
+    }
+
+    // edges used by a single face, unordered
+    fn border(indices: &[u32]) -> Vec<(u32, u32)> {
+        let mut faces_per_edge = HashMap::<(u32, u32), u32>::new();
+        for face in indices.chunks_exact(3) {
+            for i in 0..3 {
+                let (a, b) = (face[i], face[(i + 1) % 3]);
+                *faces_per_edge.entry((a.min(b), a.max(b))).or_default() += 1;
+            }
+        }
+
+        let mut border = faces_per_edge
+            .into_iter()
+            .filter(|&(_, count)| count == 1)
+            .map(|(edge, _)| edge)
+            .collect::<Vec<_>>();
+        border.sort();
+        border
+    }
+
+    #[test]
+    fn simplify_reaches_the_target_and_keeps_the_border() {
+        let grid = primitives::plane(2.0, 2.0, 8);
+        let simplified = simplify(&grid.vertices, &grid.indices, 0.25);
+
+        // the 32 border vertices alone take 30 triangles, the 128 of the grid can go to 32
+        let triangles = simplified.len() / 3;
+        assert!(triangles <= 32, "{} triangles", triangles);
+        assert!(triangles >= 30);
+        assert_eq!(border(&simplified), border(&grid.indices));
+
+        // a flat grid simplifies without flipping or losing area
+        let area = |indices: &[u32]| {
+            indices
+                .chunks_exact(3)
+                .map(|face| {
+                    let [a, b, c] = [0, 1, 2]
+                        .map(|corner| Vector3::from(grid.vertices[face[corner] as usize].pos));
+                    let normal = (b - a).cross(c - a);
+                    assert!(normal.y >= 0.0);
+                    normal.magnitude() / 2.0
+                })
+                .sum::<f32>()
+        };
+        assert!((area(&simplified) - area(&grid.indices)).abs() < 1e-4);
+    }
+
+    #[test]
+    fn simplify_keeps_everything_at_full_ratio() {
+        let grid = primitives::plane(2.0, 2.0, 4);
+        assert_eq!(simplify(&grid.vertices, &grid.indices, 1.0), grid.indices);
+    }
+
+    // triangles by the bytes of their corners, each starting at its smallest corner so the
+    // winding stays part of it
+    fn triangle_set(vertices: &[ModelVertex], indices: &[u32]) -> Vec<[Vec<u8>; 3]> {
+        let mut triangles = indices
+            .chunks_exact(3)
+            .map(|face| {
+                let mut corners = [0, 1, 2]
+                    .map(|corner| bytemuck::bytes_of(&vertices[face[corner] as usize]).to_vec());
+                let smallest = (0..3)
+                    .min_by_key(|&corner| corners[corner].clone())
+                    .unwrap();
+                corners.rotate_left(smallest);
+                corners
+            })
+            .collect::<Vec<_>>();
+        triangles.sort();
+        triangles
+    }
+
+    #[test]
+    fn optimize_welds_duplicates_and_keeps_the_triangles() {
+        for shape in [
+            primitives::cuboid(1.0, 2.0, 3.0),
+            primitives::uv_sphere(1.0, 16, 12),
+        ] {
+            // every corner its own vertex
+            let mut vertices = shape
+                .indices
+                .iter()
+                .map(|&index| shape.vertices[index as usize])
+                .collect::<Vec<_>>();
+            let mut indices = (0..vertices.len() as u32).collect::<Vec<_>>();
+            let before = triangle_set(&vertices, &indices);
+
+            optimize(&mut vertices, &mut indices);
+
+            assert_eq!(vertices.len(), shape.vertices.len());
+            assert_eq!(triangle_set(&vertices, &indices), before);
+        }
+    }
+
+    #[test]
+    fn optimize_orders_vertices_by_first_use() {
+        let shape = primitives::icosphere(1.0, 2);
+        let (mut vertices, mut indices) = (shape.vertices.clone(), shape.indices.clone());
+        optimize(&mut vertices, &mut indices);
+
+        assert_eq!(
+            triangle_set(&vertices, &indices),
+            triangle_set(&shape.vertices, &shape.indices)
+        );
+        let mut next = 0;
+        for &index in &indices {
+            assert!(index <= next);
+            if index == next {
+                next += 1;
+            }
+        }
+        assert_eq!(next as usize, vertices.len());
+    }
+}
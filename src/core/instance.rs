@@ -0,0 +1,165 @@
+use std::{mem, ops::Range};
+
+use wgpu::util::DeviceExt;
+
+use super::{
+    bounds::{Aabb, Frustum},
+    model::Vertex,
+};
+
+#[derive(Clone)]
+pub struct Instance {
+    pub position: cgmath::Vector3<f32>,
+    pub rotation: cgmath::Quaternion<f32>,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct InstanceRaw {
+    model: [[f32; 4]; 4],
+    // 1.0 tints the instance, e.g. after it was picked
+    highlight: f32,
+}
+
+impl Instance {
+    pub fn model_matrix(&self) -> cgmath::Matrix4<f32> {
+        cgmath::Matrix4::from_translation(self.position) * cgmath::Matrix4::from(self.rotation)
+    }
+
+    pub fn to_raw(&self, highlighted: bool) -> InstanceRaw {
+        InstanceRaw {
+            model: self.model_matrix().into(),
+            highlight: if highlighted { 1.0 } else { 0.0 },
+        }
+    }
+}
+
+impl InstanceRaw {
+    /// An instance placed by an arbitrary transform, e.g. a scene node's world matrix.
+    pub fn from_matrix(model: cgmath::Matrix4<f32>) -> Self {
+        InstanceRaw {
+            model: model.into(),
+            highlight: 0.0,
+        }
+    }
+}
+
+impl Vertex for InstanceRaw {
+    fn desc() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: mem::size_of::<InstanceRaw>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &[
+                wgpu::VertexAttribute {
+                    offset: 0,
+                    shader_location: 5,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 4]>() as wgpu::BufferAddress,
+                    shader_location: 6,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 8]>() as wgpu::BufferAddress,
+                    shader_location: 7,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 12]>() as wgpu::BufferAddress,
+                    shader_location: 8,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 16]>() as wgpu::BufferAddress,
+                    shader_location: 9,
+                    format: wgpu::VertexFormat::Float32,
+                },
+            ],
+        }
+    }
+}
+
+/// Per-instance vertex buffer holding only the instances that passed culling this frame,
+/// grouped by level of detail.
+pub struct InstanceBuffer {
+    buffer: wgpu::Buffer,
+    visible: u32,
+    // instances of each level, back to back
+    levels: Vec<Range<u32>>,
+}
+
+impl InstanceBuffer {
+    pub fn new(device: &wgpu::Device, label: &str, instances: &[Instance]) -> Self {
+        let raw = instances
+            .iter()
+            .map(|instance| instance.to_raw(false))
+            .collect::<Vec<_>>();
+
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(label),
+            contents: bytemuck::cast_slice(&raw),
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        });
+
+        InstanceBuffer {
+            buffer,
+            visible: instances.len() as u32,
+            // all of them at full detail until the first update
+            levels: std::iter::once(0..instances.len() as u32).collect(),
+        }
+    }
+
+    /// Compacts the instances whose world bounds intersect the frustum to the front of the
+    /// buffer, sorted by the level `lod` picks for them. Without a frustum every instance is
+    /// drawn. `highlight` indexes `instances`.
+    pub fn update(
+        &mut self,
+        queue: &wgpu::Queue,
+        instances: &[Instance],
+        bounds: &Aabb,
+        frustum: Option<&Frustum>,
+        highlight: Option<usize>,
+        lod: impl Fn(&Instance) -> usize,
+    ) {
+        let mut visible = instances
+            .iter()
+            .enumerate()
+            .filter(|(_, instance)| match frustum {
+                Some(frustum) => frustum.intersects(&bounds.transform(&instance.model_matrix())),
+                None => true,
+            })
+            .map(|(index, instance)| (lod(instance), instance.to_raw(highlight == Some(index))))
+            .collect::<Vec<_>>();
+        // stable, instances keep their order within a level
+        visible.sort_by_key(|(level, _)| *level);
+
+        let raw = visible.iter().map(|(_, raw)| *raw).collect::<Vec<_>>();
+        if !raw.is_empty() {
+            queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&raw));
+        }
+        self.visible = raw.len() as u32;
+
+        let levels = visible.last().map_or(0, |(level, _)| level + 1);
+        self.levels = (0..levels)
+            .map(|level| {
+                let start = visible.partition_point(|(other, _)| *other < level);
+                let end = visible.partition_point(|(other, _)| *other <= level);
+                start as u32..end as u32
+            })
+            .collect();
+    }
+
+    pub fn slice(&self) -> wgpu::BufferSlice<'_> {
+        self.buffer.slice(..)
+    }
+
+    pub fn visible(&self) -> u32 {
+        self.visible
+    }
+
+    /// The instance range of each level this frame, empty for levels nothing uses.
+    pub fn levels(&self) -> &[Range<u32>] {
+        &self.levels
+    }
+}
@@ -1,2 +1,12 @@
+pub mod animation;
+pub mod bounds;
+pub mod clock;
+pub mod debug;
+pub mod error;
+pub mod geometry;
+pub mod instance;
 pub mod model;
+pub mod picking;
+pub mod primitives;
+pub mod profiler;
 pub mod texture;
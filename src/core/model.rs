@@ -1,6 +1,13 @@
-use wgpu::Buffer;
+use wgpu::{util::DeviceExt, Buffer};
 
-use super::texture;
+use super::{
+    bounds::{Aabb, BoundingSphere},
+    debug::label,
+    error::ResourceError,
+    texture,
+};
+
+const NAME: &str = "Materials";
 
 pub trait Vertex {
     fn desc() -> wgpu::VertexBufferLayout<'static>;
@@ -11,26 +18,505 @@ pub trait Vertex {
 pub struct ModelVertex {
     pub pos: [f32; 3],
     pub tex_coords: [f32; 2],
-    // pub normal: [f32; 3], // TODO: update VertexBufferLayout and shader
+    pub normal: [f32; 3],
+    // xyz along +u, w the handedness of the bitangent
+    pub tangent: [f32; 4],
+}
+
+/// The joints moving a vertex of a skinned mesh, in a second vertex buffer next to its
+/// `ModelVertex`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct SkinnedVertex {
+    // indices into the skin's joints
+    pub joints: [u32; 4],
+    // summing to 1
+    pub weights: [f32; 4],
 }
 
 pub struct Mesh {
     pub name: String,
     pub vertex_buffer: Buffer,
     pub index_buffer: Buffer,
+    pub index_format: wgpu::IndexFormat,
     pub num_elements: u32,
+    // model space, the box for culling and picking, the sphere for framing and LOD selection
+    pub bounds: Aabb,
+    pub sphere: BoundingSphere,
+    // CPU copy of the geometry for picking, indices stay 32-bit whatever the buffer holds
+    pub positions: Vec<[f32; 3]>,
+    pub indices: Vec<u32>,
+    // index into the model's materials, `NO_MATERIAL` until `Model::new` resolves it
     pub materials: usize,
+    // `SkinnedVertex`es, only for meshes a skin deforms
+    pub skin_buffer: Option<Buffer>,
+}
+
+/// The material index of a mesh that wasn't assigned one.
+pub const NO_MATERIAL: usize = usize::MAX;
+
+impl Mesh {
+    /// Uploads the vertices and indices and works out the bounds. With `Uint16` the indices
+    /// are narrowed on upload, see `narrowest_index_format`.
+    pub fn new(
+        device: &wgpu::Device,
+        name: &str,
+        vertices: &[ModelVertex],
+        indices: Vec<u32>,
+        index_format: wgpu::IndexFormat,
+        materials: usize,
+    ) -> Self {
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&label(name, "vertex buffer")),
+            contents: bytemuck::cast_slice(vertices),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+
+        let narrowed;
+        let contents = match index_format {
+            wgpu::IndexFormat::Uint16 => {
+                narrowed = indices
+                    .iter()
+                    .map(|&index| index as u16)
+                    .collect::<Vec<_>>();
+                bytemuck::cast_slice(&narrowed)
+            }
+            wgpu::IndexFormat::Uint32 => bytemuck::cast_slice(&indices),
+        };
+
+        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&label(name, "index buffer")),
+            contents,
+            usage: wgpu::BufferUsages::INDEX,
+        });
+
+        Mesh {
+            name: name.to_string(),
+            vertex_buffer,
+            index_buffer,
+            index_format,
+            num_elements: indices.len() as u32,
+            bounds: Aabb::from_points(vertices.iter().map(|vertex| vertex.pos)),
+            sphere: BoundingSphere::from_points(vertices.iter().map(|vertex| vertex.pos)),
+            positions: vertices.iter().map(|vertex| vertex.pos).collect(),
+            indices,
+            materials,
+            skin_buffer: None,
+        }
+    }
+
+    /// Uploads joints and weights, one per vertex, so the mesh can be drawn skinned.
+    pub fn with_skin(mut self, device: &wgpu::Device, skin: &[SkinnedVertex]) -> Self {
+        self.skin_buffer = Some(
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some(&label(&self.name, "skin buffer")),
+                contents: bytemuck::cast_slice(skin),
+                usage: wgpu::BufferUsages::VERTEX,
+            }),
+        );
+        self
+    }
+}
+
+/// 16-bit indices when they can address every vertex, 32-bit otherwise.
+pub fn narrowest_index_format(vertex_count: usize) -> wgpu::IndexFormat {
+    // 0xffff is left out, strip topologies would read it as a restart
+    if vertex_count <= u16::MAX as usize {
+        wgpu::IndexFormat::Uint16
+    } else {
+        wgpu::IndexFormat::Uint32
+    }
+}
+
+/// When a level of detail takes over from the finer one before it.
+#[derive(Clone, Copy, Debug)]
+pub enum LodThreshold {
+    // the bounding sphere covers less than this fraction of the viewport height
+    ScreenSize(f32),
+    // the bounding sphere's center is further than this from the eye, in world units
+    Distance(f32),
+}
+
+/// A coarser stand-in for a model's meshes, drawn with the model's materials.
+pub struct Lod {
+    pub meshes: Vec<Mesh>,
+    pub threshold: LodThreshold,
+}
+
+/// How a material reacts to light. Blinn-Phong covers plain MTL files, metallic-roughness is
+/// the PBR model of glTF and the MTL PBR extension.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Shading {
+    BlinnPhong,
+    MetallicRoughness,
+}
+
+/// Material factors. Each one multiplies the matching texture, missing textures count as
+/// white, so a material without textures is described by its factors alone.
+#[derive(Clone, Copy, Debug)]
+pub struct MaterialParams {
+    pub shading: Shading,
+    // linear RGBA, alpha is the opacity (Kd and d in MTL)
+    pub base_color: [f32; 4],
+    // Ke
+    pub emissive: [f32; 3],
+    // metallic-roughness only
+    pub metallic: f32,
+    pub roughness: f32,
+    // how much of the occlusion texture applies, 0 ignores it
+    pub occlusion_strength: f32,
+    // Blinn-Phong only: Ka, Ks and the specular exponent Ns
+    pub ambient: [f32; 3],
+    pub specular: [f32; 3],
+    pub shininess: f32,
+}
+
+impl Default for MaterialParams {
+    fn default() -> Self {
+        MaterialParams {
+            shading: Shading::BlinnPhong,
+            base_color: [1.0; 4],
+            emissive: [0.0; 3],
+            metallic: 0.0,
+            roughness: 1.0,
+            occlusion_strength: 1.0,
+            ambient: [1.0; 3],
+            specular: [0.5; 3],
+            shininess: 32.0,
+        }
+    }
+}
+
+impl MaterialParams {
+    pub fn to_raw(self) -> MaterialRaw {
+        MaterialRaw {
+            base_color: self.base_color,
+            emissive: self.emissive,
+            shading: match self.shading {
+                Shading::BlinnPhong => 0,
+                Shading::MetallicRoughness => 1,
+            },
+            ambient: self.ambient,
+            shininess: self.shininess,
+            specular: self.specular,
+            metallic: self.metallic,
+            roughness: self.roughness,
+            occlusion_strength: self.occlusion_strength,
+            _padding: [0; 2],
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct MaterialRaw {
+    base_color: [f32; 4],
+    emissive: [f32; 3],
+    shading: u32,
+    ambient: [f32; 3],
+    shininess: f32,
+    specular: [f32; 3],
+    metallic: f32,
+    roughness: f32,
+    occlusion_strength: f32,
+    _padding: [u32; 2],
+}
+
+/// Optional material textures. Base color and emissive are sRGB, the rest linear; the
+/// metallic-roughness texture keeps roughness in green and metallic in blue, like glTF.
+#[derive(Default)]
+pub struct MaterialTextures {
+    pub base_color: Option<texture::Texture>,
+    pub normal: Option<texture::Texture>,
+    pub metallic_roughness: Option<texture::Texture>,
+    pub occlusion: Option<texture::Texture>,
+    pub emissive: Option<texture::Texture>,
+}
+
+/// The bind group layout shared by all materials, so any mesh can be drawn with any
+/// material, and the 1x1 textures bound in place of missing ones.
+pub struct MaterialLayout {
+    pub layout: wgpu::BindGroupLayout,
+    white: texture::Texture,
+    flat_normal: texture::Texture,
+}
+
+impl MaterialLayout {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> anyhow::Result<Self> {
+        let texture = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            },
+            count: None,
+        };
+        let sampler = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+            count: None,
+        };
+
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                texture(0),
+                sampler(1),
+                texture(2),
+                sampler(3),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                texture(5),
+                sampler(6),
+                texture(7),
+                sampler(8),
+                texture(9),
+                sampler(10),
+            ],
+            label: Some(&label(NAME, "material bind group layout")),
+        });
+
+        // linear white reads as 1.0 in sRGB too
+        let white = texture::Texture::from_color(
+            device,
+            queue,
+            [255, 255, 255, 255],
+            texture::ColorSpace::Linear,
+            &label(NAME, "white"),
+        )?;
+        let flat_normal = texture::Texture::from_color(
+            device,
+            queue,
+            [128, 128, 255, 255],
+            texture::ColorSpace::Linear,
+            &label(NAME, "flat normal"),
+        )?;
+
+        Ok(MaterialLayout {
+            layout,
+            white,
+            flat_normal,
+        })
+    }
 }
 
 pub struct Material {
-    pub name: String,
-    pub diffuse_texture: texture::Texture,
+    pub textures: MaterialTextures,
+    // holds on to the params buffer, nothing changes a material after loading
     pub bind_group: wgpu::BindGroup,
+    // drawn without back face culling, off unless the loader says otherwise
+    pub double_sided: bool,
+    // blended over what's behind it by the alpha of its base color, like MTL's `d` asks for
+    pub translucent: bool,
+}
+
+impl Material {
+    pub fn new(
+        device: &wgpu::Device,
+        layout: &MaterialLayout,
+        name: &str,
+        params: MaterialParams,
+        textures: MaterialTextures,
+    ) -> Self {
+        let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&label(name, "material buffer")),
+            contents: bytemuck::bytes_of(&params.to_raw()),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let base_color = textures.base_color.as_ref().unwrap_or(&layout.white);
+        let normal = textures.normal.as_ref().unwrap_or(&layout.flat_normal);
+        let metallic_roughness = textures
+            .metallic_roughness
+            .as_ref()
+            .unwrap_or(&layout.white);
+        let occlusion = textures.occlusion.as_ref().unwrap_or(&layout.white);
+        let emissive = textures.emissive.as_ref().unwrap_or(&layout.white);
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &layout.layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&base_color.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&base_color.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&normal.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::Sampler(&normal.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: params_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: wgpu::BindingResource::TextureView(&metallic_roughness.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 6,
+                    resource: wgpu::BindingResource::Sampler(&metallic_roughness.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 7,
+                    resource: wgpu::BindingResource::TextureView(&occlusion.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 8,
+                    resource: wgpu::BindingResource::Sampler(&occlusion.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 9,
+                    resource: wgpu::BindingResource::TextureView(&emissive.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 10,
+                    resource: wgpu::BindingResource::Sampler(&emissive.sampler),
+                },
+            ],
+            label: Some(&label(name, "material bind group")),
+        });
+
+        Material {
+            textures,
+            bind_group,
+            double_sided: false,
+            translucent: false,
+        }
+    }
 }
 
 pub struct Model {
     pub meshes: Vec<Mesh>,
     pub materials: Vec<Material>,
+    // model space, around every mesh
+    pub bounds: Aabb,
+    pub sphere: BoundingSphere,
+    // coarser levels after `meshes`, in the order they take over
+    pub lods: Vec<Lod>,
+}
+
+impl Model {
+    /// Checks every mesh's material index. Meshes with `NO_MATERIAL` or an index past the
+    /// end are pointed at a default checkerboard material, appended once if any mesh needs
+    /// it, so renderers can bind `materials[mesh.materials]` without checking. The model's
+    /// bounds are the union of its meshes', a model without meshes is a point at the origin.
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        layout: &MaterialLayout,
+        name: &str,
+        mut meshes: Vec<Mesh>,
+        mut materials: Vec<Material>,
+    ) -> Result<Self, ResourceError> {
+        let mut default_material = None;
+
+        for mesh in &mut meshes {
+            if mesh.materials < materials.len() {
+                continue;
+            }
+            if mesh.materials != NO_MATERIAL {
+                log::warn!(
+                    "{}: material {} out of range, {} loaded",
+                    mesh.name,
+                    mesh.materials,
+                    materials.len()
+                );
+            }
+
+            mesh.materials = match default_material {
+                Some(index) => index,
+                None => {
+                    let scope = label(name, "default material");
+                    let checkerboard = texture::Texture::checkerboard(device, queue, &scope)?;
+                    materials.push(Material::new(
+                        device,
+                        layout,
+                        &scope,
+                        MaterialParams::default(),
+                        MaterialTextures {
+                            base_color: Some(checkerboard),
+                            ..Default::default()
+                        },
+                    ));
+                    *default_material.insert(materials.len() - 1)
+                }
+            };
+        }
+
+        let bounds = meshes
+            .iter()
+            .map(|mesh| mesh.bounds)
+            .reduce(Aabb::union)
+            .unwrap_or_else(|| Aabb::from_points([]));
+        let sphere = meshes
+            .iter()
+            .map(|mesh| mesh.sphere)
+            .reduce(BoundingSphere::union)
+            .unwrap_or_else(|| BoundingSphere::from_points([]));
+
+        Ok(Model {
+            meshes,
+            materials,
+            bounds,
+            sphere,
+            lods: Vec::new(),
+        })
+    }
+
+    pub fn material(&self, mesh: &Mesh) -> &Material {
+        &self.materials[mesh.materials]
+    }
+
+    /// Adds a coarser level loaded as a model of its own, e.g. from a separate file. Its
+    /// materials are appended to this model's, so the files don't have to share them.
+    pub fn push_lod(&mut self, lod: Model, threshold: LodThreshold) {
+        let offset = self.materials.len();
+        let mut meshes = lod.meshes;
+        for mesh in &mut meshes {
+            mesh.materials += offset;
+        }
+
+        self.materials.extend(lod.materials);
+        self.lods.push(Lod { meshes, threshold });
+    }
+
+    /// The level to draw for an instance whose bounding sphere is `distance` from the eye
+    /// and covers `screen_size` of the viewport height, 0 being `meshes`.
+    pub fn lod_level(&self, distance: f32, screen_size: f32) -> usize {
+        self.lods
+            .iter()
+            .take_while(|lod| match lod.threshold {
+                LodThreshold::ScreenSize(size) => screen_size < size,
+                LodThreshold::Distance(min_distance) => distance > min_distance,
+            })
+            .count()
+    }
+
+    /// The meshes of a level, levels past the coarsest one draw the coarsest.
+    pub fn lod_meshes(&self, level: usize) -> &[Mesh] {
+        match level.min(self.lods.len()) {
+            0 => &self.meshes,
+            level => &self.lods[level - 1].meshes,
+        }
+    }
 }
 
 impl Vertex for ModelVertex {
@@ -50,6 +536,38 @@ impl Vertex for ModelVertex {
                     offset: mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
                     shader_location: 1,
                 },
+                wgpu::VertexAttribute {
+                    format: wgpu::VertexFormat::Float32x3,
+                    offset: mem::size_of::<[f32; 5]>() as wgpu::BufferAddress,
+                    shader_location: 2,
+                },
+                wgpu::VertexAttribute {
+                    format: wgpu::VertexFormat::Float32x4,
+                    offset: mem::size_of::<[f32; 8]>() as wgpu::BufferAddress,
+                    shader_location: 3,
+                },
+            ],
+        }
+    }
+}
+
+impl Vertex for SkinnedVertex {
+    fn desc() -> wgpu::VertexBufferLayout<'static> {
+        use std::mem;
+        wgpu::VertexBufferLayout {
+            array_stride: mem::size_of::<SkinnedVertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &[
+                wgpu::VertexAttribute {
+                    format: wgpu::VertexFormat::Uint32x4,
+                    offset: 0,
+                    shader_location: 10,
+                },
+                wgpu::VertexAttribute {
+                    format: wgpu::VertexFormat::Float32x4,
+                    offset: mem::size_of::<[u32; 4]>() as wgpu::BufferAddress,
+                    shader_location: 11,
+                },
             ],
         }
     }
@@ -0,0 +1,528 @@
+use cgmath::{InnerSpace, Matrix4, Point3, SquareMatrix, Transform, Vector3};
+
+use super::{
+    bounds::{Aabb, BoundingSphere},
+    instance::Instance,
+    model::Mesh,
+};
+
+#[derive(Clone, Copy, Debug)]
+pub struct Ray {
+    pub origin: Point3<f32>,
+    // any length, distances are measured in multiples of it; `Camera::ray` casts unit
+    // directions, so its distances are in world units
+    pub direction: Vector3<f32>,
+}
+
+impl Ray {
+    pub fn at(&self, distance: f32) -> Point3<f32> {
+        self.origin + self.direction * distance
+    }
+
+    /// The same ray in the space `matrix` maps to. Distances stay comparable because the
+    /// direction isn't renormalized.
+    pub fn transform(&self, matrix: &Matrix4<f32>) -> Ray {
+        Ray {
+            origin: matrix.transform_point(self.origin),
+            direction: matrix.transform_vector(self.direction),
+        }
+    }
+
+    /// Slab test, returns the distance to the box or 0 if the origin is inside it.
+    pub fn intersect_aabb(&self, aabb: &Aabb) -> Option<f32> {
+        let mut near = 0.0_f32;
+        let mut far = f32::INFINITY;
+
+        for axis in 0..3 {
+            let inverse = 1.0 / self.direction[axis];
+            let mut t0 = (aabb.min[axis] - self.origin[axis]) * inverse;
+            let mut t1 = (aabb.max[axis] - self.origin[axis]) * inverse;
+            if t0 > t1 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+
+            // NaN from a zero direction component on the slab boundary keeps the old value
+            near = near.max(t0);
+            far = far.min(t1);
+            if near > far {
+                return None;
+            }
+        }
+
+        Some(near)
+    }
+
+    /// Möller–Trumbore, both faces count as hits.
+    pub fn intersect_triangle(
+        &self,
+        a: Point3<f32>,
+        b: Point3<f32>,
+        c: Point3<f32>,
+    ) -> Option<f32> {
+        let edge1 = b - a;
+        let edge2 = c - a;
+        let p = self.direction.cross(edge2);
+        let determinant = edge1.dot(p);
+        if determinant.abs() < f32::EPSILON {
+            return None;
+        }
+
+        let inverse = 1.0 / determinant;
+        let s = self.origin - a;
+        let u = s.dot(p) * inverse;
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+
+        let q = s.cross(edge1);
+        let v = self.direction.dot(q) * inverse;
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+
+        let distance = edge2.dot(q) * inverse;
+        (distance >= 0.0).then_some(distance)
+    }
+
+    /// Nearest of an indexed triangle list's triangles, skipped whole if the ray misses
+    /// `bounds`.
+    pub fn intersect_mesh(
+        &self,
+        bounds: &Aabb,
+        positions: &[[f32; 3]],
+        indices: &[u32],
+    ) -> Option<f32> {
+        self.intersect_aabb(bounds)?;
+
+        let vertex = |index: u32| Point3::from(positions[index as usize]);
+        indices
+            .chunks_exact(3)
+            .filter_map(|triangle| {
+                self.intersect_triangle(
+                    vertex(triangle[0]),
+                    vertex(triangle[1]),
+                    vertex(triangle[2]),
+                )
+            })
+            .min_by(f32::total_cmp)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Hit {
+    pub instance: usize,
+    pub distance: f32,
+}
+
+/// Nearest instance whose world bounds and then triangles the ray hits.
+pub fn pick_instances(
+    ray: &Ray,
+    instances: &[Instance],
+    bounds: &Aabb,
+    meshes: &[Mesh],
+) -> Option<Hit> {
+    pick_nearest(ray, instances, bounds, |local| {
+        meshes
+            .iter()
+            .filter_map(|mesh| local.intersect_mesh(&mesh.bounds, &mesh.positions, &mesh.indices))
+            .min_by(f32::total_cmp)
+    })
+}
+
+// the nearest instance `intersect` hits given the ray in model space; instances whose world
+// bounds the ray misses, or only hits beyond the nearest hit so far, are never intersected
+fn pick_nearest(
+    ray: &Ray,
+    instances: &[Instance],
+    bounds: &Aabb,
+    intersect: impl Fn(&Ray) -> Option<f32>,
+) -> Option<Hit> {
+    let mut nearest: Option<Hit> = None;
+
+    for (index, instance) in instances.iter().enumerate() {
+        let model_matrix = instance.model_matrix();
+
+        match ray.intersect_aabb(&bounds.transform(&model_matrix)) {
+            Some(distance) if nearest.is_none_or(|hit| distance < hit.distance) => {}
+            _ => continue,
+        }
+
+        let Some(inverse) = model_matrix.invert() else {
+            continue;
+        };
+        let Some(distance) = intersect(&ray.transform(&inverse)) else {
+            continue;
+        };
+
+        if nearest.is_none_or(|hit| distance < hit.distance) {
+            nearest = Some(Hit {
+                instance: index,
+                distance,
+            });
+        }
+    }
+
+    nearest
+}
+
+pub trait Pickable {
+    fn name(&self) -> &'static str;
+    fn pick(&self, ray: &Ray) -> Option<Hit>;
+    fn set_highlight(&mut self, instance: Option<usize>);
+    /// World space bounds of one instance, None if there's no such instance.
+    fn bounding_sphere(&self, instance: usize) -> Option<BoundingSphere>;
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct PickEvent {
+    pub renderable: &'static str,
+    pub instance: usize,
+    pub distance: f32,
+    pub point: Point3<f32>,
+}
+
+type PickListener = Box<dyn FnMut(&PickEvent)>;
+
+/// Casts rays against a set of pickables, highlights the nearest hit and notifies the
+/// registered listeners about it.
+pub struct Picker {
+    listeners: Vec<PickListener>,
+}
+
+impl Picker {
+    pub fn new() -> Self {
+        Picker {
+            listeners: Vec::new(),
+        }
+    }
+
+    pub fn on_pick(&mut self, listener: impl FnMut(&PickEvent) + 'static) {
+        self.listeners.push(Box::new(listener));
+    }
+
+    /// A miss clears every highlight without notifying the listeners.
+    pub fn pick(&mut self, ray: &Ray, targets: &mut [&mut dyn Pickable]) -> Option<PickEvent> {
+        let nearest = targets
+            .iter()
+            .enumerate()
+            .filter_map(|(target, pickable)| pickable.pick(ray).map(|hit| (target, hit)))
+            .min_by(|(_, a), (_, b)| a.distance.total_cmp(&b.distance));
+
+        for (target, pickable) in targets.iter_mut().enumerate() {
+            let highlight = nearest
+                .filter(|(nearest, _)| *nearest == target)
+                .map(|(_, hit)| hit.instance);
+            pickable.set_highlight(highlight);
+        }
+
+        let (target, hit) = nearest?;
+        let event = PickEvent {
+            renderable: targets[target].name(),
+            instance: hit.instance,
+            distance: hit.distance,
+            point: ray.at(hit.distance),
+        };
+
+        for listener in &mut self.listeners {
+            listener(&event);
+        }
+
+        Some(event)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        cell::{Cell, RefCell},
+        rc::Rc,
+    };
+
+    use cgmath::{Deg, One, Quaternion, Rotation3};
+
+    use super::*;
+
+    fn ray(origin: [f32; 3], direction: [f32; 3]) -> Ray {
+        Ray {
+            origin: Point3::from(origin),
+            direction: Vector3::from(direction),
+        }
+    }
+
+    // in the z = 0 plane, counter-clockwise seen from +Z
+    fn triangle() -> [Point3<f32>; 3] {
+        [
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(1.0, 0.0, 0.0),
+            Point3::new(0.0, 1.0, 0.0),
+        ]
+    }
+
+    fn unit_box() -> Aabb {
+        Aabb {
+            min: Point3::new(-1.0, -1.0, -1.0),
+            max: Point3::new(1.0, 1.0, 1.0),
+        }
+    }
+
+    #[test]
+    fn triangle_hits_from_both_sides() {
+        let [a, b, c] = triangle();
+
+        let front = ray([0.25, 0.25, 2.0], [0.0, 0.0, -1.0]).intersect_triangle(a, b, c);
+        assert_eq!(front, Some(2.0));
+
+        let back = ray([0.25, 0.25, -3.0], [0.0, 0.0, 1.0]).intersect_triangle(a, b, c);
+        assert_eq!(back, Some(3.0));
+    }
+
+    #[test]
+    fn triangle_distance_is_in_direction_lengths() {
+        let [a, b, c] = triangle();
+        let hit = ray([0.25, 0.25, 2.0], [0.0, 0.0, -4.0]).intersect_triangle(a, b, c);
+        assert_eq!(hit, Some(0.5));
+    }
+
+    #[test]
+    fn triangle_misses() {
+        let [a, b, c] = triangle();
+
+        // outside the edges
+        assert!(ray([0.75, 0.75, 1.0], [0.0, 0.0, -1.0])
+            .intersect_triangle(a, b, c)
+            .is_none());
+        assert!(ray([-0.1, 0.5, 1.0], [0.0, 0.0, -1.0])
+            .intersect_triangle(a, b, c)
+            .is_none());
+        // pointing away
+        assert!(ray([0.25, 0.25, 1.0], [0.0, 0.0, 1.0])
+            .intersect_triangle(a, b, c)
+            .is_none());
+        // parallel to the plane
+        assert!(ray([0.25, 0.25, 0.0], [1.0, 0.0, 0.0])
+            .intersect_triangle(a, b, c)
+            .is_none());
+    }
+
+    #[test]
+    fn aabb_hits_at_the_near_face() {
+        let hit = ray([0.0, 0.0, 5.0], [0.0, 0.0, -1.0]).intersect_aabb(&unit_box());
+        assert_eq!(hit, Some(4.0));
+
+        let diagonal = ray([3.0, 3.0, 0.0], [-1.0, -1.0, 0.0]).intersect_aabb(&unit_box());
+        assert_eq!(diagonal, Some(2.0));
+    }
+
+    #[test]
+    fn aabb_hits_from_inside_at_zero() {
+        let hit = ray([0.5, 0.0, 0.0], [1.0, 0.0, 0.0]).intersect_aabb(&unit_box());
+        assert_eq!(hit, Some(0.0));
+    }
+
+    #[test]
+    fn aabb_misses() {
+        // beside the box, parallel to its faces
+        assert!(ray([0.0, 2.0, 5.0], [0.0, 0.0, -1.0])
+            .intersect_aabb(&unit_box())
+            .is_none());
+        // pointing away
+        assert!(ray([0.0, 0.0, 5.0], [0.0, 0.0, 1.0])
+            .intersect_aabb(&unit_box())
+            .is_none());
+        // past a corner
+        assert!(ray([3.0, 0.0, 0.0], [-1.0, 1.0, 0.0])
+            .intersect_aabb(&unit_box())
+            .is_none());
+    }
+
+    #[test]
+    fn transformed_ray_keeps_distances() {
+        let matrix =
+            Matrix4::from_translation(Vector3::new(10.0, 0.0, 0.0)) * Matrix4::from_scale(2.0);
+        let world = ray([10.0, 0.0, 10.0], [0.0, 0.0, -1.0]);
+        let local = world.transform(&matrix.invert().unwrap());
+
+        // the world box spans z -2..2, so it's 8 away
+        assert_eq!(local.intersect_aabb(&unit_box()), Some(8.0));
+    }
+
+    // the unit box as a triangle mesh, its corners numbered by their bits (x 1, y 2, z 4)
+    fn box_mesh() -> (Vec<[f32; 3]>, Vec<u32>) {
+        let positions = (0..8)
+            .map(|corner| [0, 1, 2].map(|axis| if corner & (1 << axis) == 0 { -1.0 } else { 1.0 }))
+            .collect();
+        let indices = vec![
+            0, 2, 1, 1, 2, 3, // -z
+            4, 5, 6, 5, 7, 6, // +z
+            0, 1, 4, 1, 5, 4, // -y
+            2, 6, 3, 3, 6, 7, // +y
+            0, 4, 2, 2, 4, 6, // -x
+            1, 3, 5, 3, 7, 5, // +x
+        ];
+        (positions, indices)
+    }
+
+    fn instance(position: [f32; 3]) -> Instance {
+        Instance {
+            position: Vector3::from(position),
+            rotation: Quaternion::one(),
+        }
+    }
+
+    fn pick_boxes(ray: &Ray, instances: &[Instance]) -> Option<Hit> {
+        let (positions, indices) = box_mesh();
+        pick_nearest(ray, instances, &unit_box(), |local| {
+            local.intersect_mesh(&unit_box(), &positions, &indices)
+        })
+    }
+
+    #[test]
+    fn nearest_instance_wins() {
+        let instances = [
+            instance([0.0, 0.0, -10.0]),
+            instance([0.0, 0.0, -4.0]),
+            instance([5.0, 0.0, -2.0]),
+        ];
+        let hit = pick_boxes(&ray([0.0, 0.0, 0.0], [0.0, 0.0, -1.0]), &instances).unwrap();
+
+        // the one at -4 is nearer than the one at -10, the one at x 5 is beside the ray
+        assert_eq!(hit.instance, 1);
+        assert_eq!(hit.distance, 3.0);
+
+        assert!(pick_boxes(&ray([0.0, 0.0, 0.0], [0.0, 0.0, 1.0]), &instances).is_none());
+    }
+
+    #[test]
+    fn culled_instances_are_never_intersected() {
+        let instances = [
+            instance([0.0, 0.0, -4.0]),
+            // beside the ray
+            instance([5.0, 0.0, -2.0]),
+            // behind the first hit
+            instance([0.0, 0.0, -10.0]),
+        ];
+        let intersected = Cell::new(0);
+        let (positions, indices) = box_mesh();
+
+        let hit = pick_nearest(
+            &ray([0.0, 0.0, 0.0], [0.0, 0.0, -1.0]),
+            &instances,
+            &unit_box(),
+            |local| {
+                intersected.set(intersected.get() + 1);
+                local.intersect_mesh(&unit_box(), &positions, &indices)
+            },
+        );
+
+        assert_eq!(hit.map(|hit| hit.instance), Some(0));
+        assert_eq!(intersected.get(), 1);
+    }
+
+    #[test]
+    fn transformed_instances_hit_in_world_distances() {
+        // a unit triangle turned to face +X and moved 5 along it
+        let positions = triangle().map(|point| point.into()).to_vec();
+        let indices = vec![0, 1, 2];
+        let bounds = Aabb {
+            min: Point3::new(0.0, 0.0, 0.0),
+            max: Point3::new(1.0, 1.0, 0.0),
+        };
+        let instances = [Instance {
+            position: Vector3::new(5.0, 0.0, 0.0),
+            rotation: Quaternion::from_angle_y(Deg(90.0)),
+        }];
+        let pick = |ray: &Ray| {
+            pick_nearest(ray, &instances, &bounds, |local| {
+                local.intersect_mesh(&bounds, &positions, &indices)
+            })
+        };
+
+        // the triangle's (0.25, 0.25) lands at (5, 0.25, -0.25)
+        let hit = pick(&ray([0.0, 0.25, -0.25], [1.0, 0.0, 0.0])).unwrap();
+        assert_eq!(hit.instance, 0);
+        assert!((hit.distance - 5.0).abs() < 1e-5);
+
+        // where the untransformed triangle would be
+        assert!(pick(&ray([0.25, 0.25, 2.0], [0.0, 0.0, -1.0])).is_none());
+    }
+
+    struct Target {
+        name: &'static str,
+        hit: Option<Hit>,
+        highlight: Option<usize>,
+    }
+
+    impl Target {
+        fn new(name: &'static str, hit: Option<(usize, f32)>) -> Self {
+            Target {
+                name,
+                hit: hit.map(|(instance, distance)| Hit { instance, distance }),
+                // stale, every pick should overwrite it
+                highlight: Some(7),
+            }
+        }
+    }
+
+    impl Pickable for Target {
+        fn name(&self) -> &'static str {
+            self.name
+        }
+
+        fn pick(&self, _ray: &Ray) -> Option<Hit> {
+            self.hit
+        }
+
+        fn set_highlight(&mut self, instance: Option<usize>) {
+            self.highlight = instance;
+        }
+
+        fn bounding_sphere(&self, _instance: usize) -> Option<BoundingSphere> {
+            None
+        }
+    }
+
+    #[test]
+    fn listeners_hear_about_the_nearest_hit() {
+        let heard = Rc::new(RefCell::new(Vec::new()));
+        let mut picker = Picker::new();
+        for listener in 0..2 {
+            let heard = heard.clone();
+            picker.on_pick(move |event: &PickEvent| {
+                heard
+                    .borrow_mut()
+                    .push((listener, event.renderable, event.instance))
+            });
+        }
+
+        let mut far = Target::new("far", Some((0, 6.0)));
+        let mut near = Target::new("near", Some((3, 2.0)));
+        let event = picker
+            .pick(
+                &ray([0.0, 0.0, 0.0], [0.0, 0.0, -1.0]),
+                &mut [&mut far, &mut near],
+            )
+            .unwrap();
+
+        assert_eq!((event.renderable, event.instance), ("near", 3));
+        assert_eq!(event.point, Point3::new(0.0, 0.0, -2.0));
+        assert_eq!(*heard.borrow(), [(0, "near", 3), (1, "near", 3)]);
+        assert_eq!((far.highlight, near.highlight), (None, Some(3)));
+    }
+
+    #[test]
+    fn misses_clear_highlights_silently() {
+        let heard = Rc::new(Cell::new(0));
+        let mut picker = Picker::new();
+        {
+            let heard = heard.clone();
+            picker.on_pick(move |_| heard.set(heard.get() + 1));
+        }
+
+        let mut target = Target::new("target", None);
+        let event = picker.pick(&ray([0.0, 0.0, 0.0], [0.0, 0.0, -1.0]), &mut [&mut target]);
+
+        assert!(event.is_none());
+        assert_eq!(heard.get(), 0);
+        assert_eq!(target.highlight, None);
+    }
+}
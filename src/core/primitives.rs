@@ -0,0 +1,633 @@
+// a toolbox for prototyping and debug drawing, shapes nothing draws yet are allowed unused
+
+use std::{collections::HashMap, f32::consts::PI};
+
+use cgmath::{InnerSpace, Vector3};
+
+use super::{
+    geometry::generate_tangents,
+    model::{narrowest_index_format, Mesh, ModelVertex},
+};
+
+/// Vertices and indices of a generated shape, centered on the origin. Front faces wind
+/// counter-clockwise and texture coordinates have v pointing down the image, like glTF.
+pub struct MeshData {
+    pub vertices: Vec<ModelVertex>,
+    pub indices: Vec<u32>,
+}
+
+impl MeshData {
+    // fills in the tangents, every generator sets positions, normals and UVs itself
+    fn new(mut vertices: Vec<ModelVertex>, mut indices: Vec<u32>) -> Self {
+        generate_tangents(&mut vertices, &mut indices);
+        // v points down, so the bitangents go the other way
+        for vertex in &mut vertices {
+            vertex.tangent[3] = -vertex.tangent[3];
+        }
+
+        MeshData { vertices, indices }
+    }
+
+    /// Uploads the data as a mesh drawn with the model's material `materials`.
+    pub fn into_mesh(self, device: &wgpu::Device, name: &str, materials: usize) -> Mesh {
+        Mesh::new(
+            device,
+            name,
+            &self.vertices,
+            self.indices,
+            narrowest_index_format(self.vertices.len()),
+            materials,
+        )
+    }
+}
+
+fn vertex(pos: Vector3<f32>, tex_coords: [f32; 2], normal: Vector3<f32>) -> ModelVertex {
+    ModelVertex {
+        pos: pos.into(),
+        tex_coords,
+        normal: normal.into(),
+        tangent: [0.0; 4],
+    }
+}
+
+// the two triangles of a grid cell, corners given top left, bottom left, bottom right and
+// top right as seen from the front
+fn quad_indices(top_left: u32, bottom_left: u32, bottom_right: u32, top_right: u32) -> [u32; 6] {
+    [
+        top_left,
+        bottom_left,
+        top_right,
+        bottom_left,
+        bottom_right,
+        top_right,
+    ]
+}
+
+// a rectangle facing `right × up`, both half extents
+fn push_face(
+    vertices: &mut Vec<ModelVertex>,
+    indices: &mut Vec<u32>,
+    center: Vector3<f32>,
+    right: Vector3<f32>,
+    up: Vector3<f32>,
+) {
+    let normal = right.cross(up).normalize();
+    let first = vertices.len() as u32;
+
+    vertices.extend([
+        vertex(center - right + up, [0.0, 0.0], normal),
+        vertex(center - right - up, [0.0, 1.0], normal),
+        vertex(center + right - up, [1.0, 1.0], normal),
+        vertex(center + right + up, [1.0, 0.0], normal),
+    ]);
+    indices.extend(quad_indices(first, first + 1, first + 2, first + 3));
+}
+
+// a disc in the XZ plane at height `y`, facing up or down
+fn push_disc(
+    vertices: &mut Vec<ModelVertex>,
+    indices: &mut Vec<u32>,
+    radius: f32,
+    y: f32,
+    sectors: u32,
+    facing_up: bool,
+) {
+    let normal = if facing_up {
+        Vector3::unit_y()
+    } else {
+        -Vector3::unit_y()
+    };
+    // seen from the front the image's top is towards -Z from above and +Z from below
+    let flip = if facing_up { 1.0 } else { -1.0 };
+    let center = vertices.len() as u32;
+
+    vertices.push(vertex(Vector3::new(0.0, y, 0.0), [0.5, 0.5], normal));
+    for i in 0..=sectors {
+        let theta = 2.0 * PI * i as f32 / sectors as f32;
+        let (x, z) = (theta.sin(), theta.cos());
+        vertices.push(vertex(
+            Vector3::new(x * radius, y, z * radius),
+            [0.5 + x * 0.5, 0.5 + flip * z * 0.5],
+            normal,
+        ));
+    }
+
+    for i in 0..sectors {
+        let (current, next) = (center + 1 + i, center + 2 + i);
+        if facing_up {
+            indices.extend([center, current, next]);
+        } else {
+            indices.extend([center, next, current]);
+        }
+    }
+}
+
+/// A regular polygon in the XY plane facing +Z, its first corner at `start_angle` radians
+/// counter-clockwise from +X. The texture is mapped onto the bounding square.
+pub fn polygon(sides: u32, radius: f32, start_angle: f32) -> MeshData {
+    let sides = sides.max(3);
+    let normal = Vector3::unit_z();
+
+    let vertices = (0..sides)
+        .map(|i| {
+            let angle = start_angle + 2.0 * PI * i as f32 / sides as f32;
+            let (x, y) = (angle.cos(), angle.sin());
+            vertex(
+                Vector3::new(x * radius, y * radius, 0.0),
+                [0.5 + x * 0.5, 0.5 - y * 0.5],
+                normal,
+            )
+        })
+        .collect();
+
+    // a fan from the first corner, fine for convex shapes
+    let indices = (1..sides - 1).flat_map(|i| [0, i, i + 1]).collect();
+
+    MeshData::new(vertices, indices)
+}
+
+/// A rectangle in the XY plane facing +Z.
+pub fn quad(width: f32, height: f32) -> MeshData {
+    let mut vertices = Vec::new();
+    let mut indices = Vec::new();
+    push_face(
+        &mut vertices,
+        &mut indices,
+        Vector3::new(0.0, 0.0, 0.0),
+        Vector3::new(width * 0.5, 0.0, 0.0),
+        Vector3::new(0.0, height * 0.5, 0.0),
+    );
+
+    MeshData::new(vertices, indices)
+}
+
+/// A rectangle in the XZ plane facing +Y, split into `subdivisions` cells along each side.
+/// The texture spans the whole plane.
+#[allow(dead_code)]
+pub fn plane(width: f32, depth: f32, subdivisions: u32) -> MeshData {
+    let cells = subdivisions.max(1);
+    let row = cells + 1;
+
+    let mut vertices = Vec::with_capacity((row * row) as usize);
+    for j in 0..row {
+        for i in 0..row {
+            let (u, v) = (i as f32 / cells as f32, j as f32 / cells as f32);
+            vertices.push(vertex(
+                Vector3::new((u - 0.5) * width, 0.0, (v - 0.5) * depth),
+                [u, v],
+                Vector3::unit_y(),
+            ));
+        }
+    }
+
+    let mut indices = Vec::with_capacity((cells * cells * 6) as usize);
+    for j in 0..cells {
+        for i in 0..cells {
+            let top_left = j * row + i;
+            indices.extend(quad_indices(
+                top_left,
+                top_left + row,
+                top_left + row + 1,
+                top_left + 1,
+            ));
+        }
+    }
+
+    MeshData::new(vertices, indices)
+}
+
+/// A box with the whole texture on each face.
+#[allow(dead_code)]
+pub fn cuboid(width: f32, height: f32, depth: f32) -> MeshData {
+    let (x, y, z) = (width * 0.5, height * 0.5, depth * 0.5);
+    let mut vertices = Vec::with_capacity(24);
+    let mut indices = Vec::with_capacity(36);
+
+    // center, right and up of each face as seen from outside
+    let faces = [
+        ([0.0, 0.0, z], [x, 0.0, 0.0], [0.0, y, 0.0]),
+        ([0.0, 0.0, -z], [-x, 0.0, 0.0], [0.0, y, 0.0]),
+        ([x, 0.0, 0.0], [0.0, 0.0, -z], [0.0, y, 0.0]),
+        ([-x, 0.0, 0.0], [0.0, 0.0, z], [0.0, y, 0.0]),
+        ([0.0, y, 0.0], [x, 0.0, 0.0], [0.0, 0.0, -z]),
+        ([0.0, -y, 0.0], [x, 0.0, 0.0], [0.0, 0.0, z]),
+    ];
+    for (center, right, up) in faces {
+        push_face(
+            &mut vertices,
+            &mut indices,
+            center.into(),
+            right.into(),
+            up.into(),
+        );
+    }
+
+    MeshData::new(vertices, indices)
+}
+
+/// A sphere of `sectors` slices around Y and `stacks` bands from pole to pole. u goes
+/// around starting at +Z, v from the north pole down.
+#[allow(dead_code)]
+pub fn uv_sphere(radius: f32, sectors: u32, stacks: u32) -> MeshData {
+    let sectors = sectors.max(3);
+    let stacks = stacks.max(2);
+    // the seam column is doubled so u can run from 0 to 1
+    let row = sectors + 1;
+
+    let mut vertices = Vec::with_capacity((row * (stacks + 1)) as usize);
+    for j in 0..=stacks {
+        let v = j as f32 / stacks as f32;
+        let phi = PI * v;
+        for i in 0..=sectors {
+            let u = i as f32 / sectors as f32;
+            let theta = 2.0 * PI * u;
+            let normal = Vector3::new(phi.sin() * theta.sin(), phi.cos(), phi.sin() * theta.cos());
+            vertices.push(vertex(normal * radius, [u, v], normal));
+        }
+    }
+
+    let mut indices = Vec::with_capacity((sectors * stacks * 6) as usize);
+    for j in 0..stacks {
+        for i in 0..sectors {
+            let top_left = j * row + i;
+            let [a, b, c, d, e, f] =
+                quad_indices(top_left, top_left + row, top_left + row + 1, top_left + 1);
+            // the triangles touching a pole collapse there
+            if j != 0 {
+                indices.extend([a, b, c]);
+            }
+            if j != stacks - 1 {
+                indices.extend([d, e, f]);
+            }
+        }
+    }
+
+    MeshData::new(vertices, indices)
+}
+
+/// A subdivided icosahedron, its triangles evenly sized unlike a UV sphere's. Mapped like
+/// `uv_sphere`, with vertices doubled along the seam and at the poles.
+#[allow(dead_code)]
+pub fn icosphere(radius: f32, subdivisions: u32) -> MeshData {
+    let t = (1.0 + 5.0f32.sqrt()) / 2.0;
+    let mut positions = [
+        [-1.0, t, 0.0],
+        [1.0, t, 0.0],
+        [-1.0, -t, 0.0],
+        [1.0, -t, 0.0],
+        [0.0, -1.0, t],
+        [0.0, 1.0, t],
+        [0.0, -1.0, -t],
+        [0.0, 1.0, -t],
+        [t, 0.0, -1.0],
+        [t, 0.0, 1.0],
+        [-t, 0.0, -1.0],
+        [-t, 0.0, 1.0],
+    ]
+    .map(|position| Vector3::from(position).normalize())
+    .to_vec();
+
+    let mut faces = vec![
+        [0, 11, 5],
+        [0, 5, 1],
+        [0, 1, 7],
+        [0, 7, 10],
+        [0, 10, 11],
+        [1, 5, 9],
+        [5, 11, 4],
+        [11, 10, 2],
+        [10, 7, 6],
+        [7, 1, 8],
+        [3, 9, 4],
+        [3, 4, 2],
+        [3, 2, 6],
+        [3, 6, 8],
+        [3, 8, 9],
+        [4, 9, 5],
+        [2, 4, 11],
+        [6, 2, 10],
+        [8, 6, 7],
+        [9, 8, 1],
+    ];
+
+    for _ in 0..subdivisions {
+        // shared edges get one midpoint
+        let mut midpoints = HashMap::<(u32, u32), u32>::new();
+        let mut midpoint = |a: u32, b: u32| {
+            *midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+                positions.push((positions[a as usize] + positions[b as usize]).normalize());
+                positions.len() as u32 - 1
+            })
+        };
+
+        faces = faces
+            .iter()
+            .flat_map(|&[a, b, c]| {
+                let (ab, bc, ca) = (midpoint(a, b), midpoint(b, c), midpoint(c, a));
+                [[a, ab, ca], [b, bc, ab], [c, ca, bc], [ab, bc, ca]]
+            })
+            .collect();
+    }
+
+    let tex_coords = |normal: Vector3<f32>| {
+        [
+            (normal.x.atan2(normal.z) / (2.0 * PI)).rem_euclid(1.0),
+            normal.y.clamp(-1.0, 1.0).acos() / PI,
+        ]
+    };
+    let mut vertices = positions
+        .iter()
+        .map(|&normal| vertex(normal * radius, tex_coords(normal), normal))
+        .collect::<Vec<_>>();
+
+    let mut wrapped = HashMap::<u32, u32>::new();
+    let mut indices = Vec::with_capacity(faces.len() * 3);
+    for face in faces {
+        let is_pole = face.map(|index| {
+            let normal = positions[index as usize];
+            normal.x.abs() < 1e-6 && normal.z.abs() < 1e-6
+        });
+        let mut face = face;
+
+        // a face straddling the seam gets copies of its low u corners moved past 1
+        let us = (0..3)
+            .filter(|&k| !is_pole[k])
+            .map(|k| vertices[face[k] as usize].tex_coords[0]);
+        let (min, max) = us.fold((f32::MAX, f32::MIN), |(min, max), u| {
+            (min.min(u), max.max(u))
+        });
+        if max - min > 0.5 {
+            for k in (0..3).filter(|&k| !is_pole[k]) {
+                let index = face[k];
+                if vertices[index as usize].tex_coords[0] < 0.5 {
+                    face[k] = *wrapped.entry(index).or_insert_with(|| {
+                        let mut copy = vertices[index as usize];
+                        copy.tex_coords[0] += 1.0;
+                        vertices.push(copy);
+                        vertices.len() as u32 - 1
+                    });
+                }
+            }
+        }
+
+        // u means nothing at a pole, each face gets a copy in between its other corners
+        for k in (0..3).filter(|&k| is_pole[k]) {
+            let others = (0..3)
+                .filter(|&other| !is_pole[other])
+                .map(|other| vertices[face[other] as usize].tex_coords[0])
+                .collect::<Vec<_>>();
+            let mut copy = vertices[face[k] as usize];
+            copy.tex_coords[0] = others.iter().sum::<f32>() / others.len().max(1) as f32;
+            vertices.push(copy);
+            face[k] = vertices.len() as u32 - 1;
+        }
+
+        indices.extend(face);
+    }
+
+    MeshData::new(vertices, indices)
+}
+
+/// A capped cylinder along Y. The side is textured like a label wrapped around it, the caps
+/// with a disc cut from the middle of the texture.
+#[allow(dead_code)]
+pub fn cylinder(radius: f32, height: f32, sectors: u32) -> MeshData {
+    let sectors = sectors.max(3);
+    let half = height * 0.5;
+
+    let mut vertices = Vec::new();
+    for i in 0..=sectors {
+        let u = i as f32 / sectors as f32;
+        let theta = 2.0 * PI * u;
+        let normal = Vector3::new(theta.sin(), 0.0, theta.cos());
+        vertices.push(vertex(
+            normal * radius + Vector3::unit_y() * half,
+            [u, 0.0],
+            normal,
+        ));
+        vertices.push(vertex(
+            normal * radius - Vector3::unit_y() * half,
+            [u, 1.0],
+            normal,
+        ));
+    }
+
+    let mut indices = (0..sectors)
+        .flat_map(|i| quad_indices(i * 2, i * 2 + 1, i * 2 + 3, i * 2 + 2))
+        .collect();
+
+    push_disc(&mut vertices, &mut indices, radius, half, sectors, true);
+    push_disc(&mut vertices, &mut indices, radius, -half, sectors, false);
+
+    MeshData::new(vertices, indices)
+}
+
+/// A cone along Y with its tip at the top and a capped base, textured like `cylinder`.
+#[allow(dead_code)]
+pub fn cone(radius: f32, height: f32, sectors: u32) -> MeshData {
+    let sectors = sectors.max(3);
+    let half = height * 0.5;
+    // the side's normals lean up by the slope
+    let slope_normal =
+        |theta: f32| Vector3::new(theta.sin() * height, radius, theta.cos() * height).normalize();
+
+    let mut vertices = Vec::new();
+    for i in 0..=sectors {
+        let u = i as f32 / sectors as f32;
+        let theta = 2.0 * PI * u;
+        vertices.push(vertex(
+            Vector3::new(theta.sin() * radius, -half, theta.cos() * radius),
+            [u, 1.0],
+            slope_normal(theta),
+        ));
+    }
+    // one tip per sector, facing the middle of it, so the shading doesn't pinch
+    for i in 0..sectors {
+        let u = (i as f32 + 0.5) / sectors as f32;
+        vertices.push(vertex(
+            Vector3::new(0.0, half, 0.0),
+            [u, 0.0],
+            slope_normal(2.0 * PI * u),
+        ));
+    }
+
+    let tips = sectors + 1;
+    let mut indices = (0..sectors).flat_map(|i| [tips + i, i, i + 1]).collect();
+
+    push_disc(&mut vertices, &mut indices, radius, -half, sectors, false);
+
+    MeshData::new(vertices, indices)
+}
+
+/// A torus around Y. `major_radius` is the distance from the center to the middle of the
+/// tube, u goes around the ring and v around the tube starting at its top.
+#[allow(dead_code)]
+pub fn torus(
+    major_radius: f32,
+    minor_radius: f32,
+    major_segments: u32,
+    minor_segments: u32,
+) -> MeshData {
+    let major_segments = major_segments.max(3);
+    let minor_segments = minor_segments.max(3);
+    let row = major_segments + 1;
+
+    let mut vertices = Vec::with_capacity((row * (minor_segments + 1)) as usize);
+    for j in 0..=minor_segments {
+        let v = j as f32 / minor_segments as f32;
+        let phi = 2.0 * PI * v;
+        for i in 0..=major_segments {
+            let u = i as f32 / major_segments as f32;
+            let theta = 2.0 * PI * u;
+            let outward = Vector3::new(theta.sin(), 0.0, theta.cos());
+            let normal = outward * phi.sin() + Vector3::unit_y() * phi.cos();
+            vertices.push(vertex(
+                outward * major_radius + normal * minor_radius,
+                [u, v],
+                normal,
+            ));
+        }
+    }
+
+    let mut indices = Vec::with_capacity((major_segments * minor_segments * 6) as usize);
+    for j in 0..minor_segments {
+        for i in 0..major_segments {
+            let top_left = j * row + i;
+            indices.extend(quad_indices(
+                top_left,
+                top_left + row,
+                top_left + row + 1,
+                top_left + 1,
+            ));
+        }
+    }
+
+    MeshData::new(vertices, indices)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shapes() -> Vec<(&'static str, MeshData)> {
+        vec![
+            ("polygon", polygon(5, 0.5, 0.3)),
+            ("quad", quad(2.0, 1.0)),
+            ("plane", plane(2.0, 3.0, 4)),
+            ("cuboid", cuboid(1.0, 2.0, 3.0)),
+            ("uv_sphere", uv_sphere(1.0, 12, 8)),
+            ("icosphere", icosphere(1.0, 2)),
+            ("cylinder", cylinder(0.5, 2.0, 10)),
+            ("cone", cone(0.5, 2.0, 10)),
+            ("torus", torus(1.0, 0.25, 12, 8)),
+        ]
+    }
+
+    #[test]
+    fn indices_form_triangles_in_range() {
+        for (name, shape) in shapes() {
+            assert_eq!(shape.indices.len() % 3, 0, "{}", name);
+            assert!(!shape.indices.is_empty(), "{}", name);
+            assert!(
+                shape
+                    .indices
+                    .iter()
+                    .all(|&index| (index as usize) < shape.vertices.len()),
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn front_faces_wind_counter_clockwise() {
+        for (name, shape) in shapes() {
+            for face in shape.indices.chunks_exact(3) {
+                let corners = [0, 1, 2].map(|corner| shape.vertices[face[corner] as usize]);
+                let [a, b, c] = corners.map(|vertex| Vector3::from(vertex.pos));
+                let face_normal = (b - a).cross(c - a);
+                // the degenerate triangles at a UV sphere's poles have no side
+                if face_normal.magnitude2() < 1e-12 {
+                    continue;
+                }
+
+                for vertex in corners {
+                    assert!(
+                        face_normal.dot(Vector3::from(vertex.normal)) > 0.0,
+                        "{} face {:?}",
+                        name,
+                        face
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn tangent_frames_are_orthonormal() {
+        for (name, shape) in shapes() {
+            for vertex in &shape.vertices {
+                let normal = Vector3::from(vertex.normal);
+                let tangent = Vector3::new(vertex.tangent[0], vertex.tangent[1], vertex.tangent[2]);
+
+                assert!((normal.magnitude() - 1.0).abs() < 1e-4, "{}", name);
+                assert!((tangent.magnitude() - 1.0).abs() < 1e-4, "{}", name);
+                assert!(normal.dot(tangent).abs() < 1e-4, "{}", name);
+                assert_eq!(vertex.tangent[3].abs(), 1.0, "{}", name);
+            }
+        }
+    }
+
+    #[test]
+    fn no_face_wraps_around_the_texture() {
+        // seam copies may go past u 1 with a repeating sampler, but no face spans the seam
+        let wrapped = ["uv_sphere", "icosphere", "cylinder", "cone", "torus"];
+        for (name, shape) in shapes()
+            .into_iter()
+            .filter(|(name, _)| wrapped.contains(name))
+        {
+            for face in shape.indices.chunks_exact(3) {
+                let [a, b, c] = [0, 1, 2].map(|corner| shape.vertices[face[corner] as usize]);
+                let us = [a, b, c].map(|vertex| vertex.tex_coords[0]);
+                let span = us.iter().fold(f32::MIN, |max, &u| max.max(u))
+                    - us.iter().fold(f32::MAX, |min, &u| min.min(u));
+
+                assert!(span <= 0.5, "{} face {:?} u {:?}", name, face, us);
+                for vertex in [a, b, c] {
+                    assert!((0.0..=1.0).contains(&vertex.tex_coords[1]), "{}", name);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn shapes_have_their_size() {
+        let extent = |shape: &MeshData, axis: usize| {
+            let values = shape.vertices.iter().map(|vertex| vertex.pos[axis]);
+            let (min, max) = values.fold((f32::MAX, f32::MIN), |(min, max), value| {
+                (min.min(value), max.max(value))
+            });
+            max - min
+        };
+
+        let cuboid = cuboid(1.0, 2.0, 3.0);
+        assert_eq!(cuboid.vertices.len(), 24);
+        assert_eq!(cuboid.indices.len(), 36);
+        assert_eq!([0, 1, 2].map(|axis| extent(&cuboid, axis)), [1.0, 2.0, 3.0]);
+
+        let plane = plane(2.0, 3.0, 4);
+        assert_eq!(plane.vertices.len(), 25);
+        assert_eq!(plane.indices.len(), 4 * 4 * 6);
+        assert_eq!(extent(&plane, 1), 0.0);
+
+        for sphere in [uv_sphere(1.5, 12, 8), icosphere(1.5, 2)] {
+            for vertex in &sphere.vertices {
+                assert!((Vector3::from(vertex.pos).magnitude() - 1.5).abs() < 1e-4);
+            }
+        }
+
+        let polygon = polygon(6, 1.0, 0.0);
+        assert_eq!(polygon.vertices.len(), 6);
+        assert_eq!(polygon.indices.len(), 4 * 3);
+    }
+}
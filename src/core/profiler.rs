@@ -0,0 +1,414 @@
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+    sync::mpsc,
+    time::Duration,
+};
+
+#[derive(Clone, Copy, Debug)]
+pub enum Stage {
+    Prepare,
+    Encode,
+    Present,
+}
+
+impl Stage {
+    fn track_name(&self) -> &'static str {
+        match self {
+            Stage::Prepare => "cpu.prepare",
+            Stage::Encode => "cpu.encode",
+            Stage::Present => "cpu.present",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Stats {
+    pub samples: usize,
+    pub min: f32,
+    pub avg: f32,
+    pub max: f32,
+    pub p50: f32,
+    pub p95: f32,
+    pub p99: f32,
+}
+
+struct Track {
+    name: String,
+    // milliseconds, oldest first
+    samples: VecDeque<f32>,
+}
+
+impl Track {
+    fn stats(&self) -> Option<Stats> {
+        if self.samples.is_empty() {
+            return None;
+        }
+
+        let mut sorted = self.samples.iter().copied().collect::<Vec<_>>();
+        sorted.sort_by(|a, b| a.total_cmp(b));
+
+        let percentile = |p: f32| {
+            let rank = (p / 100.0 * (sorted.len() - 1) as f32).round() as usize;
+            sorted[rank]
+        };
+
+        Some(Stats {
+            samples: sorted.len(),
+            min: sorted[0],
+            avg: sorted.iter().sum::<f32>() / sorted.len() as f32,
+            max: sorted[sorted.len() - 1],
+            p50: percentile(50.0),
+            p95: percentile(95.0),
+            p99: percentile(99.0),
+        })
+    }
+}
+
+/// Rolling window of CPU stage timings and GPU pass timings, in milliseconds.
+pub struct Profiler {
+    window_size: usize,
+    tracks: Vec<Track>,
+    gpu: Option<GpuTimer>,
+}
+
+impl Profiler {
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        window_size: usize,
+        gpu_passes: &[&'static str],
+    ) -> Self {
+        let gpu = GpuTimer::new(device, queue, gpu_passes);
+
+        if gpu.is_none() {
+            log::info!("TIMESTAMP_QUERY is not supported, GPU pass timings are disabled");
+        }
+
+        Profiler {
+            window_size,
+            tracks: Vec::new(),
+            gpu,
+        }
+    }
+
+    pub fn record_cpu(&mut self, stage: Stage, duration: Duration) {
+        self.record(stage.track_name(), duration.as_secs_f32() * 1000.0);
+    }
+
+    fn record(&mut self, name: &str, ms: f32) {
+        let window_size = self.window_size;
+
+        let track = match self.tracks.iter().position(|track| track.name == name) {
+            Some(index) => &mut self.tracks[index],
+            None => {
+                self.tracks.push(Track {
+                    name: name.to_string(),
+                    samples: VecDeque::with_capacity(window_size),
+                });
+                self.tracks.last_mut().unwrap()
+            }
+        };
+
+        if track.samples.len() == window_size {
+            track.samples.pop_front();
+        }
+        track.samples.push_back(ms);
+    }
+
+    pub fn stats(&self) -> Vec<(&str, Stats)> {
+        self.tracks
+            .iter()
+            .filter_map(|track| track.stats().map(|stats| (track.name.as_str(), stats)))
+            .collect()
+    }
+
+    /// Called once per frame before encoding; decides whether this frame writes timestamps.
+    pub fn begin_frame(&mut self, device: &wgpu::Device) {
+        let timings = match &mut self.gpu {
+            Some(gpu) => gpu.collect(device),
+            None => return,
+        };
+
+        for (name, ms) in timings.unwrap_or_default() {
+            self.record(&format!("gpu.{}", name), ms);
+        }
+
+        if let Some(gpu) = &mut self.gpu {
+            gpu.active = gpu.pending.is_none();
+        }
+    }
+
+    pub fn timestamp_writes(&self, pass: &str) -> Option<wgpu::RenderPassTimestampWrites<'_>> {
+        self.gpu.as_ref()?.timestamp_writes(pass)
+    }
+
+    pub fn resolve(&self, encoder: &mut wgpu::CommandEncoder) {
+        if let Some(gpu) = &self.gpu {
+            gpu.resolve(encoder);
+        }
+    }
+
+    /// Must be called after the encoder returned to `resolve` was submitted.
+    pub fn end_frame(&mut self) {
+        if let Some(gpu) = &mut self.gpu {
+            gpu.map();
+        }
+    }
+
+    pub fn log(&self) {
+        for (name, stats) in self.stats() {
+            log::info!(
+                "{:<12} min {:>7.3} avg {:>7.3} max {:>7.3} p50 {:>7.3} p95 {:>7.3} p99 {:>7.3} ms ({} samples)",
+                name,
+                stats.min,
+                stats.avg,
+                stats.max,
+                stats.p50,
+                stats.p95,
+                stats.p99,
+                stats.samples
+            );
+        }
+    }
+
+    pub fn export_csv(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+
+        writeln!(
+            writer,
+            "metric,samples,min_ms,avg_ms,max_ms,p50_ms,p95_ms,p99_ms"
+        )?;
+        for (name, stats) in self.stats() {
+            writeln!(
+                writer,
+                "{},{},{},{},{},{},{},{}",
+                name,
+                stats.samples,
+                stats.min,
+                stats.avg,
+                stats.max,
+                stats.p50,
+                stats.p95,
+                stats.p99
+            )?;
+        }
+
+        writer.flush()?;
+
+        Ok(())
+    }
+}
+
+/// Begin/end timestamps for every named render pass, read back without stalling the frame.
+struct GpuTimer {
+    query_set: wgpu::QuerySet,
+    resolve_buffer: wgpu::Buffer,
+    readback_buffer: wgpu::Buffer,
+    passes: Vec<&'static str>,
+    // nanoseconds per timestamp tick
+    period: f32,
+    // timestamps are only written while the readback buffer is free
+    active: bool,
+    pending: Option<mpsc::Receiver<Result<(), wgpu::BufferAsyncError>>>,
+}
+
+impl GpuTimer {
+    fn new(device: &wgpu::Device, queue: &wgpu::Queue, passes: &[&'static str]) -> Option<Self> {
+        if !device.features().contains(wgpu::Features::TIMESTAMP_QUERY) || passes.is_empty() {
+            return None;
+        }
+
+        let count = passes.len() as u32 * 2;
+        let size = count as wgpu::BufferAddress * wgpu::QUERY_SIZE as wgpu::BufferAddress;
+
+        let query_set = device.create_query_set(&wgpu::QuerySetDescriptor {
+            label: Some("Profiler timestamp query set"),
+            ty: wgpu::QueryType::Timestamp,
+            count,
+        });
+
+        let resolve_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Profiler resolve buffer"),
+            size,
+            usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+
+        let readback_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Profiler readback buffer"),
+            size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Some(GpuTimer {
+            query_set,
+            resolve_buffer,
+            readback_buffer,
+            passes: passes.to_vec(),
+            period: queue.get_timestamp_period(),
+            active: false,
+            pending: None,
+        })
+    }
+
+    fn timestamp_writes(&self, pass: &str) -> Option<wgpu::RenderPassTimestampWrites<'_>> {
+        if !self.active {
+            return None;
+        }
+
+        let index = self.passes.iter().position(|name| *name == pass)? as u32;
+
+        Some(wgpu::RenderPassTimestampWrites {
+            query_set: &self.query_set,
+            beginning_of_pass_write_index: Some(index * 2),
+            end_of_pass_write_index: Some(index * 2 + 1),
+        })
+    }
+
+    fn resolve(&self, encoder: &mut wgpu::CommandEncoder) {
+        if !self.active {
+            return;
+        }
+
+        encoder.resolve_query_set(
+            &self.query_set,
+            0..self.passes.len() as u32 * 2,
+            &self.resolve_buffer,
+            0,
+        );
+        encoder.copy_buffer_to_buffer(
+            &self.resolve_buffer,
+            0,
+            &self.readback_buffer,
+            0,
+            self.resolve_buffer.size(),
+        );
+    }
+
+    fn map(&mut self) {
+        if !self.active {
+            return;
+        }
+
+        let (sender, receiver) = mpsc::channel();
+
+        self.readback_buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| {
+                let _ = sender.send(result);
+            });
+
+        self.pending = Some(receiver);
+        self.active = false;
+    }
+
+    fn collect(&mut self, device: &wgpu::Device) -> Option<Vec<(&'static str, f32)>> {
+        device.poll(wgpu::Maintain::Poll);
+
+        match self.pending.as_ref()?.try_recv() {
+            Ok(Ok(())) => {}
+            Ok(Err(error)) => {
+                log::warn!("Failed to read back GPU timestamps: {}", error);
+                self.pending = None;
+                return None;
+            }
+            Err(mpsc::TryRecvError::Empty) => return None,
+            Err(mpsc::TryRecvError::Disconnected) => {
+                self.pending = None;
+                return None;
+            }
+        }
+
+        let timings = {
+            let data = self.readback_buffer.slice(..).get_mapped_range();
+            let timestamps: &[u64] = bytemuck::cast_slice(&data);
+
+            self.passes
+                .iter()
+                .enumerate()
+                .map(|(index, name)| {
+                    let ticks = timestamps[index * 2 + 1].wrapping_sub(timestamps[index * 2]);
+                    (*name, ticks as f32 * self.period / 1_000_000.0)
+                })
+                .collect()
+        };
+
+        self.readback_buffer.unmap();
+        self.pending = None;
+
+        Some(timings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn profiler(window_size: usize) -> Profiler {
+        Profiler {
+            window_size,
+            tracks: Vec::new(),
+            gpu: None,
+        }
+    }
+
+    #[test]
+    fn full_windows_drop_the_oldest_sample() {
+        let mut profiler = profiler(3);
+        for ms in [10.0, 1.0, 2.0, 3.0] {
+            profiler.record("cpu.encode", ms);
+        }
+        profiler.record("cpu.present", 5.0);
+
+        let stats = profiler.stats();
+        assert_eq!(stats.len(), 2);
+        let (name, encode) = stats[0];
+        assert_eq!(name, "cpu.encode");
+        assert_eq!(encode.samples, 3);
+        assert_eq!((encode.min, encode.max), (1.0, 3.0));
+        assert_eq!(stats[1].1.samples, 1);
+    }
+
+    #[test]
+    fn stats_of_a_known_window() {
+        let mut profiler = profiler(100);
+        // 1 to 100 recorded backwards, the order mustn't matter
+        for ms in (1..=100).rev().map(|ms| ms as f32) {
+            profiler.record("cpu.prepare", ms);
+        }
+
+        let (_, stats) = profiler.stats()[0];
+        assert_eq!((stats.min, stats.max), (1.0, 100.0));
+        assert_eq!(stats.avg, 50.5);
+        // nearest rank over 0..99
+        assert_eq!(stats.p50, 51.0);
+        assert_eq!(stats.p95, 95.0);
+        assert_eq!(stats.p99, 99.0);
+    }
+
+    #[test]
+    fn csv_has_a_header_and_a_row_per_track() {
+        let mut profiler = profiler(4);
+        profiler.record("cpu.prepare", 2.0);
+        profiler.record("gpu.main", 0.5);
+        profiler.record("gpu.main", 1.5);
+
+        let path = std::env::temp_dir().join(format!("timings-{}.csv", std::process::id()));
+        profiler.export_csv(&path).unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            text.lines().collect::<Vec<_>>(),
+            [
+                "metric,samples,min_ms,avg_ms,max_ms,p50_ms,p95_ms,p99_ms",
+                "cpu.prepare,1,2,2,2,2,2,2",
+                "gpu.main,2,0.5,1,1.5,1.5,1.5,1.5",
+            ]
+        );
+    }
+}
@@ -1,3 +1,80 @@
+use super::error::ResourceError;
+
+/// Which end of the depth range is near. Reverse-Z puts the near plane at 1.0 and uses an
+/// infinite far plane, which spreads float precision evenly over distance.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DepthMode {
+    Standard,
+    ReverseZ,
+}
+
+impl DepthMode {
+    pub fn compare(self) -> wgpu::CompareFunction {
+        match self {
+            DepthMode::Standard => wgpu::CompareFunction::Less,
+            DepthMode::ReverseZ => wgpu::CompareFunction::Greater,
+        }
+    }
+
+    /// Depth the buffer is cleared to, i.e. the farthest value.
+    pub fn clear_value(self) -> f32 {
+        match self {
+            DepthMode::Standard => 1.0,
+            DepthMode::ReverseZ => 0.0,
+        }
+    }
+
+    pub fn depth_stencil_state(self) -> wgpu::DepthStencilState {
+        wgpu::DepthStencilState {
+            format: Texture::DEPTH_FORMAT,
+            depth_write_enabled: true,
+            depth_compare: self.compare(),
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }
+    }
+}
+
+/// How texel values are stored. Colors are sRGB encoded and converted to linear when
+/// sampled, data like normal maps is already linear and read as is.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ColorSpace {
+    Srgb,
+    Linear,
+}
+
+impl ColorSpace {
+    pub fn format(self) -> wgpu::TextureFormat {
+        match self {
+            ColorSpace::Srgb => wgpu::TextureFormat::Rgba8UnormSrgb,
+            ColorSpace::Linear => wgpu::TextureFormat::Rgba8Unorm,
+        }
+    }
+}
+
+/// How a texture is filtered and what lies beyond its edges, see `Texture::create_sampler`.
+#[derive(Clone, Copy, Debug)]
+pub struct SamplerOptions {
+    pub address_mode_u: wgpu::AddressMode,
+    pub address_mode_v: wgpu::AddressMode,
+    pub mag_filter: wgpu::FilterMode,
+    pub min_filter: wgpu::FilterMode,
+    // between mip levels
+    pub mipmap_filter: wgpu::FilterMode,
+}
+
+impl Default for SamplerOptions {
+    fn default() -> Self {
+        SamplerOptions {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Nearest,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+        }
+    }
+}
+
 pub struct Texture {
     pub texture: wgpu::Texture,
     pub view: wgpu::TextureView,
@@ -12,17 +89,22 @@ impl Texture {
         queue: &wgpu::Queue,
         data: Vec<u8>,
         file_name: &str,
+        color_space: ColorSpace,
         raw: Option<image::ImageBuffer<image::Rgba<u8>, Vec<u8>>>,
         sampler: wgpu::Sampler,
-    ) -> anyhow::Result<Self> {
-        let mut tmp_data;
+    ) -> Result<Self, ResourceError> {
+        let tmp_data;
 
         match raw {
             Some(info) => {
                 tmp_data = info;
             }
             _ => {
-                let img = image::load_from_memory(&data).unwrap();
+                let img =
+                    image::load_from_memory(&data).map_err(|source| ResourceError::Decode {
+                        path: file_name.to_string(),
+                        source,
+                    })?;
                 tmp_data = img.to_rgba8();
             }
         }
@@ -41,7 +123,7 @@ impl Texture {
             mip_level_count: 1,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            format: color_space.format(),
             usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
             view_formats: &[],
         });
@@ -71,9 +153,63 @@ impl Texture {
         })
     }
 
+    /// A 1x1 texture of a single color, e.g. a stand-in for a texture a material lacks.
+    pub fn from_color(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        color: [u8; 4],
+        color_space: ColorSpace,
+        label: &str,
+    ) -> Result<Self, ResourceError> {
+        Self::from_bytes(
+            device,
+            queue,
+            Vec::new(),
+            label,
+            color_space,
+            Some(image::ImageBuffer::from_pixel(1, 1, image::Rgba(color))),
+            Self::create_sampler(device, SamplerOptions::default()),
+        )
+    }
+
+    /// Magenta and black squares, bound where a texture failed to load so it stands out.
+    pub fn checkerboard(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        label: &str,
+    ) -> Result<Self, ResourceError> {
+        const SIZE: u32 = 8;
+
+        let checkerboard = image::ImageBuffer::from_fn(SIZE, SIZE, |x, y| {
+            if (x + y) % 2 == 0 {
+                image::Rgba([255, 0, 255, 255])
+            } else {
+                image::Rgba([0, 0, 0, 255])
+            }
+        });
+
+        Self::from_bytes(
+            device,
+            queue,
+            Vec::new(),
+            label,
+            ColorSpace::Srgb,
+            Some(checkerboard),
+            // sharp squares however close the camera gets
+            Self::create_sampler(
+                device,
+                SamplerOptions {
+                    mag_filter: wgpu::FilterMode::Nearest,
+                    ..Default::default()
+                },
+            ),
+        )
+    }
+
     pub fn create_depth_texture(
         device: &wgpu::Device,
         config: &wgpu::SurfaceConfiguration,
+        depth_mode: DepthMode,
         label: &str,
     ) -> Self {
         let texture = device.create_texture(&wgpu::TextureDescriptor {
@@ -86,7 +222,7 @@ impl Texture {
             mip_level_count: 1,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Depth32Float,
+            format: Self::DEPTH_FORMAT,
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
             view_formats: &[],
         });
@@ -100,7 +236,10 @@ impl Texture {
             mag_filter: wgpu::FilterMode::Linear,
             min_filter: wgpu::FilterMode::Linear,
             mipmap_filter: wgpu::FilterMode::Nearest,
-            compare: Some(wgpu::CompareFunction::LessEqual), // 5.
+            compare: Some(match depth_mode {
+                DepthMode::Standard => wgpu::CompareFunction::LessEqual,
+                DepthMode::ReverseZ => wgpu::CompareFunction::GreaterEqual,
+            }), // 5.
             lod_min_clamp: 0.0,
             lod_max_clamp: 100.0,
             ..Default::default()
@@ -123,17 +262,14 @@ impl Texture {
         self.sampler = new_texture.sampler;
     }
 
-    pub fn create_sampler(
-        device: &wgpu::Device,
-        mag_filter: Option<wgpu::FilterMode>,
-    ) -> wgpu::Sampler {
+    pub fn create_sampler(device: &wgpu::Device, options: SamplerOptions) -> wgpu::Sampler {
         device.create_sampler(&wgpu::SamplerDescriptor {
-            address_mode_u: wgpu::AddressMode::ClampToEdge,
-            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_u: options.address_mode_u,
+            address_mode_v: options.address_mode_v,
             address_mode_w: wgpu::AddressMode::ClampToEdge,
-            mag_filter: mag_filter.unwrap_or(wgpu::FilterMode::Linear), // TODO: make it changeable
-            min_filter: wgpu::FilterMode::Nearest,
-            mipmap_filter: wgpu::FilterMode::Nearest,
+            mag_filter: options.mag_filter,
+            min_filter: options.min_filter,
+            mipmap_filter: options.mipmap_filter,
             ..Default::default()
         })
     }
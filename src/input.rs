@@ -0,0 +1,561 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::anyhow;
+use winit::{
+    event::MouseButton,
+    keyboard::{KeyCode, ModifiersState},
+};
+
+use self::event::InputEvent;
+
+pub mod event;
+pub mod record;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Input {
+    Key(KeyCode),
+    Mouse(MouseButton),
+}
+
+impl Input {
+    /// A `KeyCode` variant name like `KeyW` or `F5`, or `MouseLeft`, `MouseMiddle` and
+    /// `MouseRight`.
+    pub fn parse(name: &str) -> Option<Input> {
+        Some(match name {
+            "MouseLeft" => Input::Mouse(MouseButton::Left),
+            "MouseRight" => Input::Mouse(MouseButton::Right),
+            "MouseMiddle" => Input::Mouse(MouseButton::Middle),
+            _ => Input::Key(*KEYS.iter().find(|key| format!("{:?}", key) == name)?),
+        })
+    }
+
+    /// The name `parse` accepts, None for inputs that can't be named.
+    pub fn name(&self) -> Option<String> {
+        match self {
+            Input::Mouse(MouseButton::Left) => Some(String::from("MouseLeft")),
+            Input::Mouse(MouseButton::Right) => Some(String::from("MouseRight")),
+            Input::Mouse(MouseButton::Middle) => Some(String::from("MouseMiddle")),
+            Input::Mouse(_) => None,
+            Input::Key(key) => KEYS.contains(key).then(|| format!("{:?}", key)),
+        }
+    }
+}
+
+/// An input plus the modifiers that have to be held with it. Other modifiers may be held
+/// too, so `KeyW` still moves while sprinting with Shift, unless another binding of the same
+/// input asks for them: with `Ctrl+KeyS` bound, `KeyS` doesn't fire while Ctrl is held.
+#[derive(Clone, Copy, Debug)]
+pub struct Binding {
+    pub input: Input,
+    pub modifiers: ModifiersState,
+}
+
+impl Binding {
+    /// Parses e.g. `KeyW`, `Ctrl+Shift+F5` or `MouseLeft`.
+    pub fn parse(text: &str) -> Option<Binding> {
+        let mut parts = text.split('+').collect::<Vec<_>>();
+        let name = parts.pop()?;
+
+        let mut modifiers = ModifiersState::empty();
+        for modifier in parts {
+            modifiers |= match modifier {
+                "Ctrl" => ModifiersState::CONTROL,
+                "Shift" => ModifiersState::SHIFT,
+                "Alt" => ModifiersState::ALT,
+                "Super" => ModifiersState::SUPER,
+                _ => return None,
+            };
+        }
+
+        Some(Binding {
+            input: Input::parse(name)?,
+            modifiers,
+        })
+    }
+}
+
+/// Named actions and axes bound to keys and mouse buttons. Input events update the input
+/// state, `end_frame` clears the per-frame pressed and released sets.
+pub struct ActionMap {
+    actions: HashMap<String, Vec<Binding>>,
+    // bindings with the value they contribute while held
+    axes: HashMap<String, Vec<(Binding, f32)>>,
+    modifiers: ModifiersState,
+    cursor_position: (f64, f64),
+    held: HashSet<Input>,
+    pressed: HashSet<Input>,
+    released: HashSet<Input>,
+}
+
+impl ActionMap {
+    pub fn new() -> Self {
+        ActionMap {
+            actions: HashMap::new(),
+            axes: HashMap::new(),
+            modifiers: ModifiersState::empty(),
+            cursor_position: (0.0, 0.0),
+            held: HashSet::new(),
+            pressed: HashSet::new(),
+            released: HashSet::new(),
+        }
+    }
+
+    /// One declaration per line, `#` starts a comment:
+    /// `action <name> <binding>...` or `axis <name> <binding> <value>`.
+    pub fn parse(text: &str, source: &str) -> anyhow::Result<Self> {
+        let mut map = ActionMap::new();
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let error =
+                |message: &str| anyhow!("{}:{}: {} '{}'", source, number + 1, message, line);
+            let binding = |text: &str| Binding::parse(text).ok_or_else(|| error("unknown binding"));
+
+            let words = line.split_whitespace().collect::<Vec<_>>();
+            match words[..] {
+                ["action", name, ref bindings @ ..] if !bindings.is_empty() => {
+                    for text in bindings {
+                        map.bind(name, binding(text)?);
+                    }
+                }
+                ["axis", name, text, value] => {
+                    let value = value.parse().map_err(|_| error("invalid axis value"))?;
+                    map.bind_axis(name, binding(text)?, value);
+                }
+                _ => return Err(error("invalid declaration")),
+            }
+        }
+
+        Ok(map)
+    }
+
+    pub fn bind(&mut self, action: &str, binding: Binding) {
+        self.actions
+            .entry(action.to_string())
+            .or_default()
+            .push(binding);
+    }
+
+    pub fn bind_axis(&mut self, axis: &str, binding: Binding, value: f32) {
+        self.axes
+            .entry(axis.to_string())
+            .or_default()
+            .push((binding, value));
+    }
+
+    pub fn process(&mut self, event: &InputEvent) {
+        match *event {
+            InputEvent::Button { input, pressed } => {
+                if pressed {
+                    if self.held.insert(input) {
+                        self.pressed.insert(input);
+                    }
+                } else if self.held.remove(&input) {
+                    self.released.insert(input);
+                }
+            }
+            InputEvent::Modifiers(modifiers) => self.modifiers = modifiers,
+            InputEvent::CursorMoved { x, y } => self.cursor_position = (x, y),
+            // key releases aren't delivered while unfocused
+            InputEvent::Focused(false) => {
+                self.released.extend(self.held.drain());
+                self.modifiers = ModifiersState::empty();
+            }
+            _ => {}
+        }
+    }
+
+    /// Last cursor position in physical pixels.
+    pub fn cursor_position(&self) -> (f64, f64) {
+        self.cursor_position
+    }
+
+    fn bindings(&self) -> impl Iterator<Item = &Binding> {
+        self.actions
+            .values()
+            .flatten()
+            .chain(self.axes.values().flatten().map(|(binding, _)| binding))
+    }
+
+    // its modifiers are held and no binding of the same input wants more of the held ones
+    fn active(&self, binding: &Binding) -> bool {
+        self.modifiers.contains(binding.modifiers)
+            && !self.bindings().any(|other| {
+                other.input == binding.input
+                    && other.modifiers != binding.modifiers
+                    && other.modifiers.contains(binding.modifiers)
+                    && self.modifiers.contains(other.modifiers)
+            })
+    }
+
+    fn matches(&self, action: &str, inputs: &HashSet<Input>) -> bool {
+        self.actions.get(action).is_some_and(|bindings| {
+            bindings
+                .iter()
+                .any(|binding| inputs.contains(&binding.input) && self.active(binding))
+        })
+    }
+
+    /// True in the frame the action went down.
+    pub fn pressed(&self, action: &str) -> bool {
+        self.matches(action, &self.pressed)
+    }
+
+    pub fn held(&self, action: &str) -> bool {
+        self.matches(action, &self.held)
+    }
+
+    /// True in the frame the action went up.
+    pub fn released(&self, action: &str) -> bool {
+        self.matches(action, &self.released)
+    }
+
+    /// Sum of the held bindings' values, clamped to -1..1.
+    pub fn axis(&self, axis: &str) -> f32 {
+        self.axes.get(axis).map_or(0.0, |bindings| {
+            bindings
+                .iter()
+                .filter(|(binding, _)| self.held.contains(&binding.input) && self.active(binding))
+                .map(|(_, value)| value)
+                .sum::<f32>()
+                .clamp(-1.0, 1.0)
+        })
+    }
+
+    pub fn end_frame(&mut self) {
+        self.pressed.clear();
+        self.released.clear();
+    }
+}
+
+// every `KeyCode`, a bindings file names them by their variant name
+const KEYS: &[KeyCode] = &[
+    KeyCode::Backquote,
+    KeyCode::Backslash,
+    KeyCode::BracketLeft,
+    KeyCode::BracketRight,
+    KeyCode::Comma,
+    KeyCode::Digit0,
+    KeyCode::Digit1,
+    KeyCode::Digit2,
+    KeyCode::Digit3,
+    KeyCode::Digit4,
+    KeyCode::Digit5,
+    KeyCode::Digit6,
+    KeyCode::Digit7,
+    KeyCode::Digit8,
+    KeyCode::Digit9,
+    KeyCode::Equal,
+    KeyCode::IntlBackslash,
+    KeyCode::IntlRo,
+    KeyCode::IntlYen,
+    KeyCode::KeyA,
+    KeyCode::KeyB,
+    KeyCode::KeyC,
+    KeyCode::KeyD,
+    KeyCode::KeyE,
+    KeyCode::KeyF,
+    KeyCode::KeyG,
+    KeyCode::KeyH,
+    KeyCode::KeyI,
+    KeyCode::KeyJ,
+    KeyCode::KeyK,
+    KeyCode::KeyL,
+    KeyCode::KeyM,
+    KeyCode::KeyN,
+    KeyCode::KeyO,
+    KeyCode::KeyP,
+    KeyCode::KeyQ,
+    KeyCode::KeyR,
+    KeyCode::KeyS,
+    KeyCode::KeyT,
+    KeyCode::KeyU,
+    KeyCode::KeyV,
+    KeyCode::KeyW,
+    KeyCode::KeyX,
+    KeyCode::KeyY,
+    KeyCode::KeyZ,
+    KeyCode::Minus,
+    KeyCode::Period,
+    KeyCode::Quote,
+    KeyCode::Semicolon,
+    KeyCode::Slash,
+    KeyCode::AltLeft,
+    KeyCode::AltRight,
+    KeyCode::Backspace,
+    KeyCode::CapsLock,
+    KeyCode::ContextMenu,
+    KeyCode::ControlLeft,
+    KeyCode::ControlRight,
+    KeyCode::Enter,
+    KeyCode::SuperLeft,
+    KeyCode::SuperRight,
+    KeyCode::ShiftLeft,
+    KeyCode::ShiftRight,
+    KeyCode::Space,
+    KeyCode::Tab,
+    KeyCode::Convert,
+    KeyCode::KanaMode,
+    KeyCode::Lang1,
+    KeyCode::Lang2,
+    KeyCode::Lang3,
+    KeyCode::Lang4,
+    KeyCode::Lang5,
+    KeyCode::NonConvert,
+    KeyCode::Delete,
+    KeyCode::End,
+    KeyCode::Help,
+    KeyCode::Home,
+    KeyCode::Insert,
+    KeyCode::PageDown,
+    KeyCode::PageUp,
+    KeyCode::ArrowDown,
+    KeyCode::ArrowLeft,
+    KeyCode::ArrowRight,
+    KeyCode::ArrowUp,
+    KeyCode::NumLock,
+    KeyCode::Numpad0,
+    KeyCode::Numpad1,
+    KeyCode::Numpad2,
+    KeyCode::Numpad3,
+    KeyCode::Numpad4,
+    KeyCode::Numpad5,
+    KeyCode::Numpad6,
+    KeyCode::Numpad7,
+    KeyCode::Numpad8,
+    KeyCode::Numpad9,
+    KeyCode::NumpadAdd,
+    KeyCode::NumpadBackspace,
+    KeyCode::NumpadClear,
+    KeyCode::NumpadClearEntry,
+    KeyCode::NumpadComma,
+    KeyCode::NumpadDecimal,
+    KeyCode::NumpadDivide,
+    KeyCode::NumpadEnter,
+    KeyCode::NumpadEqual,
+    KeyCode::NumpadHash,
+    KeyCode::NumpadMemoryAdd,
+    KeyCode::NumpadMemoryClear,
+    KeyCode::NumpadMemoryRecall,
+    KeyCode::NumpadMemoryStore,
+    KeyCode::NumpadMemorySubtract,
+    KeyCode::NumpadMultiply,
+    KeyCode::NumpadParenLeft,
+    KeyCode::NumpadParenRight,
+    KeyCode::NumpadStar,
+    KeyCode::NumpadSubtract,
+    KeyCode::Escape,
+    KeyCode::Fn,
+    KeyCode::FnLock,
+    KeyCode::PrintScreen,
+    KeyCode::ScrollLock,
+    KeyCode::Pause,
+    KeyCode::BrowserBack,
+    KeyCode::BrowserFavorites,
+    KeyCode::BrowserForward,
+    KeyCode::BrowserHome,
+    KeyCode::BrowserRefresh,
+    KeyCode::BrowserSearch,
+    KeyCode::BrowserStop,
+    KeyCode::Eject,
+    KeyCode::LaunchApp1,
+    KeyCode::LaunchApp2,
+    KeyCode::LaunchMail,
+    KeyCode::MediaPlayPause,
+    KeyCode::MediaSelect,
+    KeyCode::MediaStop,
+    KeyCode::MediaTrackNext,
+    KeyCode::MediaTrackPrevious,
+    KeyCode::Power,
+    KeyCode::Sleep,
+    KeyCode::AudioVolumeDown,
+    KeyCode::AudioVolumeMute,
+    KeyCode::AudioVolumeUp,
+    KeyCode::WakeUp,
+    KeyCode::Meta,
+    KeyCode::Hyper,
+    KeyCode::Turbo,
+    KeyCode::Abort,
+    KeyCode::Resume,
+    KeyCode::Suspend,
+    KeyCode::Again,
+    KeyCode::Copy,
+    KeyCode::Cut,
+    KeyCode::Find,
+    KeyCode::Open,
+    KeyCode::Paste,
+    KeyCode::Props,
+    KeyCode::Select,
+    KeyCode::Undo,
+    KeyCode::Hiragana,
+    KeyCode::Katakana,
+    KeyCode::F1,
+    KeyCode::F2,
+    KeyCode::F3,
+    KeyCode::F4,
+    KeyCode::F5,
+    KeyCode::F6,
+    KeyCode::F7,
+    KeyCode::F8,
+    KeyCode::F9,
+    KeyCode::F10,
+    KeyCode::F11,
+    KeyCode::F12,
+    KeyCode::F13,
+    KeyCode::F14,
+    KeyCode::F15,
+    KeyCode::F16,
+    KeyCode::F17,
+    KeyCode::F18,
+    KeyCode::F19,
+    KeyCode::F20,
+    KeyCode::F21,
+    KeyCode::F22,
+    KeyCode::F23,
+    KeyCode::F24,
+    KeyCode::F25,
+    KeyCode::F26,
+    KeyCode::F27,
+    KeyCode::F28,
+    KeyCode::F29,
+    KeyCode::F30,
+    KeyCode::F31,
+    KeyCode::F32,
+    KeyCode::F33,
+    KeyCode::F34,
+    KeyCode::F35,
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn button(key: KeyCode, pressed: bool) -> InputEvent {
+        InputEvent::Button {
+            input: Input::Key(key),
+            pressed,
+        }
+    }
+
+    fn map(text: &str) -> ActionMap {
+        ActionMap::parse(text, "test").unwrap()
+    }
+
+    #[test]
+    fn parses_the_shipped_bindings() {
+        let mut map = map(include_str!("../res/bindings.txt"));
+
+        map.process(&button(KeyCode::Escape, true));
+        assert!(map.pressed("quit"));
+        map.end_frame();
+
+        map.process(&button(KeyCode::KeyW, true));
+        assert_eq!(map.axis("move_forward"), 1.0);
+        map.process(&button(KeyCode::KeyS, true));
+        assert_eq!(map.axis("move_forward"), 0.0);
+    }
+
+    #[test]
+    fn parses_bindings() {
+        let binding = Binding::parse("Ctrl+Shift+F5").unwrap();
+        assert_eq!(binding.input, Input::Key(KeyCode::F5));
+        assert_eq!(
+            binding.modifiers,
+            ModifiersState::CONTROL | ModifiersState::SHIFT
+        );
+
+        let binding = Binding::parse("MouseMiddle").unwrap();
+        assert_eq!(binding.input, Input::Mouse(MouseButton::Middle));
+        assert!(binding.modifiers.is_empty());
+
+        assert!(Binding::parse("Hyper+KeyA").is_none());
+        assert!(Binding::parse("KeyAA").is_none());
+        assert!(Binding::parse("Ctrl+").is_none());
+    }
+
+    #[test]
+    fn rejects_bad_declarations() {
+        for text in [
+            "action quit",
+            "action quit Esc",
+            "axis move KeyW",
+            "axis move KeyW fast",
+            "bind quit Escape",
+        ] {
+            let error = ActionMap::parse(text, "bindings.txt").err().unwrap();
+            assert!(error.to_string().starts_with("bindings.txt:1:"), "{}", text);
+        }
+    }
+
+    #[test]
+    fn modifiers_pick_the_most_specific_binding() {
+        let mut map = map("action save F5\naction save_as Ctrl+F5\naction back KeyS\n");
+
+        map.process(&button(KeyCode::F5, true));
+        assert!(map.pressed("save"));
+        assert!(!map.pressed("save_as"));
+        map.process(&button(KeyCode::F5, false));
+        map.end_frame();
+
+        map.process(&InputEvent::Modifiers(ModifiersState::CONTROL));
+        map.process(&button(KeyCode::F5, true));
+        assert!(map.pressed("save_as"));
+        assert!(!map.pressed("save"));
+
+        // nothing more specific is bound to S, so it works with any modifiers
+        map.process(&button(KeyCode::KeyS, true));
+        assert!(map.pressed("back"));
+    }
+
+    #[test]
+    fn any_key_can_be_bound() {
+        let mut map = map("action zoom_in NumpadAdd\naction zoom_out Ctrl+Minus\n");
+
+        map.process(&button(KeyCode::NumpadAdd, true));
+        assert!(map.pressed("zoom_in"));
+        map.process(&InputEvent::Modifiers(ModifiersState::CONTROL));
+        map.process(&button(KeyCode::Minus, true));
+        assert!(map.pressed("zoom_out"));
+
+        for key in [
+            KeyCode::Numpad7,
+            KeyCode::PageUp,
+            KeyCode::Comma,
+            KeyCode::F24,
+        ] {
+            let name = Input::Key(key).name().unwrap();
+            assert_eq!(Input::parse(&name), Some(Input::Key(key)));
+        }
+    }
+
+    #[test]
+    fn axes_hold_through_other_modifiers() {
+        let mut map = map("axis move KeyW 1\naxis move KeyS -1\naction sprint ShiftLeft\n");
+
+        map.process(&button(KeyCode::ShiftLeft, true));
+        map.process(&InputEvent::Modifiers(ModifiersState::SHIFT));
+        map.process(&button(KeyCode::KeyW, true));
+        assert!(map.held("sprint"));
+        assert_eq!(map.axis("move"), 1.0);
+    }
+
+    #[test]
+    fn losing_focus_releases_everything() {
+        let mut map = map("action fire MouseLeft\n");
+
+        map.process(&InputEvent::Button {
+            input: Input::Mouse(MouseButton::Left),
+            pressed: true,
+        });
+        map.end_frame();
+        assert!(map.held("fire"));
+
+        map.process(&InputEvent::Focused(false));
+        assert!(map.released("fire"));
+        assert!(!map.held("fire"));
+    }
+}
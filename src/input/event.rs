@@ -0,0 +1,117 @@
+use winit::{
+    event::{DeviceEvent, ElementState, KeyEvent, MouseScrollDelta, WindowEvent},
+    keyboard::{ModifiersState, PhysicalKey},
+};
+
+use super::Input;
+
+/// The parts of winit's window and device events the app reacts to, in a form that can be
+/// written to and read back from a recording.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum InputEvent {
+    Button { input: Input, pressed: bool },
+    Modifiers(ModifiersState),
+    // physical pixels
+    CursorMoved { x: f64, y: f64 },
+    // lines, positive away from the user
+    Wheel { lines: f32 },
+    // raw, unaccelerated mouse motion
+    MouseMotion { dx: f64, dy: f64 },
+    Focused(bool),
+}
+
+impl InputEvent {
+    pub fn from_window_event(event: &WindowEvent) -> Option<InputEvent> {
+        let pressed = |state: &ElementState| *state == ElementState::Pressed;
+
+        match event {
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(key),
+                        state,
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } => Some(InputEvent::Button {
+                input: Input::Key(*key),
+                pressed: pressed(state),
+            }),
+            WindowEvent::MouseInput { state, button, .. } => Some(InputEvent::Button {
+                input: Input::Mouse(*button),
+                pressed: pressed(state),
+            }),
+            WindowEvent::ModifiersChanged(modifiers) => {
+                Some(InputEvent::Modifiers(modifiers.state()))
+            }
+            WindowEvent::CursorMoved { position, .. } => Some(InputEvent::CursorMoved {
+                x: position.x,
+                y: position.y,
+            }),
+            WindowEvent::MouseWheel { delta, .. } => Some(InputEvent::Wheel {
+                lines: match delta {
+                    MouseScrollDelta::LineDelta(_, y) => *y,
+                    MouseScrollDelta::PixelDelta(position) => position.y as f32 / 20.0,
+                },
+            }),
+            WindowEvent::Focused(focused) => Some(InputEvent::Focused(*focused)),
+            _ => None,
+        }
+    }
+
+    pub fn from_device_event(event: &DeviceEvent) -> Option<InputEvent> {
+        match event {
+            DeviceEvent::MouseMotion { delta: (dx, dy) } => {
+                Some(InputEvent::MouseMotion { dx: *dx, dy: *dy })
+            }
+            _ => None,
+        }
+    }
+
+    /// Space separated, e.g. `button KeyW down` or `cursor 320 240`. Buttons without a
+    /// name can't be written and give None.
+    pub fn to_text(self) -> Option<String> {
+        Some(match self {
+            InputEvent::Button { input, pressed } => format!(
+                "button {} {}",
+                input.name()?,
+                if pressed { "down" } else { "up" }
+            ),
+            InputEvent::Modifiers(modifiers) => format!("modifiers {}", modifiers.bits()),
+            InputEvent::CursorMoved { x, y } => format!("cursor {} {}", x, y),
+            InputEvent::Wheel { lines } => format!("wheel {}", lines),
+            InputEvent::MouseMotion { dx, dy } => format!("motion {} {}", dx, dy),
+            InputEvent::Focused(focused) => format!("focused {}", focused),
+        })
+    }
+
+    pub fn parse(words: &[&str]) -> Option<InputEvent> {
+        Some(match words {
+            ["button", name, state] => InputEvent::Button {
+                input: Input::parse(name)?,
+                pressed: match *state {
+                    "down" => true,
+                    "up" => false,
+                    _ => return None,
+                },
+            },
+            ["modifiers", bits] => {
+                InputEvent::Modifiers(ModifiersState::from_bits_truncate(bits.parse().ok()?))
+            }
+            ["cursor", x, y] => InputEvent::CursorMoved {
+                x: x.parse().ok()?,
+                y: y.parse().ok()?,
+            },
+            ["wheel", lines] => InputEvent::Wheel {
+                lines: lines.parse().ok()?,
+            },
+            ["motion", dx, dy] => InputEvent::MouseMotion {
+                dx: dx.parse().ok()?,
+                dy: dy.parse().ok()?,
+            },
+            ["focused", focused] => InputEvent::Focused(focused.parse().ok()?),
+            _ => return None,
+        })
+    }
+}
@@ -0,0 +1,197 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context};
+use winit::event::{DeviceEvent, WindowEvent};
+
+use crate::core::clock::Clock;
+
+use super::event::InputEvent;
+
+#[derive(Clone, Copy, Debug)]
+enum Entry {
+    // a frame started at the entry's time
+    Frame,
+    Event(InputEvent),
+}
+
+/// Frames and input events with their app time, in the order they happened.
+pub struct Recording {
+    entries: Vec<(f32, Entry)>,
+}
+
+impl Recording {
+    pub fn new() -> Self {
+        Recording {
+            entries: Vec::new(),
+        }
+    }
+
+    /// One entry per line: `<time> frame` or `<time> <event>`, see `InputEvent::to_text`.
+    pub fn save(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let mut text = String::from("# time frame | time event...\n");
+
+        for (time, entry) in &self.entries {
+            let line = match entry {
+                Entry::Frame => Some(String::from("frame")),
+                Entry::Event(event) => event.to_text(),
+            };
+
+            if let Some(line) = line {
+                text.push_str(&format!("{} {}\n", time, line));
+            }
+        }
+
+        fs::write(path, text)?;
+
+        Ok(())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read input recording {}", path.display()))?;
+
+        let mut entries = Vec::new();
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let invalid = || {
+                anyhow!(
+                    "{}:{}: invalid entry '{}'",
+                    path.display(),
+                    number + 1,
+                    line
+                )
+            };
+
+            let words = line.split_whitespace().collect::<Vec<_>>();
+            let time = words[0].parse::<f32>().map_err(|_| invalid())?;
+
+            let entry = match words[1..] {
+                ["frame"] => Entry::Frame,
+                ref event => Entry::Event(InputEvent::parse(event).ok_or_else(invalid)?),
+            };
+
+            entries.push((time, entry));
+        }
+
+        Ok(Recording { entries })
+    }
+}
+
+/// A frame starting, see `InputSource::tick`.
+pub struct Tick {
+    // seconds since the last frame
+    pub dt: f32,
+    // the recorded events that came before the frame when replaying, empty otherwise
+    pub events: Vec<InputEvent>,
+}
+
+/// Where input comes from: the window, the window while writing everything to a recording,
+/// or a recording with the window's input ignored.
+pub enum InputSource {
+    Live,
+    Record {
+        recording: Recording,
+        path: PathBuf,
+    },
+    Replay {
+        recording: Recording,
+        // index of the next entry
+        position: usize,
+    },
+}
+
+impl InputSource {
+    pub fn record(path: impl Into<PathBuf>) -> Self {
+        InputSource::Record {
+            recording: Recording::new(),
+            path: path.into(),
+        }
+    }
+
+    pub fn replay(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        Ok(InputSource::Replay {
+            recording: Recording::load(path)?,
+            position: 0,
+        })
+    }
+
+    /// An event from the window, passed on to be handled right away unless replaying.
+    pub fn input_event(&mut self, event: InputEvent, now: f32) -> Option<InputEvent> {
+        match self {
+            InputSource::Live => Some(event),
+            InputSource::Record { recording, .. } => {
+                recording.entries.push((now, Entry::Event(event)));
+                Some(event)
+            }
+            InputSource::Replay { .. } => None,
+        }
+    }
+
+    pub fn window_event(&mut self, event: &WindowEvent, now: f32) -> Option<InputEvent> {
+        InputEvent::from_window_event(event).and_then(|event| self.input_event(event, now))
+    }
+
+    pub fn device_event(&mut self, event: &DeviceEvent, now: f32) -> Option<InputEvent> {
+        InputEvent::from_device_event(event).and_then(|event| self.input_event(event, now))
+    }
+
+    /// Starts a new frame. A replay moves the clock to the next recorded frame and hands
+    /// back every event recorded before it, to be handled before the frame like the live
+    /// ones were, and returns None once the recording is exhausted. Events after the last
+    /// frame never reached one and are dropped.
+    pub fn tick(&mut self, clock: &mut Clock) -> Option<Tick> {
+        match self {
+            InputSource::Live => Some(Tick {
+                dt: clock.tick(),
+                events: Vec::new(),
+            }),
+            InputSource::Record { recording, .. } => {
+                let dt = clock.tick();
+                recording.entries.push((clock.now(), Entry::Frame));
+                Some(Tick {
+                    dt,
+                    events: Vec::new(),
+                })
+            }
+            InputSource::Replay {
+                recording,
+                position,
+            } => {
+                let mut events = Vec::new();
+                loop {
+                    let (time, entry) = recording.entries.get(*position)?;
+                    *position += 1;
+
+                    match entry {
+                        Entry::Event(event) => events.push(*event),
+                        Entry::Frame => {
+                            return Some(Tick {
+                                dt: clock.advance_to(*time),
+                                events,
+                            })
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Writes a recording to its file, does nothing for the other sources.
+    pub fn finish(&self) -> anyhow::Result<()> {
+        if let InputSource::Record { recording, path } = self {
+            recording.save(path)?;
+            log::info!("Input recorded to {}", path.display());
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,157 @@
+use bytemuck::Zeroable;
+use cgmath::{InnerSpace, Point3, Vector3};
+use wgpu::util::DeviceExt;
+
+use crate::core::debug::label;
+
+const NAME: &str = "Lights";
+
+// must match the array length in shader.wgsl
+pub const MAX_LIGHTS: usize = 8;
+
+#[derive(Clone, Copy, Debug)]
+pub enum Light {
+    // parallel rays, like sunlight
+    Directional {
+        // the way the light travels
+        direction: Vector3<f32>,
+        color: [f32; 3],
+        intensity: f32,
+    },
+    Point {
+        position: Point3<f32>,
+        color: [f32; 3],
+        intensity: f32,
+        // distance at which the light has faded out completely
+        range: f32,
+    },
+}
+
+impl Light {
+    fn to_raw(self) -> LightRaw {
+        let scale = |color: [f32; 3], intensity: f32| color.map(|channel| channel * intensity);
+
+        match self {
+            Light::Directional {
+                direction,
+                color,
+                intensity,
+            } => LightRaw {
+                position: direction.normalize().into(),
+                kind: 0,
+                color: scale(color, intensity),
+                range: 0.0,
+            },
+            Light::Point {
+                position,
+                color,
+                intensity,
+                range,
+            } => LightRaw {
+                position: position.into(),
+                kind: 1,
+                color: scale(color, intensity),
+                range,
+            },
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct LightRaw {
+    // the direction for directional lights
+    position: [f32; 3],
+    // 0 directional, 1 point
+    kind: u32,
+    // premultiplied by the intensity
+    color: [f32; 3],
+    range: f32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct LightsUniform {
+    ambient: [f32; 3],
+    count: u32,
+    lights: [LightRaw; MAX_LIGHTS],
+}
+
+/// The scene's lights in one uniform buffer, bound to group 3 of the lit pipelines.
+pub struct Lights {
+    // applied everywhere, scaled by each material's ambient color
+    pub ambient: [f32; 3],
+    pub lights: Vec<Light>,
+    layout: wgpu::BindGroupLayout,
+    buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+}
+
+impl Lights {
+    pub fn new(device: &wgpu::Device, ambient: [f32; 3], lights: Vec<Light>) -> Self {
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+            label: Some(&label(NAME, "light bind group layout")),
+        });
+
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&label(NAME, "light buffer")),
+            contents: bytemuck::bytes_of(&LightsUniform::zeroed()),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            }],
+            label: Some(&label(NAME, "light bind group")),
+        });
+
+        Lights {
+            ambient,
+            lights,
+            layout,
+            buffer,
+            bind_group,
+        }
+    }
+
+    pub fn bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.layout
+    }
+
+    /// Uploads the lights, anything past `MAX_LIGHTS` is dropped.
+    pub fn update(&self, queue: &wgpu::Queue) {
+        if self.lights.len() > MAX_LIGHTS {
+            log::warn!(
+                "{} lights, only the first {} are used",
+                self.lights.len(),
+                MAX_LIGHTS
+            );
+        }
+
+        let mut uniform = LightsUniform::zeroed();
+        uniform.ambient = self.ambient;
+        for (raw, light) in uniform.lights.iter_mut().zip(&self.lights) {
+            *raw = light.to_raw();
+            uniform.count += 1;
+        }
+
+        queue.write_buffer(&self.buffer, 0, bytemuck::bytes_of(&uniform));
+    }
+
+    pub fn bind<'rpass>(&'rpass self, render_pass: &mut wgpu::RenderPass<'rpass>) {
+        render_pass.set_bind_group(3, &self.bind_group, &[]);
+    }
+}
@@ -1,74 +1,42 @@
-use cgmath::SquareMatrix;
-use components::{char::Char, cube::Cube};
-use std::{borrow::Cow, time::SystemTime};
+use anyhow::{bail, Context};
+use components::{char::Char, cube::Cube, scene::SceneModel, PipelineInputs};
+use std::{borrow::Cow, path::PathBuf, time::Instant};
 use winit::{
-    event::{Event, KeyEvent, WindowEvent},
+    event::{Event, WindowEvent},
     event_loop::{ControlFlow, EventLoop},
-    keyboard::{KeyCode, PhysicalKey},
     window::Window,
 };
 
 use crate::{
+    camera::{
+        orbit::OrbitController,
+        path::CameraPath,
+        view::{ViewLayout, Views},
+        Camera, CameraController,
+    },
     components::pentagon::{Pentagon, Renderable},
-    core::texture::Texture,
+    core::{
+        bounds::BoundingSphere,
+        clock::Clock,
+        debug::label,
+        error::install_uncaptured_error_handler,
+        model::MaterialLayout,
+        picking::{PickEvent, Pickable, Picker, Ray},
+        profiler::{Profiler, Stage},
+        texture::{DepthMode, Texture},
+    },
+    input::{event::InputEvent, record::InputSource, ActionMap},
+    light::{Light, Lights},
+    resources::load_string,
 };
 
+mod camera;
 mod components;
 mod core;
+mod input;
+mod light;
 mod resources;
-
-#[rustfmt::skip]
-pub const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
-    1.0, 0.0, 0.0, 0.0,
-    0.0, 1.0, 0.0, 0.0,
-    0.0, 0.0, 0.5, 0.5,
-    0.0, 0.0, 0.0, 1.0,
-);
-
-pub struct Camera {
-    eye: cgmath::Point3<f32>,
-    target: cgmath::Point3<f32>,
-    up: cgmath::Vector3<f32>,
-    aspect: f32,
-    fovy: f32,
-    znear: f32,
-    zfar: f32,
-}
-
-impl Camera {
-    fn build_view_projection_matrix(&self) -> cgmath::Matrix4<f32> {
-        // 1.
-        let view = cgmath::Matrix4::look_at_rh(self.eye, self.target, self.up);
-        // 2.
-        let proj = cgmath::perspective(cgmath::Deg(self.fovy), self.aspect, self.znear, self.zfar);
-
-        // 3.
-        return OPENGL_TO_WGPU_MATRIX * proj * view;
-    }
-
-    fn update_aspect(&mut self, aspect: f32) {
-        self.aspect = aspect;
-    }
-
-    // TODO: replace it with a more complex control
-    fn update_eye(&mut self, new_eye_pos: cgmath::Point3<f32>) {
-        self.eye = new_eye_pos;
-    }
-}
-
-impl Default for Camera {
-    fn default() -> Self {
-        Camera {
-            eye: (0.0, 1.3, 6.0).into(),
-            target: (0.0, 0.0, 0.0).into(),
-            up: cgmath::Vector3::unit_y(),
-            aspect: 4.0 as f32 / 3.0 as f32,
-            fovy: 45.0,
-            znear: 0.1,
-            zfar: 100.0,
-        }
-    }
-}
+mod scene;
 
 // TODO: Move this to somewhere else
 #[repr(C)]
@@ -80,24 +48,70 @@ struct Vertex {
     tex_coords: [f32; 2],
 }
 
-#[repr(C)]
-struct CameraUniform {
-    view_proj: [[f32; 4]; 4],
+struct Options {
+    reverse_z: bool,
+    fixed_step: bool,
+    headless: bool,
+    record: Option<PathBuf>,
+    replay: Option<PathBuf>,
+    // a glTF file in res/ drawn alongside the demo objects
+    scene: Option<String>,
 }
 
-impl CameraUniform {
-    fn new() -> Self {
-        CameraUniform {
-            view_proj: cgmath::Matrix4::identity().into(),
+impl Options {
+    fn from_args() -> anyhow::Result<Self> {
+        let mut options = Options {
+            reverse_z: false,
+            fixed_step: false,
+            headless: false,
+            record: None,
+            replay: None,
+            scene: None,
+        };
+
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--reverse-z" => options.reverse_z = true,
+                "--fixed-step" => options.fixed_step = true,
+                "--headless" => options.headless = true,
+                "--record" => {
+                    options.record = Some(args.next().context("--record needs a file")?.into())
+                }
+                "--replay" => {
+                    options.replay = Some(args.next().context("--replay needs a file")?.into())
+                }
+                "--scene" => options.scene = Some(args.next().context("--scene needs a file")?),
+                _ => bail!("Unknown argument '{}'", arg),
+            }
+        }
+
+        if options.record.is_some() && options.replay.is_some() {
+            bail!("--record and --replay can't be combined");
+        }
+        if options.headless && options.replay.is_none() {
+            bail!("--headless needs --replay <file>");
         }
-    }
 
-    fn update_view_proj(&mut self, camera: &Camera) {
-        self.view_proj = camera.build_view_projection_matrix().into();
+        Ok(options)
     }
 }
 
-async fn run(event_loop: EventLoop<()>, window: Window) {
+async fn load_bindings() -> ActionMap {
+    let text = load_string("bindings.txt")
+        .await
+        .unwrap_or_else(|err| panic!("{:#}", err));
+
+    ActionMap::parse(&text, "bindings.txt").unwrap_or_else(|err| panic!("{:#}", err))
+}
+
+async fn run(
+    event_loop: EventLoop<()>,
+    window: Window,
+    options: Options,
+    mut input_source: InputSource,
+    mut clock: Clock,
+) {
     let size = window.inner_size();
 
     let instance = wgpu::Instance::default();
@@ -113,71 +127,59 @@ async fn run(event_loop: EventLoop<()>, window: Window) {
         .await
         .expect("Failed to find an appropriate adapter");
 
-    // Create the logical device and command queue
-    let (device, queue) = adapter
-        .request_device(
-            &wgpu::DeviceDescriptor {
-                label: None,
-                features: wgpu::Features::empty(),
-                // Make sure we use the texture resolution limits from the adapter, so we can support images the size of the swapchain.
-                limits: wgpu::Limits::downlevel_webgl2_defaults()
-                    .using_resolution(adapter.limits()),
-            },
-            None,
-        )
-        .await
-        .expect("Failed to create device");
-
-    // Load the shaders from disk
-    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-        label: None,
-        source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("shader.wgsl"))),
-    });
-
     let swapchain_capabilities = surface.get_capabilities(&adapter);
     let swapchain_format = swapchain_capabilities.formats[0];
 
-    let mut camera = Camera::default();
+    let mut demo = Demo::new(
+        &adapter,
+        swapchain_format,
+        &options,
+        size.width,
+        size.height,
+    )
+    .await;
+    let mut controls = Controls::new(load_bindings().await, demo.camera());
 
-    let mut pentagon_model = Pentagon::new(&device, &shader, &swapchain_format, &camera, &queue);
-
-    let mut cube_model = Cube::new(&device, &shader, &swapchain_format, &camera, &queue).await;
-
-    let texture_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-        label: None,
-        source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("texture.wgsl"))),
-    });
+    let texture_shader = demo
+        .device
+        .create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("texture.wgsl"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("texture.wgsl"))),
+        });
 
     let texture_bind_group_layout =
-        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            entries: &[
-                wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::FRAGMENT,
-                    ty: wgpu::BindingType::Texture {
-                        multisampled: false,
-                        view_dimension: wgpu::TextureViewDimension::D2,
-                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+        demo.device
+            .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            multisampled: false,
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        },
+                        count: None,
                     },
-                    count: None,
-                },
-                wgpu::BindGroupLayoutEntry {
-                    binding: 1,
-                    visibility: wgpu::ShaderStages::FRAGMENT,
-                    // This should match the filterable field of the
-                    // corresponding Texture entry above.
-                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-                    count: None,
-                },
-            ],
-            label: Some("texture_bind_group_layout"),
-        });
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        // This should match the filterable field of the
+                        // corresponding Texture entry above.
+                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                        count: None,
+                    },
+                ],
+                label: Some(&label("Char", "texture bind group layout")),
+            });
 
-    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-        label: None,
-        bind_group_layouts: &[&texture_bind_group_layout],
-        push_constant_ranges: &[],
-    });
+    let pipeline_layout = demo
+        .device
+        .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some(&label("Char", "pipeline layout")),
+            bind_group_layouts: &[&texture_bind_group_layout],
+            push_constant_ranges: &[],
+        });
 
     let mut config = wgpu::SurfaceConfiguration {
         usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
@@ -190,24 +192,22 @@ async fn run(event_loop: EventLoop<()>, window: Window) {
     };
 
     let mut depth_texture: Texture =
-        Texture::create_depth_texture(&device, &config, "Depth Texture");
+        Texture::create_depth_texture(&demo.device, &config, demo.depth_mode, "Depth texture");
 
     let mut char = Char::new(
-        &device,
-        &queue,
+        &demo.device,
+        &demo.queue,
         &texture_shader,
         &pipeline_layout,
         &texture_bind_group_layout,
         &config,
-    );
-
-    surface.configure(&device, &config);
-
-    pentagon_model.prepare(&queue, None, 0.0);
+    )
+    .await
+    .unwrap_or_else(|err| panic!("{:#}", err));
 
-    cube_model.prepare(&queue, None, 0.0);
+    surface.configure(&demo.device, &config);
 
-    char.prepare(&queue, None, 0.0);
+    char.prepare(&demo.queue, None, 0.0);
 
     // let mut hello_text = Text::new(&device, &queue, swapchain_format);
 
@@ -221,7 +221,7 @@ async fn run(event_loop: EventLoop<()>, window: Window) {
     //     .buffer
     //     .shape_until_scroll(&mut hello_text.font_system);
 
-    let now = SystemTime::now();
+    let mut last_profiler_log = Instant::now();
 
     // let mut modifiers = ModifiersState::default();
 
@@ -231,7 +231,16 @@ async fn run(event_loop: EventLoop<()>, window: Window) {
         // Have the closure take ownership of the resources.
         // `event_loop.run` never returns, therefore we must do this to ensure
         // the resources are properly cleaned up.
-        let _ = (&instance, &adapter, &shader, &pipeline_layout);
+        let _ = (&instance, &adapter, &pipeline_layout);
+
+        let input_event = match &event {
+            Event::WindowEvent { event, .. } => input_source.window_event(event, clock.now()),
+            Event::DeviceEvent { event, .. } => input_source.device_event(event, clock.now()),
+            _ => None,
+        };
+        if let Some(input_event) = input_event {
+            controls.process(&input_event);
+        }
 
         match event {
             Event::WindowEvent {
@@ -241,15 +250,16 @@ async fn run(event_loop: EventLoop<()>, window: Window) {
                 // Reconfigure the surface with the new size
                 config.width = size.width;
                 config.height = size.height;
-                surface.configure(&device, &config);
+                surface.configure(&demo.device, &config);
 
                 depth_texture.recreate_texture(Texture::create_depth_texture(
-                    &device,
+                    &demo.device,
                     &config,
+                    demo.depth_mode,
                     "Depth texture",
                 ));
 
-                camera.update_aspect(size.width as f32 / size.height as f32);
+                demo.views.resize(size.width, size.height);
                 // On macos the window needs to be redrawn manually after resizing
                 window.request_redraw();
             }
@@ -257,10 +267,45 @@ async fn run(event_loop: EventLoop<()>, window: Window) {
                 event: WindowEvent::RedrawRequested,
                 ..
             } => {
-                let elapsed_time: f32 = now.elapsed().unwrap().as_secs_f32();
+                let Some(tick) = input_source.tick(&mut clock) else {
+                    log::info!("Replay finished after {:.2}s", clock.now());
+                    elwt.exit();
+                    return;
+                };
+                // replayed events arrive where the live ones did, right before their frame
+                for input_event in &tick.events {
+                    controls.process(input_event);
+                }
+                let elapsed_time = clock.now();
 
-                pentagon_model.prepare(&queue, Some(&camera), elapsed_time);
-                cube_model.prepare(&queue, Some(&camera), elapsed_time);
+                controls.update(&mut demo, tick.dt, elapsed_time);
+                let Demo {
+                    device,
+                    queue,
+                    depth_mode,
+                    views,
+                    lights,
+                    pentagon_model,
+                    cube_model,
+                    scene_model,
+                    profiler,
+                    ..
+                } = &mut demo;
+                views.update(queue);
+
+                // the views share one instance buffer, so only cull when a single camera draws it
+                let cull_camera = match views.view_layout() {
+                    ViewLayout::Single => Some(&views.primary().camera),
+                    ViewLayout::Quad => None,
+                };
+
+                let prepare_start = Instant::now();
+                pentagon_model.prepare(queue, cull_camera, elapsed_time);
+                cube_model.prepare(queue, cull_camera, elapsed_time);
+                if let Some(scene_model) = scene_model {
+                    scene_model.prepare(queue, cull_camera, elapsed_time);
+                }
+                profiler.record_cpu(Stage::Prepare, prepare_start.elapsed());
 
                 // hello_text.prepare(
                 //     &device,
@@ -277,11 +322,15 @@ async fn run(event_loop: EventLoop<()>, window: Window) {
                 let view = frame
                     .texture
                     .create_view(&wgpu::TextureViewDescriptor::default());
-                let mut encoder =
-                    device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+
+                let encode_start = Instant::now();
+                profiler.begin_frame(device);
+                let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("Frame encoder"),
+                });
                 {
                     let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                        label: None,
+                        label: Some("Scene pass"),
                         color_attachments: &[Some(wgpu::RenderPassColorAttachment {
                             view: &view,
                             resolve_target: None,
@@ -293,21 +342,30 @@ async fn run(event_loop: EventLoop<()>, window: Window) {
                         depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
                             view: depth_texture.get_view(),
                             depth_ops: Some(wgpu::Operations {
-                                load: wgpu::LoadOp::Clear(1.0),
+                                load: wgpu::LoadOp::Clear(depth_mode.clear_value()),
                                 store: wgpu::StoreOp::Store,
                             }),
                             stencil_ops: None,
                         }),
-                        timestamp_writes: None,
+                        timestamp_writes: profiler.timestamp_writes("scene"),
                         occlusion_query_set: None,
                     });
-                    pentagon_model.render(&mut rpass);
-                    cube_model.render(&mut rpass);
+                    for view in views.iter() {
+                        rpass.push_debug_group(&view.name);
+                        view.bind(&mut rpass, config.width, config.height);
+                        lights.bind(&mut rpass);
+                        pentagon_model.render(&mut rpass);
+                        cube_model.render(&mut rpass);
+                        if let Some(scene_model) = scene_model.as_ref() {
+                            scene_model.render(&mut rpass);
+                        }
+                        rpass.pop_debug_group();
+                    }
                 }
 
                 {
                     let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                        label: None,
+                        label: Some("UI pass"),
                         color_attachments: &[Some(wgpu::RenderPassColorAttachment {
                             view: &view,
                             resolve_target: None,
@@ -317,28 +375,27 @@ async fn run(event_loop: EventLoop<()>, window: Window) {
                             },
                         })],
                         depth_stencil_attachment: None,
-                        timestamp_writes: None,
+                        timestamp_writes: profiler.timestamp_writes("ui"),
                         occlusion_query_set: None,
                     });
                     char.render(&mut rpass);
                     // hello_text.render(&mut rpass);
                 }
 
+                profiler.resolve(&mut encoder);
                 queue.submit(Some(encoder.finish()));
+                profiler.end_frame();
+                profiler.record_cpu(Stage::Encode, encode_start.elapsed());
+
+                let present_start = Instant::now();
                 frame.present();
+                profiler.record_cpu(Stage::Present, present_start.elapsed());
+
+                if last_profiler_log.elapsed().as_secs() >= 5 {
+                    profiler.log();
+                    last_profiler_log = Instant::now();
+                }
             }
-            Event::WindowEvent {
-                event:
-                    WindowEvent::KeyboardInput {
-                        event:
-                            KeyEvent {
-                                physical_key: PhysicalKey::Code(KeyCode::Escape),
-                                ..
-                            },
-                        ..
-                    },
-                ..
-            } => elwt.exit(),
 
             // Event::WindowEvent {
             //     event:
@@ -383,17 +440,436 @@ async fn run(event_loop: EventLoop<()>, window: Window) {
             //     }
             //     _ => {}
             // },
-            // Event::MainEventsCleared => {
-            //     window.request_redraw();
-            // }
+            // after the frame's RedrawRequested, so actions act on the camera the frame drew
+            Event::AboutToWait => {
+                if !controls.actions(&mut demo, Some(&window), clock.now()) {
+                    elwt.exit();
+                }
+
+                window.request_redraw();
+            }
+            Event::LoopExiting => {
+                if let Err(err) = input_source.finish() {
+                    log::error!("Failed to save input recording: {:#}", err);
+                }
+            }
             _ => {}
         }
     });
 }
 
+// nothing sizes the views of a headless replay, so they get winit's usual window size
+const HEADLESS_SIZE: (u32, u32) = (800, 600);
+
+/// What the frame's actions act on besides the input and camera control: the primary
+/// camera, the views and the renderables in them.
+trait Frontend {
+    fn camera(&mut self) -> &mut Camera;
+    fn toggle_view_layout(&mut self);
+    /// The ray through a cursor position in physical pixels, None outside every view.
+    fn ray(&self, x: f64, y: f64) -> Option<Ray>;
+    /// Picks the nearest renderable, notifying the pick listeners.
+    fn pick(&mut self, ray: &Ray) -> Option<PickEvent>;
+    /// What `focus` frames: the picked instance, or everything when nothing is picked.
+    fn focus_bounds(&self, selection: Option<PickEvent>) -> Option<BoundingSphere>;
+    fn export_timings(&mut self);
+    fn next_animation(&mut self);
+}
+
+// the device, views, lights and renderables, set up the same way for the window and for
+// headless replays
+struct Demo {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    depth_mode: DepthMode,
+    material_layout: MaterialLayout,
+    views: Views,
+    lights: Lights,
+    pentagon_model: Pentagon,
+    cube_model: Cube,
+    scene_model: Option<SceneModel>,
+    profiler: Profiler,
+    picker: Picker,
+}
+
+impl Demo {
+    async fn new(
+        adapter: &wgpu::Adapter,
+        color_format: wgpu::TextureFormat,
+        options: &Options,
+        width: u32,
+        height: u32,
+    ) -> Self {
+        // Create the logical device and command queue
+        let (device, queue) = adapter
+            .request_device(
+                &wgpu::DeviceDescriptor {
+                    label: Some("Device"),
+                    // Timestamp queries are optional, the profiler falls back to CPU timings only
+                    features: adapter.features() & wgpu::Features::TIMESTAMP_QUERY,
+                    // Make sure we use the texture resolution limits from the adapter, so we can support images the size of the swapchain.
+                    limits: wgpu::Limits::downlevel_webgl2_defaults()
+                        .using_resolution(adapter.limits()),
+                },
+                None,
+            )
+            .await
+            .expect("Failed to create device");
+
+        install_uncaptured_error_handler(&device);
+
+        // Load the shaders from disk
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("shader.wgsl"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("shader.wgsl"))),
+        });
+
+        let depth_mode = if options.reverse_z {
+            DepthMode::ReverseZ
+        } else {
+            DepthMode::Standard
+        };
+
+        let camera = Camera {
+            depth_mode,
+            ..Camera::default()
+        };
+        let mut views = Views::new(&device, camera, width, height);
+
+        let mut lights = Lights::new(
+            &device,
+            [0.1, 0.1, 0.1],
+            vec![
+                Light::Directional {
+                    direction: cgmath::Vector3::new(-0.3, -1.0, -0.5),
+                    color: [1.0, 0.98, 0.92],
+                    intensity: 1.0,
+                },
+                Light::Point {
+                    position: cgmath::Point3::new(0.0, 2.0, 0.0),
+                    color: [1.0, 0.6, 0.3],
+                    intensity: 2.0,
+                    range: 20.0,
+                },
+            ],
+        );
+
+        let material_layout =
+            MaterialLayout::new(&device, &queue).unwrap_or_else(|err| panic!("{:#}", err));
+        let pipeline_inputs = PipelineInputs {
+            shader: &shader,
+            swapchain_format: color_format,
+            material_layout: &material_layout,
+            camera_bind_group_layout: views.bind_group_layout(),
+            light_bind_group_layout: lights.bind_group_layout(),
+            depth_mode,
+        };
+
+        // a scene that fails to load is skipped, the demo objects still draw
+        let scene_model = match &options.scene {
+            Some(file_name) => SceneModel::new(file_name, &device, &pipeline_inputs, &queue)
+                .await
+                .map_err(|err| log::error!("Failed to load scene: {:#}", err))
+                .ok(),
+            None => None,
+        };
+        let mut pentagon_model = Pentagon::new(&device, &pipeline_inputs, &queue)
+            .await
+            .unwrap_or_else(|err| panic!("{:#}", err));
+
+        let mut cube_model = Cube::new(&device, &pipeline_inputs, &queue)
+            .await
+            .unwrap_or_else(|err| panic!("{:#}", err));
+
+        if let Some(scene_model) = &scene_model {
+            lights.lights.extend(scene_model.scene.lights());
+
+            // start from the scene's first camera if it has one
+            let camera = &mut views.primary_mut().camera;
+            if let Some(scene_camera) = scene_model.scene.cameras(camera).into_iter().next() {
+                *camera = scene_camera;
+                // the surface decides the aspect, not the file, as it does after every resize
+                views.resize(width, height);
+            }
+        }
+        lights.update(&queue);
+
+        pentagon_model.prepare(&queue, None, 0.0);
+
+        cube_model.prepare(&queue, None, 0.0);
+
+        let profiler = Profiler::new(&device, &queue, 240, &["scene", "ui"]);
+
+        let mut picker = Picker::new();
+        picker.on_pick(|event| {
+            log::info!(
+                "Picked {} instance {} at ({:.2}, {:.2}, {:.2}), {:.2} units away",
+                event.renderable,
+                event.instance,
+                event.point.x,
+                event.point.y,
+                event.point.z,
+                event.distance
+            );
+        });
+
+        Demo {
+            device,
+            queue,
+            depth_mode,
+            material_layout,
+            views,
+            lights,
+            pentagon_model,
+            cube_model,
+            scene_model,
+            profiler,
+            picker,
+        }
+    }
+}
+
+impl Frontend for Demo {
+    fn camera(&mut self) -> &mut Camera {
+        &mut self.views.primary_mut().camera
+    }
+
+    fn toggle_view_layout(&mut self) {
+        let view_layout = match self.views.view_layout() {
+            ViewLayout::Single => ViewLayout::Quad,
+            ViewLayout::Quad => ViewLayout::Single,
+        };
+        self.views.set_view_layout(&self.device, view_layout);
+    }
+
+    fn ray(&self, x: f64, y: f64) -> Option<Ray> {
+        self.views.ray(x, y)
+    }
+
+    fn pick(&mut self, ray: &Ray) -> Option<PickEvent> {
+        let targets: &mut [&mut dyn Pickable] =
+            &mut [&mut self.pentagon_model, &mut self.cube_model];
+        self.picker.pick(ray, targets)
+    }
+
+    fn focus_bounds(&self, selection: Option<PickEvent>) -> Option<BoundingSphere> {
+        let targets: [&dyn Pickable; 2] = [&self.pentagon_model, &self.cube_model];
+        match selection {
+            Some(picked) => targets
+                .iter()
+                .find(|target| target.name() == picked.renderable)
+                .and_then(|target| target.bounding_sphere(picked.instance)),
+            None => self
+                .scene_model
+                .as_ref()
+                .and_then(|scene_model| scene_model.scene.bounding_sphere()),
+        }
+    }
+
+    fn export_timings(&mut self) {
+        match self.profiler.export_csv("frame_timings.csv") {
+            Ok(()) => log::info!("Frame timings written to frame_timings.csv"),
+            Err(err) => log::error!("Failed to export frame timings: {}", err),
+        }
+    }
+
+    fn next_animation(&mut self) {
+        if let Some(scene_model) = &mut self.scene_model {
+            scene_model.next_animation();
+        }
+    }
+}
+
+/// The input state and camera control, driven a frame at a time in the event loop's order:
+/// the events before the frame, the frame's camera update, then its actions.
+struct Controls {
+    input: ActionMap,
+    camera_controller: CameraController,
+    camera_path: CameraPath,
+    // where save_path and load_path keep the camera path
+    path_file: PathBuf,
+    // the last pick, what `focus` frames
+    selection: Option<PickEvent>,
+}
+
+impl Controls {
+    fn new(input: ActionMap, camera: &Camera) -> Self {
+        Controls {
+            input,
+            camera_controller: CameraController::Orbit(OrbitController::new(camera)),
+            camera_path: CameraPath::new(),
+            path_file: PathBuf::from("camera_path.txt"),
+            selection: None,
+        }
+    }
+
+    fn process(&mut self, input_event: &InputEvent) {
+        self.input.process(input_event);
+        self.camera_controller.process(input_event);
+    }
+
+    // the camera part of a frame, before the views are uploaded
+    fn update(&mut self, frontend: &mut impl Frontend, dt: f32, now: f32) {
+        let camera = frontend.camera();
+        if self.camera_path.is_playing() {
+            if !self.camera_path.update(camera, now) {
+                self.camera_controller.reset(camera);
+            }
+        } else {
+            self.camera_controller.update(camera, dt, &self.input);
+        }
+    }
+
+    // after the frame, so actions act on the camera it drew; false once quitting. The
+    // window, when there is one, has its cursor grabbed while flying.
+    fn actions(&mut self, frontend: &mut impl Frontend, window: Option<&Window>, now: f32) -> bool {
+        let input = &self.input;
+
+        if input.pressed("export_timings") {
+            frontend.export_timings();
+        }
+        if input.pressed("toggle_controller") {
+            self.camera_controller.toggle(frontend.camera(), window);
+        }
+        if input.pressed("toggle_projection") {
+            frontend.camera().toggle_projection();
+        }
+        if input.pressed("toggle_view_layout") {
+            frontend.toggle_view_layout();
+        }
+        if input.pressed("record_keyframe") {
+            self.camera_path.record(frontend.camera(), 2.0);
+            log::info!(
+                "Recorded camera keyframe {}",
+                self.camera_path.keyframes().len()
+            );
+        }
+        if input.pressed("clear_path") {
+            self.camera_path.clear();
+            log::info!("Camera path cleared");
+        }
+        if input.pressed("toggle_playback") {
+            if self.camera_path.is_playing() {
+                self.camera_path.stop();
+                self.camera_controller.reset(frontend.camera());
+            } else {
+                self.camera_path.play(now);
+            }
+        }
+        if input.pressed("save_path") {
+            match self.camera_path.save(&self.path_file) {
+                Ok(()) => log::info!("Camera path written to {}", self.path_file.display()),
+                Err(err) => log::error!("Failed to save camera path: {}", err),
+            }
+        }
+        if input.pressed("load_path") {
+            match self.camera_path.load(&self.path_file) {
+                Ok(()) => log::info!("Camera path loaded from {}", self.path_file.display()),
+                Err(err) => log::error!("Failed to load camera path: {:#}", err),
+            }
+        }
+        // like a click, picking happens when the button comes back up
+        if input.released("pick") {
+            let (x, y) = input.cursor_position();
+            if let Some(ray) = frontend.ray(x, y) {
+                self.selection = frontend.pick(&ray);
+            }
+        }
+        if input.pressed("focus") {
+            if let Some(sphere) = frontend.focus_bounds(self.selection) {
+                let camera = frontend.camera();
+                camera.frame(&sphere);
+                self.camera_controller.reset(camera);
+            }
+        }
+        if input.pressed("next_animation") {
+            frontend.next_animation();
+        }
+
+        let quit = input.pressed("quit");
+        self.input.end_frame();
+
+        !quit
+    }
+
+    // a whole frame of a headless replay, None once the replay is over
+    fn frame(
+        &mut self,
+        frontend: &mut impl Frontend,
+        input_source: &mut InputSource,
+        clock: &mut Clock,
+    ) -> Option<bool> {
+        let tick = input_source.tick(clock)?;
+        for input_event in &tick.events {
+            self.process(input_event);
+        }
+        self.update(frontend, tick.dt, clock.now());
+
+        Some(self.actions(frontend, None, clock.now()))
+    }
+}
+
+/// Replays a recording without a window, through the same setup, camera updates and actions
+/// as the windowed app but drawing nothing, and logs where the camera ends up. Two runs of the
+/// same recording with `--fixed-step` end in the same place.
+async fn run_headless(options: Options, mut input_source: InputSource, mut clock: Clock) {
+    let instance = wgpu::Instance::default();
+    let adapter = instance
+        .request_adapter(&wgpu::RequestAdapterOptions::default())
+        .await
+        .expect("Failed to find an appropriate adapter");
+
+    // nothing is presented, the pipelines only need some color format to target
+    let (width, height) = HEADLESS_SIZE;
+    let mut demo = Demo::new(
+        &adapter,
+        wgpu::TextureFormat::Rgba8UnormSrgb,
+        &options,
+        width,
+        height,
+    )
+    .await;
+    let mut controls = Controls::new(load_bindings().await, demo.camera());
+    let mut frames = 0;
+
+    while let Some(running) = controls.frame(&mut demo, &mut input_source, &mut clock) {
+        frames += 1;
+        if !running {
+            break;
+        }
+    }
+
+    let camera = demo.camera();
+    log::info!(
+        "Replayed {} frames in {:.2}s, camera eye {:?} target {:?}",
+        frames,
+        clock.now(),
+        camera.eye,
+        camera.target
+    );
+}
+
 fn main() {
     env_logger::init();
 
+    let options = Options::from_args().unwrap_or_else(|err| panic!("{:#}", err));
+
+    let input_source = match (&options.record, &options.replay) {
+        (Some(path), _) => InputSource::record(path),
+        (None, Some(path)) => InputSource::replay(path).unwrap_or_else(|err| panic!("{:#}", err)),
+        (None, None) => InputSource::Live,
+    };
+    let clock = if options.fixed_step {
+        Clock::fixed(1.0 / 60.0)
+    } else {
+        Clock::real()
+    };
+
+    if options.headless {
+        pollster::block_on(run_headless(options, input_source, clock));
+        return;
+    }
+
     let event_loop = EventLoop::new().unwrap();
     // let window = winit::window::WindowBuilder::new()
     //     .with_title("demo-1")
@@ -401,5 +877,191 @@ fn main() {
     //     .unwrap();
 
     let window = winit::window::Window::new(&event_loop).unwrap();
-    pollster::block_on(run(event_loop, window));
+    pollster::block_on(run(event_loop, window, options, input_source, clock));
+}
+
+#[cfg(test)]
+mod tests {
+    use winit::{event::MouseButton, keyboard::KeyCode};
+
+    use super::*;
+    use crate::input::Input;
+
+    fn bindings() -> ActionMap {
+        ActionMap::parse(include_str!("../res/bindings.txt"), "bindings.txt").unwrap()
+    }
+
+    fn button(input: Input, pressed: bool) -> InputEvent {
+        InputEvent::Button { input, pressed }
+    }
+
+    // stands in for the demo: every pick hits 5 units ahead, focus frames what was picked
+    struct Stage {
+        camera: Camera,
+        view_layout: ViewLayout,
+    }
+
+    impl Stage {
+        fn new() -> Self {
+            Stage {
+                camera: Camera::default(),
+                view_layout: ViewLayout::Single,
+            }
+        }
+    }
+
+    impl Frontend for Stage {
+        fn camera(&mut self) -> &mut Camera {
+            &mut self.camera
+        }
+
+        fn toggle_view_layout(&mut self) {
+            self.view_layout = match self.view_layout {
+                ViewLayout::Single => ViewLayout::Quad,
+                ViewLayout::Quad => ViewLayout::Single,
+            };
+        }
+
+        fn ray(&self, x: f64, y: f64) -> Option<Ray> {
+            let (width, height) = HEADLESS_SIZE;
+            let ndc_x = x as f32 / width as f32 * 2.0 - 1.0;
+            let ndc_y = 1.0 - y as f32 / height as f32 * 2.0;
+            Some(self.camera.ray(ndc_x, ndc_y))
+        }
+
+        fn pick(&mut self, ray: &Ray) -> Option<PickEvent> {
+            Some(PickEvent {
+                renderable: "stage",
+                instance: 0,
+                distance: 5.0,
+                point: ray.at(5.0),
+            })
+        }
+
+        fn focus_bounds(&self, selection: Option<PickEvent>) -> Option<BoundingSphere> {
+            selection.map(|picked| BoundingSphere {
+                center: picked.point,
+                radius: 1.0,
+            })
+        }
+
+        fn export_timings(&mut self) {}
+
+        fn next_animation(&mut self) {}
+    }
+
+    // per frame, the events arriving before it
+    fn session() -> Vec<Vec<InputEvent>> {
+        let key = |code, pressed| button(Input::Key(code), pressed);
+        let mouse = |mouse_button, pressed| button(Input::Mouse(mouse_button), pressed);
+        let motion = InputEvent::MouseMotion { dx: 12.0, dy: -5.0 };
+
+        vec![
+            // before the first frame, e.g. focus and the cursor entering the window
+            vec![
+                InputEvent::Focused(true),
+                mouse(MouseButton::Left, true),
+                motion,
+            ],
+            vec![motion],
+            vec![mouse(MouseButton::Left, false), key(KeyCode::KeyK, true)],
+            vec![key(KeyCode::KeyK, false), InputEvent::Wheel { lines: 2.0 }],
+            // the one keyframe survives a save, clear and load
+            vec![key(KeyCode::F5, true)],
+            vec![key(KeyCode::F5, false), key(KeyCode::KeyJ, true)],
+            vec![key(KeyCode::KeyJ, false), key(KeyCode::F9, true)],
+            vec![key(KeyCode::F9, false), key(KeyCode::KeyV, true)],
+            vec![
+                key(KeyCode::KeyV, false),
+                InputEvent::CursorMoved { x: 600.0, y: 150.0 },
+                mouse(MouseButton::Right, true),
+            ],
+            vec![mouse(MouseButton::Right, false)],
+            vec![key(KeyCode::KeyF, true)],
+            vec![key(KeyCode::KeyF, false), key(KeyCode::Tab, true)],
+            vec![key(KeyCode::Tab, false), key(KeyCode::KeyW, true), motion],
+            vec![],
+            vec![key(KeyCode::KeyW, false), key(KeyCode::KeyP, true)],
+            vec![key(KeyCode::KeyP, false), key(KeyCode::KeyL, true)],
+            vec![key(KeyCode::KeyL, false)],
+            vec![],
+        ]
+    }
+
+    // what each frame ends with
+    type State = (
+        cgmath::Point3<f32>,
+        cgmath::Point3<f32>,
+        ViewLayout,
+        usize,
+        Option<cgmath::Point3<f32>>,
+    );
+
+    fn state(controls: &Controls, stage: &Stage) -> State {
+        (
+            stage.camera.eye,
+            stage.camera.target,
+            stage.view_layout,
+            controls.camera_path.keyframes().len(),
+            controls.selection.map(|picked| picked.point),
+        )
+    }
+
+    fn controls_saving_to(path_file: &std::path::Path, stage: &Stage) -> Controls {
+        Controls {
+            path_file: path_file.to_path_buf(),
+            ..Controls::new(bindings(), &stage.camera)
+        }
+    }
+
+    #[test]
+    fn replay_matches_the_recorded_session() {
+        let temp =
+            |name: &str| std::env::temp_dir().join(format!("{}-{}.txt", name, std::process::id()));
+        let (path, path_file) = (temp("replay"), temp("replay-path"));
+
+        // recorded the way the event loop runs: events as they arrive, then the frame
+        let mut input_source = InputSource::record(&path);
+        let mut clock = Clock::fixed(1.0 / 60.0);
+        let mut stage = Stage::new();
+        let mut controls = controls_saving_to(&path_file, &stage);
+        let mut recorded = Vec::new();
+        for events in session() {
+            for event in events {
+                if let Some(event) = input_source.input_event(event, clock.now()) {
+                    controls.process(&event);
+                }
+            }
+            let tick = input_source.tick(&mut clock).unwrap();
+            assert!(tick.events.is_empty());
+            controls.update(&mut stage, tick.dt, clock.now());
+            assert!(controls.actions(&mut stage, None, clock.now()));
+            recorded.push(state(&controls, &stage));
+        }
+        input_source.finish().unwrap();
+
+        // the session moved the camera and went through every action, or the comparison
+        // proves nothing
+        assert_ne!(recorded[0].0, Camera::default().eye);
+        assert!(recorded.windows(2).any(|pair| pair[0].0 != pair[1].0));
+        let [saved, cleared, loaded] = [4, 5, 6].map(|frame| recorded[frame].3);
+        assert_eq!((saved, cleared, loaded), (1, 0, 1));
+        assert_eq!(recorded[7].2, ViewLayout::Quad);
+        let picked = recorded[9].4.unwrap();
+        assert_eq!(recorded[10].1, picked);
+
+        let mut input_source = InputSource::replay(&path).unwrap();
+        let mut clock = Clock::fixed(1.0 / 60.0);
+        let mut stage = Stage::new();
+        let mut controls = controls_saving_to(&path_file, &stage);
+        let mut replayed = Vec::new();
+        while let Some(running) = controls.frame(&mut stage, &mut input_source, &mut clock) {
+            assert!(running);
+            replayed.push(state(&controls, &stage));
+        }
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(&path_file).unwrap();
+
+        assert_eq!(replayed, recorded);
+    }
 }
@@ -1,35 +1,47 @@
 use std::io::{BufReader, Cursor};
 
-use wgpu::{self, util::DeviceExt};
-
 use crate::core::{
-    model::{self, Material},
-    texture,
+    debug::{asset_scope, label},
+    error::ResourceError,
+    geometry::{self, compact, generate_normals, generate_tangents, simplify},
+    model::{
+        self, Lod, LodThreshold, Material, MaterialLayout, MaterialParams, MaterialTextures, Mesh,
+        ModelVertex, Shading,
+    },
+    texture::{self, ColorSpace},
 };
 
-pub async fn load_binary(file_name: &str) -> anyhow::Result<Vec<u8>> {
+mod gltf;
+
+pub use self::gltf::load_gltf;
+
+// meshes without normals get hard edges where faces meet at more than this (degrees)
+const NORMAL_CREASE_ANGLE: f32 = 60.0;
+
+pub async fn load_binary(file_name: &str) -> Result<Vec<u8>, ResourceError> {
     let path = std::path::Path::new(env!("OUT_DIR"))
         .join("res")
         .join(file_name);
-    let data = std::fs::read(path)?;
+    let data = std::fs::read(path).map_err(|err| ResourceError::read(file_name, err))?;
 
     Ok(data)
 }
 
-pub async fn load_string(file_name: &str) -> anyhow::Result<String> {
+pub async fn load_string(file_name: &str) -> Result<String, ResourceError> {
     let path = std::path::Path::new(env!("OUT_DIR"))
         .join("res")
         .join(file_name);
-    let data = std::fs::read_to_string(path).unwrap();
+    let data = std::fs::read_to_string(path).map_err(|err| ResourceError::read(file_name, err))?;
 
     Ok(data)
 }
 
 pub async fn load_texture(
     file_name: &str,
+    color_space: ColorSpace,
     device: &wgpu::Device,
     queue: &wgpu::Queue,
-) -> anyhow::Result<texture::Texture> {
+) -> Result<texture::Texture, ResourceError> {
     let data = load_binary(file_name).await?;
 
     texture::Texture::from_bytes(
@@ -37,17 +49,122 @@ pub async fn load_texture(
         queue,
         data,
         file_name,
+        color_space,
         None,
-        texture::Texture::create_sampler(device, None),
+        texture::Texture::create_sampler(device, texture::SamplerOptions::default()),
     )
 }
 
+/// Where a level of detail comes from.
+#[derive(Clone, Debug)]
+pub enum LodSource {
+    // the model's meshes simplified to about this fraction of their triangles
+    Simplify(f32),
+    // another OBJ file, with its own materials
+    #[allow(dead_code)]
+    File(String),
+}
+
+/// What `load_model` does on top of loading the file.
+#[derive(Clone, Debug, Default)]
+pub struct ModelOptions {
+    // coarser levels in the order they take over
+    pub lods: Vec<(LodSource, LodThreshold)>,
+    // run `geometry::optimize` on every mesh and use 16-bit indices where they fit
+    pub optimize: bool,
+}
+
+/// Loads an OBJ file and the MTL files it references, plus the levels of detail `options`
+/// asks for. Only a missing or unparsable OBJ is an error, broken materials and textures
+/// are logged and replaced by fallbacks.
 pub async fn load_model(
     file_name: &str,
     device: &wgpu::Device,
     queue: &wgpu::Queue,
-    layout: &wgpu::BindGroupLayout,
-) -> anyhow::Result<model::Model> {
+    material_layout: &MaterialLayout,
+    options: &ModelOptions,
+) -> Result<model::Model, ResourceError> {
+    let (mut model, geometry) =
+        load_obj(file_name, device, queue, material_layout, options.optimize).await?;
+
+    let triangles = |meshes: &[Mesh]| meshes.iter().map(|mesh| mesh.indices.len() / 3).sum();
+    let mut coarsest: usize = triangles(&model.meshes);
+
+    for (level, (source, threshold)) in options.lods.iter().enumerate() {
+        let lod = match source {
+            LodSource::Simplify(ratio) => {
+                // simplified on the CPU first, only levels that are kept get uploaded
+                let simplified = geometry
+                    .iter()
+                    .map(|(vertices, indices)| {
+                        let (mut vertices, mut indices) =
+                            compact(vertices, &simplify(vertices, indices, *ratio));
+                        let index_format =
+                            finish_geometry(&mut vertices, &mut indices, options.optimize);
+                        (vertices, indices, index_format)
+                    })
+                    .collect::<Vec<_>>();
+
+                // seams and hard edges can keep a mesh from simplifying at all
+                let simplified_triangles = simplified
+                    .iter()
+                    .map(|(_, indices, _)| indices.len() / 3)
+                    .sum::<usize>();
+                if simplified_triangles >= coarsest {
+                    log::info!(
+                        "{}: lod {} skipped, nothing simplifies below {} triangles",
+                        file_name,
+                        level + 1,
+                        coarsest
+                    );
+                    continue;
+                }
+
+                let meshes = simplified
+                    .into_iter()
+                    .zip(&model.meshes)
+                    .map(|((vertices, indices, index_format), mesh)| {
+                        let name = format!("{} lod {}", mesh.name, level + 1);
+                        Mesh::new(
+                            device,
+                            &name,
+                            &vertices,
+                            indices,
+                            index_format,
+                            mesh.materials,
+                        )
+                    })
+                    .collect::<Vec<_>>();
+
+                Lod {
+                    meshes,
+                    threshold: *threshold,
+                }
+            }
+            LodSource::File(lod_file) => {
+                let (lod, _) =
+                    load_obj(lod_file, device, queue, material_layout, options.optimize).await?;
+                coarsest = triangles(&lod.meshes);
+                model.push_lod(lod, *threshold);
+                continue;
+            }
+        };
+
+        coarsest = triangles(&lod.meshes);
+        model.lods.push(lod);
+    }
+
+    Ok(model)
+}
+
+// the model and the CPU copy of each mesh's vertices and indices
+async fn load_obj(
+    file_name: &str,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    material_layout: &MaterialLayout,
+    optimize: bool,
+) -> Result<(model::Model, Vec<(Vec<ModelVertex>, Vec<u32>)>), ResourceError> {
     let obj_text = load_string(file_name).await?;
     let obj_cursor = Cursor::new(obj_text);
     let mut obj_buffer = BufReader::new(obj_cursor);
@@ -60,73 +177,339 @@ pub async fn load_model(
             ..Default::default()
         },
         |p| async move {
-            let mat_text = load_string(&p).await.unwrap();
-            tobj::load_mtl_buf(&mut BufReader::new(Cursor::new(mat_text)))
+            match load_string(&p).await {
+                Ok(mat_text) => tobj::load_mtl_buf(&mut BufReader::new(Cursor::new(mat_text))),
+                Err(err) => {
+                    log::warn!("{}", err);
+                    Err(tobj::LoadError::OpenFileFailed)
+                }
+            }
         },
     )
-    .await?;
+    .await
+    .map_err(|source| ResourceError::MalformedObj {
+        path: file_name.to_string(),
+        source,
+    })?;
+
+    // without its materials the model still draws, with the default one
+    let obj_materials = obj_materials.unwrap_or_else(|err| {
+        log::warn!("{}: materials not loaded: {}", file_name, err);
+        Vec::new()
+    });
 
     let mut materials = Vec::new();
-    for m in obj_materials? {
-        let diffuse_texture = load_texture(&m.diffuse_texture.unwrap(), device, queue).await?;
-
-        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&diffuse_texture.view),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&diffuse_texture.sampler),
-                },
-            ],
-            label: None,
-        });
+    for m in obj_materials {
+        let scope = asset_scope(file_name, &m.name);
+        let unknown = |key: &str| m.unknown_param.get(key).map(|value| value.trim());
+
+        let textures = MaterialTextures {
+            // a checkerboard makes a broken color texture obvious, the others are left out
+            base_color: match load_material_texture(
+                &scope,
+                m.diffuse_texture.as_deref(),
+                ColorSpace::Srgb,
+                device,
+                queue,
+            )
+            .await
+            {
+                Ok(texture) => texture,
+                Err(err) => {
+                    log::warn!("{}", err);
+                    Some(texture::Texture::checkerboard(
+                        device,
+                        queue,
+                        &label(&scope, "checkerboard"),
+                    )?)
+                }
+            },
+            // `norm` is the PBR extension's name for it
+            normal: warn_missing(
+                load_material_texture(
+                    &scope,
+                    m.normal_texture.as_deref().or(unknown("norm")),
+                    ColorSpace::Linear,
+                    device,
+                    queue,
+                )
+                .await,
+            ),
+            metallic_roughness: warn_missing(
+                load_metallic_roughness(
+                    unknown("map_Pr"),
+                    unknown("map_Pm"),
+                    &label(&scope, "metallic roughness"),
+                    device,
+                    queue,
+                )
+                .await,
+            ),
+            occlusion: None,
+            emissive: warn_missing(
+                load_material_texture(&scope, unknown("map_Ke"), ColorSpace::Srgb, device, queue)
+                    .await,
+            ),
+        };
 
+        let params = material_params(&m);
         materials.push(Material {
-            name: m.name,
-            diffuse_texture,
-            bind_group,
+            translucent: params.base_color[3] < 1.0,
+            ..Material::new(device, material_layout, &scope, params, textures)
         });
     }
 
+    let mut geometry = Vec::with_capacity(models.len());
     let meshes = models
         .into_iter()
         .map(|m| {
-            let vertices = (0..m.mesh.positions.len() / 3)
+            let scope = asset_scope(file_name, &m.name);
+
+            let has_normals = !m.mesh.normals.is_empty();
+            let has_tex_coords = !m.mesh.texcoords.is_empty();
+
+            let mut vertices = (0..m.mesh.positions.len() / 3)
                 .map(|i| model::ModelVertex {
                     pos: [
                         m.mesh.positions[i * 3],
                         m.mesh.positions[i * 3 + 1],
                         m.mesh.positions[i * 3 + 2],
                     ],
-                    tex_coords: [m.mesh.texcoords[i * 2], m.mesh.texcoords[i * 2 + 1]], // TODO: load normals
+                    tex_coords: if has_tex_coords {
+                        [m.mesh.texcoords[i * 2], m.mesh.texcoords[i * 2 + 1]]
+                    } else {
+                        [0.0; 2]
+                    },
+                    normal: if has_normals {
+                        [
+                            m.mesh.normals[i * 3],
+                            m.mesh.normals[i * 3 + 1],
+                            m.mesh.normals[i * 3 + 2],
+                        ]
+                    } else {
+                        [0.0; 3]
+                    },
+                    tangent: [0.0; 4],
                 })
                 .collect::<Vec<_>>();
 
-            let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: Some(&format!("{:?} vertex buffer", file_name)),
-                contents: bytemuck::cast_slice(&vertices),
-                usage: wgpu::BufferUsages::VERTEX,
-            });
-
-            let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: Some(&format!("{:?} index buffer", file_name)),
-                contents: bytemuck::cast_slice(&m.mesh.indices),
-                usage: wgpu::BufferUsages::INDEX,
-            });
-
-            model::Mesh {
-                name: file_name.to_string(),
-                vertex_buffer,
-                index_buffer,
-                num_elements: m.mesh.indices.len() as u32,
-                materials: m.mesh.material_id.unwrap_or(0),
+            let mut indices = m.mesh.indices;
+            if !has_normals {
+                generate_normals(&mut vertices, &mut indices, NORMAL_CREASE_ANGLE);
             }
+            generate_tangents(&mut vertices, &mut indices);
+            let index_format = finish_geometry(&mut vertices, &mut indices, optimize);
+
+            let mesh = Mesh::new(
+                device,
+                &scope,
+                &vertices,
+                indices.clone(),
+                index_format,
+                m.mesh.material_id.unwrap_or(model::NO_MATERIAL),
+            );
+            geometry.push((vertices, indices));
+            mesh
         })
         .collect::<Vec<_>>();
 
-    Ok(model::Model { materials, meshes })
+    let model = model::Model::new(device, queue, material_layout, file_name, meshes, materials)?;
+    Ok((model, geometry))
+}
+
+// the optional optimization pass, returns the index format the result fits
+fn finish_geometry(
+    vertices: &mut Vec<ModelVertex>,
+    indices: &mut Vec<u32>,
+    optimize: bool,
+) -> wgpu::IndexFormat {
+    if !optimize {
+        return wgpu::IndexFormat::Uint32;
+    }
+
+    geometry::optimize(vertices, indices);
+
+    model::narrowest_index_format(vertices.len())
+}
+
+// an optional texture of a material, failures carry the material's name
+async fn load_material_texture(
+    material: &str,
+    file_name: Option<&str>,
+    color_space: ColorSpace,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+) -> Result<Option<texture::Texture>, ResourceError> {
+    let Some(file_name) = file_name else {
+        return Ok(None);
+    };
+
+    load_texture(file_name, color_space, device, queue)
+        .await
+        .map(Some)
+        .map_err(|cause| ResourceError::MissingTexture {
+            material: material.to_string(),
+            path: file_name.to_string(),
+            cause: Box::new(cause),
+        })
+}
+
+// material textures are optional, one that fails to load is dropped with a warning
+fn warn_missing(
+    texture: Result<Option<texture::Texture>, ResourceError>,
+) -> Option<texture::Texture> {
+    texture.unwrap_or_else(|err| {
+        log::warn!("{}", err);
+        None
+    })
+}
+
+async fn load_gray(file_name: Option<&str>) -> Result<Option<image::GrayImage>, ResourceError> {
+    Ok(match file_name {
+        Some(file_name) => Some(
+            image::load_from_memory(&load_binary(file_name).await?)
+                .map_err(|source| ResourceError::Decode {
+                    path: file_name.to_string(),
+                    source,
+                })?
+                .to_luma8(),
+        ),
+        None => None,
+    })
+}
+
+// MTL keeps roughness and metallic in separate grayscale maps, the shader expects them in
+// the green and blue channels of one texture like glTF does
+async fn load_metallic_roughness(
+    roughness_file: Option<&str>,
+    metallic_file: Option<&str>,
+    texture_label: &str,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+) -> Result<Option<texture::Texture>, ResourceError> {
+    let roughness = load_gray(roughness_file).await?;
+    let metallic = load_gray(metallic_file).await?;
+
+    let Some((width, height)) = roughness
+        .as_ref()
+        .or(metallic.as_ref())
+        .map(|map| map.dimensions())
+    else {
+        return Ok(None);
+    };
+    if let (Some(roughness), Some(metallic)) = (&roughness, &metallic) {
+        if roughness.dimensions() != metallic.dimensions() {
+            return Err(ResourceError::malformed(
+                texture_label,
+                "roughness and metallic maps differ in size",
+            ));
+        }
+    }
+
+    // a missing map reads as 1.0 so the factor alone applies
+    let channel = |map: &Option<image::GrayImage>, x, y| {
+        map.as_ref().map_or(255, |map| map.get_pixel(x, y)[0])
+    };
+    let combined = image::RgbaImage::from_fn(width, height, |x, y| {
+        image::Rgba([0, channel(&roughness, x, y), channel(&metallic, x, y), 255])
+    });
+
+    texture::Texture::from_bytes(
+        device,
+        queue,
+        Vec::new(),
+        texture_label,
+        ColorSpace::Linear,
+        Some(combined),
+        texture::Texture::create_sampler(device, texture::SamplerOptions::default()),
+    )
+    .map(Some)
+}
+
+// colors are used as written, Blender exports its linear values. Parameters the MTL file
+// leaves out keep their defaults
+fn material_params(m: &tobj::Material) -> MaterialParams {
+    let defaults = MaterialParams::default();
+
+    // tobj keeps Ke and the PBR extension among the parameters it doesn't know
+    let unknown = |key: &str| m.unknown_param.get(key);
+    let float = |key: &str| unknown(key).and_then(|value| value.trim().parse::<f32>().ok());
+
+    let emissive = unknown("Ke").and_then(|value| {
+        let channels = value
+            .split_whitespace()
+            .map(|channel| channel.parse::<f32>().ok())
+            .collect::<Option<Vec<_>>>()?;
+        <[f32; 3]>::try_from(channels).ok()
+    });
+
+    let metallic_roughness = ["Pr", "Pm", "map_Pr", "map_Pm"]
+        .iter()
+        .any(|key| unknown(key).is_some());
+
+    let [r, g, b, _] = defaults.base_color;
+    let [r, g, b] = m.diffuse.unwrap_or([r, g, b]);
+
+    MaterialParams {
+        shading: if metallic_roughness {
+            Shading::MetallicRoughness
+        } else {
+            Shading::BlinnPhong
+        },
+        base_color: [r, g, b, m.dissolve.unwrap_or(defaults.base_color[3])],
+        emissive: emissive.unwrap_or(defaults.emissive),
+        metallic: float("Pm").unwrap_or(defaults.metallic),
+        roughness: float("Pr").unwrap_or(defaults.roughness),
+        occlusion_strength: defaults.occlusion_strength,
+        ambient: m.ambient.unwrap_or(defaults.ambient),
+        specular: m.specular.unwrap_or(defaults.specular),
+        shininess: m.shininess.unwrap_or(defaults.shininess),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: [f32; 3], expected: [f32; 3]) {
+        for (actual, expected) in actual.iter().zip(expected) {
+            assert!(
+                (actual - expected).abs() < 1e-4,
+                "{:?} != {:?}",
+                actual,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn mtl_colors_pass_through() {
+        let m = tobj::Material {
+            ambient: Some([0.0, 0.02, 1.0]),
+            diffuse: Some([0.8, 0.8, 0.8]),
+            specular: Some([0.5, 0.5, 0.5]),
+            dissolve: Some(0.5),
+            unknown_param: [(String::from("Ke"), String::from("1 0.5 0"))].into(),
+            ..Default::default()
+        };
+        let params = material_params(&m);
+
+        assert_close(params.ambient, [0.0, 0.02, 1.0]);
+        let [r, g, b, a] = params.base_color;
+        assert_close([r, g, b], [0.8; 3]);
+        assert_eq!(a, 0.5);
+        assert_close(params.specular, [0.5; 3]);
+        assert_close(params.emissive, [1.0, 0.5, 0.0]);
+    }
+
+    #[test]
+    fn missing_mtl_colors_keep_the_defaults() {
+        let params = material_params(&tobj::Material::default());
+        let defaults = MaterialParams::default();
+
+        assert_eq!(params.base_color, defaults.base_color);
+        assert_eq!(params.ambient, defaults.ambient);
+        assert_eq!(params.specular, defaults.specular);
+        assert_eq!(params.emissive, defaults.emissive);
+    }
 }
@@ -0,0 +1,813 @@
+use std::path::Path;
+
+use base64::Engine;
+use cgmath::{Matrix4, Quaternion, SquareMatrix, Vector3, Vector4};
+
+use crate::{
+    camera::Projection,
+    core::{
+        animation::{
+            AnimationClip, Channel, Interpolation, NodeTransform, Property, Skin, MAX_JOINTS,
+        },
+        debug::{asset_scope, label},
+        error::ResourceError,
+        geometry::{generate_normals, generate_tangents},
+        model::{
+            self, Material, MaterialLayout, MaterialParams, MaterialTextures, Shading,
+            SkinnedVertex,
+        },
+        texture::{self, ColorSpace},
+    },
+    scene::{Node, Scene, SceneCamera, SceneLight},
+};
+
+use super::{load_binary, NORMAL_CREASE_ANGLE};
+
+/// Loads a `.gltf` or `.glb` file with its meshes, materials, node hierarchy, skins and
+/// animations.
+///
+/// External buffers and images are looked up next to the file, embedded ones come from the
+/// binary chunk or `data:` URIs. Images that fail to load are logged and their textures
+/// replaced, a broken base color by a checkerboard.
+pub async fn load_gltf(
+    file_name: &str,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    material_layout: &MaterialLayout,
+) -> Result<Scene, ResourceError> {
+    let ::gltf::Gltf { document, mut blob } =
+        ::gltf::Gltf::from_slice(&load_binary(file_name).await?)
+            .map_err(|err| ResourceError::malformed(file_name, format!("invalid glTF: {}", err)))?;
+
+    let mut buffers = Vec::new();
+    for buffer in document.buffers() {
+        let data = match buffer.source() {
+            ::gltf::buffer::Source::Bin => blob
+                .take()
+                .ok_or_else(|| ResourceError::malformed(file_name, "missing binary chunk"))?,
+            ::gltf::buffer::Source::Uri(uri) => load_uri(file_name, uri).await?,
+        };
+        if data.len() < buffer.length() {
+            return Err(ResourceError::malformed(
+                file_name,
+                format!(
+                    "buffer {} is {} bytes, expected {}",
+                    buffer.index(),
+                    data.len(),
+                    buffer.length()
+                ),
+            ));
+        }
+        buffers.push(data);
+    }
+
+    // decoded once, uploaded once per use since the color space depends on the slot, None
+    // for the ones that failed
+    let mut images = Vec::new();
+    for image in document.images() {
+        let image_name = asset_scope(
+            file_name,
+            &image
+                .name()
+                .map(String::from)
+                .unwrap_or_else(|| format!("image {}", image.index())),
+        );
+        let data = match image.source() {
+            ::gltf::image::Source::View { view, .. } => {
+                view_bytes(file_name, &buffers, &view).map(<[u8]>::to_vec)
+            }
+            ::gltf::image::Source::Uri { uri, .. } => load_uri(file_name, uri).await,
+        };
+        let decoded = data.and_then(|data| {
+            image::load_from_memory(&data).map_err(|source| ResourceError::Decode {
+                path: image_name,
+                source,
+            })
+        });
+
+        images.push(match decoded {
+            Ok(decoded) => Some(decoded.to_rgba8()),
+            Err(err) => {
+                log::warn!("{}", err);
+                None
+            }
+        });
+    }
+
+    let load_texture = |texture: ::gltf::Texture, color_space, texture_label: &str| {
+        let Some(image) = &images[texture.source().index()] else {
+            return Ok(None);
+        };
+
+        texture::Texture::from_bytes(
+            device,
+            queue,
+            Vec::new(),
+            texture_label,
+            color_space,
+            Some(image.clone()),
+            texture::Texture::create_sampler(device, sampler_options(&texture.sampler())),
+        )
+        .map(Some)
+    };
+
+    let mut materials = Vec::new();
+    for m in document.materials() {
+        let scope = asset_scope(
+            file_name,
+            &m.name()
+                .map(String::from)
+                .unwrap_or_else(|| format!("material {}", materials.len())),
+        );
+        let pbr = m.pbr_metallic_roughness();
+
+        let tex_coords = [
+            pbr.base_color_texture().map(|info| info.tex_coord()),
+            pbr.metallic_roughness_texture()
+                .map(|info| info.tex_coord()),
+            m.normal_texture().map(|info| info.tex_coord()),
+            m.occlusion_texture().map(|info| info.tex_coord()),
+            m.emissive_texture().map(|info| info.tex_coord()),
+        ];
+        if tex_coords.iter().flatten().any(|&set| set != 0) {
+            log::warn!("{}: only the first texture coordinate set is used", scope);
+        }
+
+        let textures = MaterialTextures {
+            base_color: match pbr.base_color_texture() {
+                Some(info) => Some(
+                    match load_texture(
+                        info.texture(),
+                        ColorSpace::Srgb,
+                        &label(&scope, "base color"),
+                    )? {
+                        Some(texture) => texture,
+                        None => texture::Texture::checkerboard(
+                            device,
+                            queue,
+                            &label(&scope, "checkerboard"),
+                        )?,
+                    },
+                ),
+                None => None,
+            },
+            normal: m
+                .normal_texture()
+                .map(|info| {
+                    load_texture(info.texture(), ColorSpace::Linear, &label(&scope, "normal"))
+                })
+                .transpose()?
+                .flatten(),
+            metallic_roughness: pbr
+                .metallic_roughness_texture()
+                .map(|info| {
+                    load_texture(
+                        info.texture(),
+                        ColorSpace::Linear,
+                        &label(&scope, "metallic roughness"),
+                    )
+                })
+                .transpose()?
+                .flatten(),
+            occlusion: m
+                .occlusion_texture()
+                .map(|info| {
+                    load_texture(
+                        info.texture(),
+                        ColorSpace::Linear,
+                        &label(&scope, "occlusion"),
+                    )
+                })
+                .transpose()?
+                .flatten(),
+            emissive: m
+                .emissive_texture()
+                .map(|info| {
+                    load_texture(info.texture(), ColorSpace::Srgb, &label(&scope, "emissive"))
+                })
+                .transpose()?
+                .flatten(),
+        };
+
+        let params = MaterialParams {
+            shading: Shading::MetallicRoughness,
+            base_color: pbr.base_color_factor(),
+            emissive: m.emissive_factor(),
+            metallic: pbr.metallic_factor(),
+            roughness: pbr.roughness_factor(),
+            occlusion_strength: m
+                .occlusion_texture()
+                .map_or(1.0, |occlusion| occlusion.strength()),
+            ..Default::default()
+        };
+
+        materials.push(Material {
+            double_sided: m.double_sided(),
+            ..Material::new(device, material_layout, &scope, params, textures)
+        });
+    }
+
+    // primitives without a material get glTF's default one, appended when first needed
+    let mut default_material = None;
+
+    let mut meshes = Vec::new();
+    // how many joints each model mesh's vertices need, 0 for the unskinned ones
+    let mut joint_counts = Vec::new();
+    // the model meshes each glTF mesh turned into, one per primitive
+    let mut mesh_primitives = Vec::new();
+    for mesh in document.meshes() {
+        let mesh_name = mesh
+            .name()
+            .map(String::from)
+            .unwrap_or_else(|| format!("mesh {}", mesh.index()));
+
+        let mut primitives = Vec::new();
+        for primitive in mesh.primitives() {
+            let scope = asset_scope(file_name, &format!("{}/{}", mesh_name, primitive.index()));
+
+            if primitive.mode() != ::gltf::mesh::Mode::Triangles {
+                log::warn!(
+                    "{}: skipping {:?} primitive, only triangles are supported",
+                    scope,
+                    primitive.mode()
+                );
+                continue;
+            }
+
+            let reader =
+                primitive.reader(|buffer| buffers.get(buffer.index()).map(|data| &data[..]));
+
+            let positions = reader
+                .read_positions()
+                .ok_or_else(|| ResourceError::malformed(&scope, "primitive has no positions"))?;
+            let mut vertices = positions
+                .map(|pos| model::ModelVertex {
+                    pos,
+                    tex_coords: [0.0; 2],
+                    normal: [0.0; 3],
+                    tangent: [0.0; 4],
+                })
+                .collect::<Vec<_>>();
+
+            if let Some(tex_coords) = reader.read_tex_coords(0) {
+                for (vertex, tex_coords) in vertices.iter_mut().zip(tex_coords.into_f32()) {
+                    vertex.tex_coords = tex_coords;
+                }
+            }
+
+            let mut indices = match reader.read_indices() {
+                Some(indices) => indices.into_u32().collect::<Vec<_>>(),
+                None => (0..vertices.len() as u32).collect(),
+            };
+            if indices
+                .iter()
+                .any(|&index| index as usize >= vertices.len())
+            {
+                return Err(ResourceError::malformed(&scope, "index out of range"));
+            }
+
+            // weights are normalized, exporters don't always get them to sum to 1
+            let mut skin = match (reader.read_joints(0), reader.read_weights(0)) {
+                (Some(joints), Some(weights)) => Some(
+                    joints
+                        .into_u16()
+                        .zip(weights.into_f32())
+                        .map(|(joints, weights)| {
+                            let total = weights.iter().sum::<f32>();
+                            SkinnedVertex {
+                                joints: joints.map(u32::from),
+                                weights: match total > 0.0 {
+                                    true => weights.map(|weight| weight / total),
+                                    false => weights,
+                                },
+                            }
+                        })
+                        .collect::<Vec<_>>(),
+                ),
+                _ => None,
+            };
+            if skin
+                .as_ref()
+                .is_some_and(|skin| skin.len() != vertices.len())
+            {
+                return Err(ResourceError::malformed(
+                    &scope,
+                    "joint and weight count differs from the vertex count",
+                ));
+            }
+
+            let has_normals = match reader.read_normals() {
+                Some(normals) => {
+                    for (vertex, normal) in vertices.iter_mut().zip(normals) {
+                        vertex.normal = normal;
+                    }
+                    true
+                }
+                None => false,
+            };
+            // both can split vertices, the joints have to follow
+            let mut follow = |origins: Vec<u32>| {
+                if let Some(skin) = &mut skin {
+                    *skin = origins
+                        .iter()
+                        .map(|&origin| skin[origin as usize])
+                        .collect();
+                }
+            };
+            if !has_normals {
+                follow(generate_normals(
+                    &mut vertices,
+                    &mut indices,
+                    NORMAL_CREASE_ANGLE,
+                ));
+            }
+
+            // tangents from the file only fit the file's normals
+            match reader.read_tangents().filter(|_| has_normals) {
+                Some(tangents) => {
+                    for (vertex, tangent) in vertices.iter_mut().zip(tangents) {
+                        vertex.tangent = tangent;
+                    }
+                }
+                None => {
+                    follow(generate_tangents(&mut vertices, &mut indices));
+                    // glTF's v axis points down, so its bitangents go the other way
+                    for vertex in &mut vertices {
+                        vertex.tangent[3] = -vertex.tangent[3];
+                    }
+                }
+            }
+
+            let materials = match primitive.material().index() {
+                Some(index) => index,
+                None => *default_material.get_or_insert_with(|| {
+                    materials.push(Material::new(
+                        device,
+                        material_layout,
+                        &asset_scope(file_name, "default material"),
+                        MaterialParams {
+                            shading: Shading::MetallicRoughness,
+                            metallic: 1.0,
+                            ..Default::default()
+                        },
+                        MaterialTextures::default(),
+                    ));
+                    materials.len() - 1
+                }),
+            };
+
+            let mesh = model::Mesh::new(
+                device,
+                &scope,
+                &vertices,
+                indices,
+                wgpu::IndexFormat::Uint32,
+                materials,
+            );
+            primitives.push(meshes.len());
+            joint_counts.push(skin.as_deref().map_or(0, joint_count));
+            meshes.push(match &skin {
+                Some(skin) => mesh.with_skin(device, skin),
+                None => mesh,
+            });
+        }
+        mesh_primitives.push(primitives);
+    }
+
+    let mut skins = Vec::new();
+    for skin in document.skins() {
+        let scope = asset_scope(
+            file_name,
+            &skin
+                .name()
+                .map(String::from)
+                .unwrap_or_else(|| format!("skin {}", skin.index())),
+        );
+
+        let joints = skin.joints().map(|joint| joint.index()).collect::<Vec<_>>();
+        if joints.len() > MAX_JOINTS {
+            log::warn!(
+                "{}: {} joints, more than the {} the shader has room for, drawing its meshes unskinned",
+                scope,
+                joints.len(),
+                MAX_JOINTS
+            );
+        }
+
+        let reader = skin.reader(|buffer| buffers.get(buffer.index()).map(|data| &data[..]));
+        let inverse_bind_matrices = match reader.read_inverse_bind_matrices() {
+            Some(matrices) => matrices.map(Matrix4::from).collect::<Vec<_>>(),
+            None => vec![Matrix4::identity(); joints.len()],
+        };
+        if inverse_bind_matrices.len() < joints.len() {
+            return Err(ResourceError::malformed(
+                &scope,
+                "fewer inverse bind matrices than joints",
+            ));
+        }
+
+        skins.push(Skin {
+            joints,
+            inverse_bind_matrices,
+        });
+    }
+
+    let mut animations = Vec::new();
+    for animation in document.animations() {
+        let name = animation
+            .name()
+            .map(String::from)
+            .unwrap_or_else(|| format!("animation {}", animation.index()));
+        let scope = asset_scope(file_name, &name);
+
+        let mut channels = Vec::new();
+        for channel in animation.channels() {
+            let reader = channel.reader(|buffer| buffers.get(buffer.index()).map(|data| &data[..]));
+
+            let times = reader
+                .read_inputs()
+                .ok_or_else(|| ResourceError::malformed(&scope, "channel has no keyframe times"))?
+                .collect::<Vec<_>>();
+            let outputs = reader.read_outputs().ok_or_else(|| {
+                ResourceError::malformed(&scope, "channel has no keyframe values")
+            })?;
+
+            let xyz = |[x, y, z]: [f32; 3]| Vector4::new(x, y, z, 0.0);
+            let (property, values) = match outputs {
+                ::gltf::animation::util::ReadOutputs::Translations(translations) => (
+                    Property::Translation,
+                    translations.map(xyz).collect::<Vec<_>>(),
+                ),
+                ::gltf::animation::util::ReadOutputs::Rotations(rotations) => (
+                    Property::Rotation,
+                    rotations.into_f32().map(Vector4::from).collect(),
+                ),
+                ::gltf::animation::util::ReadOutputs::Scales(scales) => {
+                    (Property::Scale, scales.map(xyz).collect())
+                }
+                ::gltf::animation::util::ReadOutputs::MorphTargetWeights(_) => {
+                    log::warn!(
+                        "{}: skipping morph target weights, morph targets are not supported",
+                        scope
+                    );
+                    continue;
+                }
+            };
+
+            let (interpolation, values_per_key) = match channel.sampler().interpolation() {
+                ::gltf::animation::Interpolation::Step => (Interpolation::Step, 1),
+                ::gltf::animation::Interpolation::Linear => (Interpolation::Linear, 1),
+                ::gltf::animation::Interpolation::CubicSpline => (Interpolation::CubicSpline, 3),
+            };
+            if values.len() != times.len() * values_per_key {
+                return Err(ResourceError::malformed(
+                    &scope,
+                    "keyframe value count doesn't match the keyframe times",
+                ));
+            }
+
+            channels.push(Channel {
+                node: channel.target().node().index(),
+                property,
+                interpolation,
+                times,
+                values,
+            });
+        }
+
+        animations.push(AnimationClip::new(name, channels));
+    }
+
+    let nodes = document
+        .nodes()
+        .map(|n| {
+            let (translation, [x, y, z, w], scale) = n.transform().decomposed();
+            let mut node = Node::new(
+                n.name()
+                    .map(String::from)
+                    .unwrap_or_else(|| format!("node {}", n.index())),
+                NodeTransform {
+                    translation: Vector3::from(translation),
+                    rotation: Quaternion::new(w, x, y, z),
+                    scale: Vector3::from(scale),
+                },
+            );
+            node.children = n.children().map(|child| child.index()).collect();
+            node.meshes = n
+                .mesh()
+                .map(|mesh| mesh_primitives[mesh.index()].clone())
+                .unwrap_or_default();
+            node.camera = n.camera().map(|camera| scene_camera(&camera));
+            node.light = n.light().map(|light| scene_light(&light));
+            // skins the shader can't fit leave their meshes in the bind pose
+            node.skin = n
+                .skin()
+                .map(|skin| skin.index())
+                .filter(|&skin| skins[skin].joints.len() <= MAX_JOINTS);
+            node
+        })
+        .collect::<Vec<_>>();
+    check_hierarchy(file_name, &nodes)?;
+    for node in &nodes {
+        let Some(skin) = node.skin else {
+            continue;
+        };
+        if node
+            .meshes
+            .iter()
+            .any(|&mesh| joint_counts[mesh] > skins[skin].joints.len())
+        {
+            return Err(ResourceError::malformed(
+                &asset_scope(file_name, &node.name),
+                "joint index out of range for the node's skin",
+            ));
+        }
+    }
+
+    let roots = match document
+        .default_scene()
+        .or_else(|| document.scenes().next())
+    {
+        Some(scene) => scene.nodes().map(|node| node.index()).collect(),
+        // no scenes, show everything that isn't someone's child
+        None => {
+            let mut is_child = vec![false; nodes.len()];
+            for node in &nodes {
+                for &child in &node.children {
+                    is_child[child] = true;
+                }
+            }
+            (0..nodes.len()).filter(|&index| !is_child[index]).collect()
+        }
+    };
+
+    Ok(Scene {
+        model: model::Model::new(device, queue, material_layout, file_name, meshes, materials)?,
+        nodes,
+        roots,
+        skins,
+        animations,
+    })
+}
+
+// one past the highest joint index, the shader reads past the skin's joints otherwise
+fn joint_count(skin: &[SkinnedVertex]) -> usize {
+    skin.iter()
+        .flat_map(|vertex| vertex.joints)
+        .max()
+        .map_or(0, |joint| joint as usize + 1)
+}
+
+// the scene walks the hierarchy down from its roots, which only ends for a forest
+fn check_hierarchy(file_name: &str, nodes: &[Node]) -> Result<(), ResourceError> {
+    let mut parents = vec![0; nodes.len()];
+    for node in nodes {
+        for &child in &node.children {
+            parents[child] += 1;
+        }
+    }
+    if let Some(node) = parents.iter().position(|&count| count > 1) {
+        return Err(ResourceError::malformed(
+            file_name,
+            format!("{} has more than one parent", nodes[node].name),
+        ));
+    }
+
+    // with one parent at most, whatever the parentless nodes don't lead to is on a cycle
+    let mut visited = vec![false; nodes.len()];
+    let mut stack = (0..nodes.len())
+        .filter(|&index| parents[index] == 0)
+        .collect::<Vec<_>>();
+    while let Some(index) = stack.pop() {
+        visited[index] = true;
+        stack.extend(&nodes[index].children);
+    }
+    match visited.iter().position(|&visited| !visited) {
+        Some(node) => Err(ResourceError::malformed(
+            file_name,
+            format!("{} is its own ancestor", nodes[node].name),
+        )),
+        None => Ok(()),
+    }
+}
+
+// the bytes of a buffer view, which the gltf crate doesn't check against the buffer
+fn view_bytes<'a>(
+    file_name: &str,
+    buffers: &'a [Vec<u8>],
+    view: &::gltf::buffer::View,
+) -> Result<&'a [u8], ResourceError> {
+    let end = view.offset().checked_add(view.length());
+    end.and_then(|end| buffers.get(view.buffer().index())?.get(view.offset()..end))
+        .ok_or_else(|| ResourceError::malformed(file_name, "image buffer view out of range"))
+}
+
+// `data:` URIs are decoded in place, anything else is a path relative to the glTF file
+async fn load_uri(file_name: &str, uri: &str) -> Result<Vec<u8>, ResourceError> {
+    if let Some(data) = uri.strip_prefix("data:") {
+        let (_, payload) = data.split_once(";base64,").ok_or_else(|| {
+            ResourceError::malformed(file_name, "only base64 data URIs are supported")
+        })?;
+        return base64::engine::general_purpose::STANDARD
+            .decode(payload)
+            .map_err(|err| {
+                ResourceError::malformed(file_name, format!("invalid base64 data URI: {}", err))
+            });
+    }
+
+    let path = Path::new(file_name)
+        .parent()
+        .unwrap_or(Path::new(""))
+        .join(percent_decode(uri));
+    let path = path
+        .to_str()
+        .ok_or_else(|| ResourceError::malformed(file_name, format!("non UTF-8 path {:?}", path)))?;
+
+    load_binary(path).await
+}
+
+// relative URIs may escape spaces and the like as %XX
+fn percent_decode(uri: &str) -> String {
+    let bytes = uri.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], escaped) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+// filters the file leaves to the implementation keep the defaults
+fn sampler_options(sampler: &::gltf::texture::Sampler) -> texture::SamplerOptions {
+    use ::gltf::texture::{MagFilter, MinFilter, WrappingMode};
+
+    let defaults = texture::SamplerOptions::default();
+    let address_mode = |mode| match mode {
+        WrappingMode::ClampToEdge => wgpu::AddressMode::ClampToEdge,
+        WrappingMode::MirroredRepeat => wgpu::AddressMode::MirrorRepeat,
+        WrappingMode::Repeat => wgpu::AddressMode::Repeat,
+    };
+    let (min_filter, mipmap_filter) = match sampler.min_filter() {
+        Some(MinFilter::Nearest) => (wgpu::FilterMode::Nearest, defaults.mipmap_filter),
+        Some(MinFilter::Linear) => (wgpu::FilterMode::Linear, defaults.mipmap_filter),
+        Some(MinFilter::NearestMipmapNearest) => {
+            (wgpu::FilterMode::Nearest, wgpu::FilterMode::Nearest)
+        }
+        Some(MinFilter::LinearMipmapNearest) => {
+            (wgpu::FilterMode::Linear, wgpu::FilterMode::Nearest)
+        }
+        Some(MinFilter::NearestMipmapLinear) => {
+            (wgpu::FilterMode::Nearest, wgpu::FilterMode::Linear)
+        }
+        Some(MinFilter::LinearMipmapLinear) => (wgpu::FilterMode::Linear, wgpu::FilterMode::Linear),
+        None => (defaults.min_filter, defaults.mipmap_filter),
+    };
+
+    texture::SamplerOptions {
+        address_mode_u: address_mode(sampler.wrap_s()),
+        address_mode_v: address_mode(sampler.wrap_t()),
+        mag_filter: match sampler.mag_filter() {
+            Some(MagFilter::Nearest) => wgpu::FilterMode::Nearest,
+            Some(MagFilter::Linear) => wgpu::FilterMode::Linear,
+            None => defaults.mag_filter,
+        },
+        min_filter,
+        mipmap_filter,
+    }
+}
+
+fn scene_camera(camera: &::gltf::Camera) -> SceneCamera {
+    match camera.projection() {
+        ::gltf::camera::Projection::Perspective(perspective) => SceneCamera {
+            projection: Projection::Perspective {
+                fovy: perspective.yfov().to_degrees(),
+            },
+            znear: perspective.znear(),
+            zfar: perspective.zfar(),
+            aspect: perspective.aspect_ratio(),
+        },
+        ::gltf::camera::Projection::Orthographic(orthographic) => SceneCamera {
+            // glTF's magnifications are half extents
+            projection: Projection::Orthographic {
+                height: orthographic.ymag() * 2.0,
+            },
+            znear: orthographic.znear(),
+            zfar: Some(orthographic.zfar()),
+            aspect: Some(orthographic.xmag() / orthographic.ymag()),
+        },
+    }
+}
+
+fn scene_light(light: &::gltf::khr_lights_punctual::Light) -> SceneLight {
+    let color = light.color();
+    let intensity = light.intensity();
+
+    match light.kind() {
+        ::gltf::khr_lights_punctual::Kind::Directional => {
+            SceneLight::Directional { color, intensity }
+        }
+        ::gltf::khr_lights_punctual::Kind::Point => SceneLight::Point {
+            color,
+            intensity,
+            range: light.range(),
+        },
+        ::gltf::khr_lights_punctual::Kind::Spot {
+            inner_cone_angle,
+            outer_cone_angle,
+        } => SceneLight::Spot {
+            color,
+            intensity,
+            range: light.range(),
+            inner_cone_angle,
+            outer_cone_angle,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn document(json: &str) -> ::gltf::Document {
+        ::gltf::Gltf::from_slice(json.as_bytes()).unwrap().document
+    }
+
+    fn nodes(children: &[&[usize]]) -> Vec<Node> {
+        children
+            .iter()
+            .enumerate()
+            .map(|(index, children)| {
+                let mut node = Node::new(format!("node {}", index), NodeTransform::default());
+                node.children = children.to_vec();
+                node
+            })
+            .collect()
+    }
+
+    #[test]
+    fn node_hierarchies_must_be_forests() {
+        assert!(check_hierarchy("test.gltf", &nodes(&[&[1, 2], &[], &[3], &[], &[]])).is_ok());
+
+        // A -> B -> C -> B
+        assert!(check_hierarchy("test.gltf", &nodes(&[&[1], &[2], &[1]])).is_err());
+        // a cycle with no way in
+        assert!(check_hierarchy("test.gltf", &nodes(&[&[], &[2], &[1]])).is_err());
+        assert!(check_hierarchy("test.gltf", &nodes(&[&[0]])).is_err());
+        // two parents, walked twice
+        assert!(check_hierarchy("test.gltf", &nodes(&[&[2], &[2], &[]])).is_err());
+    }
+
+    #[test]
+    fn joint_counts_cover_the_highest_index() {
+        let vertex = |joints| SkinnedVertex {
+            joints,
+            weights: [0.25; 4],
+        };
+
+        assert_eq!(joint_count(&[]), 0);
+        assert_eq!(
+            joint_count(&[vertex([0, 1, 2, 0]), vertex([4, 0, 0, 0])]),
+            5
+        );
+    }
+
+    #[test]
+    fn image_views_past_the_buffer_are_malformed() {
+        let document = document(
+            r#"{
+                "asset": { "version": "2.0" },
+                "buffers": [{ "byteLength": 8 }],
+                "bufferViews": [
+                    { "buffer": 0, "byteOffset": 2, "byteLength": 4 },
+                    { "buffer": 0, "byteOffset": 6, "byteLength": 4 }
+                ]
+            }"#,
+        );
+        let buffers = vec![(0..8).collect::<Vec<u8>>()];
+        let views = document.views().collect::<Vec<_>>();
+
+        assert_eq!(
+            view_bytes("test.glb", &buffers, &views[0]).unwrap(),
+            &[2, 3, 4, 5]
+        );
+        assert!(matches!(
+            view_bytes("test.glb", &buffers, &views[1]),
+            Err(ResourceError::Malformed { .. })
+        ));
+    }
+}
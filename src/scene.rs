@@ -0,0 +1,264 @@
+use cgmath::{EuclideanSpace, InnerSpace, Matrix4, Point3, SquareMatrix, Vector4};
+
+use crate::{
+    camera::{Camera, Projection},
+    core::{
+        animation::{AnimationClip, NodeTransform, Skin},
+        bounds::BoundingSphere,
+        model::Model,
+    },
+    light::Light,
+};
+
+/// A camera attached to a node, looking down the node's -Z with +Y up.
+#[derive(Clone, Copy, Debug)]
+pub struct SceneCamera {
+    pub projection: Projection,
+    pub znear: f32,
+    // None for an infinite far plane
+    pub zfar: Option<f32>,
+    // None to follow the surface
+    pub aspect: Option<f32>,
+}
+
+/// A light attached to a node. Directional and spot lights shine down the node's -Z.
+#[derive(Clone, Copy, Debug)]
+pub enum SceneLight {
+    Directional {
+        color: [f32; 3],
+        intensity: f32,
+    },
+    Point {
+        color: [f32; 3],
+        intensity: f32,
+        // None for unlimited
+        range: Option<f32>,
+    },
+    Spot {
+        color: [f32; 3],
+        intensity: f32,
+        range: Option<f32>,
+        // radians from the axis
+        inner_cone_angle: f32,
+        outer_cone_angle: f32,
+    },
+}
+
+pub struct Node {
+    pub name: String,
+    // relative to the parent, the rest pose when animated
+    pub transform: NodeTransform,
+    pub children: Vec<usize>,
+    // indices into the model's meshes
+    pub meshes: Vec<usize>,
+    // index into the scene's skins, deforming the meshes that have joints
+    pub skin: Option<usize>,
+    pub camera: Option<SceneCamera>,
+    pub light: Option<SceneLight>,
+}
+
+/// Meshes and materials plus the node hierarchy placing them, as loaded from a scene file.
+pub struct Scene {
+    pub model: Model,
+    pub nodes: Vec<Node>,
+    // nodes without a parent, the hierarchy is walked from these
+    pub roots: Vec<usize>,
+    pub skins: Vec<Skin>,
+    pub animations: Vec<AnimationClip>,
+}
+
+// point lights without a range get one where they've faded to a thousandth
+fn default_range(intensity: f32) -> f32 {
+    (intensity * 1000.0).sqrt().max(1.0)
+}
+
+impl Scene {
+    /// Node to world matrices, by node index. Nodes not reachable from a root keep identity.
+    pub fn world_transforms(&self) -> Vec<Matrix4<f32>> {
+        self.posed_world_transforms(&self.rest_pose())
+    }
+
+    /// Every node's own transform, what animations start from.
+    pub fn rest_pose(&self) -> Vec<NodeTransform> {
+        self.nodes.iter().map(|node| node.transform).collect()
+    }
+
+    /// Node to world matrices with the nodes moved to `pose`, by node index.
+    pub fn posed_world_transforms(&self, pose: &[NodeTransform]) -> Vec<Matrix4<f32>> {
+        let mut world = vec![Matrix4::identity(); self.nodes.len()];
+
+        let mut stack = self
+            .roots
+            .iter()
+            .map(|&root| (root, Matrix4::identity()))
+            .collect::<Vec<_>>();
+        while let Some((index, parent)) = stack.pop() {
+            world[index] = parent * pose[index].matrix();
+            let node = &self.nodes[index];
+            stack.extend(node.children.iter().map(|&child| (child, world[index])));
+        }
+
+        world
+    }
+
+    // meshes with joints on nodes with a skin, the rest are drawn with the node's matrix
+    fn is_skinned(&self, node: &Node, mesh: usize) -> bool {
+        node.skin.is_some() && self.model.meshes[mesh].skin_buffer.is_some()
+    }
+
+    /// World space instances of every mesh that isn't skinned, by mesh index, given the
+    /// node world matrices.
+    pub fn mesh_transforms(&self, world: &[Matrix4<f32>]) -> Vec<Vec<Matrix4<f32>>> {
+        let mut transforms = vec![Vec::new(); self.model.meshes.len()];
+
+        for (node, world) in self.nodes.iter().zip(world) {
+            for &mesh in &node.meshes {
+                if !self.is_skinned(node, mesh) {
+                    transforms[mesh].push(*world);
+                }
+            }
+        }
+
+        transforms
+    }
+
+    /// Every skinned mesh as (mesh, skin), each drawn once with its skin's joints. The node
+    /// holding it doesn't move it, the joints place it in the world.
+    pub fn skinned_meshes(&self) -> Vec<(usize, usize)> {
+        let mut skinned = Vec::new();
+
+        for node in &self.nodes {
+            for &mesh in &node.meshes {
+                if let Some(skin) = node.skin.filter(|_| self.is_skinned(node, mesh)) {
+                    skinned.push((mesh, skin));
+                }
+            }
+        }
+
+        skinned
+    }
+
+    /// World space bounds of every mesh instance, None for a scene that draws nothing.
+    /// Skinned meshes count in their bind pose, bounded by the mesh placed by each of its
+    /// skin's joints since every vertex is a blend of those.
+    pub fn bounding_sphere(&self) -> Option<BoundingSphere> {
+        let world = self.world_transforms();
+        let joints = &self
+            .skins
+            .iter()
+            .map(|skin| skin.joint_matrices(&world))
+            .collect::<Vec<_>>();
+
+        self.nodes
+            .iter()
+            .zip(&world)
+            .flat_map(|(node, world)| {
+                node.meshes.iter().flat_map(move |&mesh| {
+                    let sphere = self.model.meshes[mesh].sphere;
+                    match node.skin.filter(|_| self.is_skinned(node, mesh)) {
+                        Some(skin) => joints[skin]
+                            .iter()
+                            .map(|joint| sphere.transform(joint))
+                            .collect(),
+                        None => vec![sphere.transform(world)],
+                    }
+                })
+            })
+            .reduce(BoundingSphere::union)
+    }
+
+    /// The scene's lights in world space. Spot lights become point lights, the renderer
+    /// has no cones.
+    pub fn lights(&self) -> Vec<Light> {
+        let world = self.world_transforms();
+
+        self.nodes
+            .iter()
+            .zip(&world)
+            .filter_map(|(node, world)| {
+                let position = Point3::from_vec((world * Vector4::unit_w()).truncate());
+                let direction = (world * -Vector4::unit_z()).truncate();
+
+                let (color, intensity, range) = match node.light? {
+                    SceneLight::Directional { color, intensity } => {
+                        return Some(Light::Directional {
+                            direction,
+                            color,
+                            intensity,
+                        })
+                    }
+                    SceneLight::Point {
+                        color,
+                        intensity,
+                        range,
+                    } => (color, intensity, range),
+                    SceneLight::Spot {
+                        color,
+                        intensity,
+                        range,
+                        inner_cone_angle,
+                        outer_cone_angle,
+                    } => {
+                        log::warn!(
+                            "{}: spot light with a {:.0}-{:.0} degree cone drawn as a point light",
+                            node.name,
+                            inner_cone_angle.to_degrees(),
+                            outer_cone_angle.to_degrees()
+                        );
+                        (color, intensity, range)
+                    }
+                };
+
+                Some(Light::Point {
+                    position,
+                    color,
+                    intensity,
+                    range: range.unwrap_or_else(|| default_range(intensity)),
+                })
+            })
+            .collect()
+    }
+
+    /// Cameras for the scene's camera nodes, `template` supplies what the scene doesn't
+    /// say, like the depth mode and the aspect ratio.
+    pub fn cameras(&self, template: &Camera) -> Vec<Camera> {
+        let world = self.world_transforms();
+
+        self.nodes
+            .iter()
+            .zip(&world)
+            .filter_map(|(node, world)| {
+                let camera = node.camera?;
+
+                let eye = Point3::from_vec((world * Vector4::unit_w()).truncate());
+                let forward = (world * -Vector4::unit_z()).truncate().normalize();
+                let up = (world * Vector4::unit_y()).truncate().normalize();
+
+                Some(Camera {
+                    eye,
+                    target: eye + forward,
+                    up,
+                    aspect: camera.aspect.unwrap_or(template.aspect),
+                    projection: camera.projection,
+                    znear: camera.znear,
+                    zfar: camera.zfar.unwrap_or(template.zfar),
+                    depth_mode: template.depth_mode,
+                })
+            })
+            .collect()
+    }
+}
+
+impl Node {
+    pub fn new(name: String, transform: NodeTransform) -> Self {
+        Node {
+            name,
+            transform,
+            children: Vec::new(),
+            meshes: Vec::new(),
+            skin: None,
+            camera: None,
+            light: None,
+        }
+    }
+}
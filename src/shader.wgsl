@@ -1,5 +1,6 @@
 struct CameraUniform {
-    view_proj: mat4x4<f32>
+    view_proj: mat4x4<f32>,
+    view_position: vec4<f32>,
 }
 @group(1) @binding(0)
 var<uniform> camera: CameraUniform;
@@ -7,9 +8,33 @@ var<uniform> camera: CameraUniform;
 @group(2) @binding(0)
 var<uniform> elapsed_time: f32;
 
+// MAX_JOINTS in core::animation, one skin's joints at a time through a dynamic offset
+const MAX_JOINTS: u32 = 128u;
+@group(2) @binding(1)
+var<uniform> joint_matrices: array<mat4x4<f32>, MAX_JOINTS>;
+
+struct Light {
+    // the direction the light travels for directional lights
+    position: vec3<f32>,
+    // 0 directional, 1 point
+    kind: u32,
+    color: vec3<f32>,
+    range: f32,
+}
+
+struct Lights {
+    ambient: vec3<f32>,
+    count: u32,
+    lights: array<Light, 8>,
+}
+@group(3) @binding(0)
+var<uniform> lights: Lights;
+
 struct VertexInput {
     @location(0) pos: vec3<f32>,
     @location(1) tex_coords: vec2<f32>,
+    @location(2) normal: vec3<f32>,
+    @location(3) tangent: vec4<f32>,
 };
 
 struct InstanceInput {
@@ -17,17 +42,50 @@ struct InstanceInput {
     @location(6) model_matrix_1: vec4<f32>,
     @location(7) model_matrix_2: vec4<f32>,
     @location(8) model_matrix_3: vec4<f32>,
+    @location(9) highlight: f32,
+};
+
+struct SkinInput {
+    @location(10) joints: vec4<u32>,
+    @location(11) weights: vec4<f32>,
 };
 
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
     @location(0) tex_coords: vec2<f32>,
+    @location(1) highlight: f32,
+    @location(2) world_normal: vec3<f32>,
+    @location(3) world_tangent: vec4<f32>,
+    @location(4) world_position: vec3<f32>,
 }
 
-@vertex
-fn vs_main(model: VertexInput, instance: InstanceInput) -> VertexOutput {
+fn vertex_output(model: VertexInput, model_matrix: mat4x4<f32>, highlight: f32) -> VertexOutput {
     var out: VertexOutput;
 
+    out.tex_coords = model.tex_coords;
+    out.highlight = highlight;
+
+    // the cofactors of the upper 3x3 are its inverse transpose times the determinant, which
+    // keeps normals perpendicular under non-uniform scale; a mirroring matrix flips them and
+    // the bitangent, so both get the determinant's sign back
+    let x = model_matrix[0].xyz;
+    let y = model_matrix[1].xyz;
+    let z = model_matrix[2].xyz;
+    let mirror = select(1.0, -1.0, dot(x, cross(y, z)) < 0.0);
+    let normal_matrix = mat3x3<f32>(cross(y, z), cross(z, x), cross(x, y)) * mirror;
+
+    out.world_normal = normalize(normal_matrix * model.normal);
+    let world_tangent = (model_matrix * vec4<f32>(model.tangent.xyz, 0.0)).xyz;
+    out.world_tangent = vec4<f32>(normalize(world_tangent), model.tangent.w * mirror);
+    let world_position = model_matrix * vec4<f32>(model.pos, 1.0);
+    out.world_position = world_position.xyz;
+    out.clip_position = camera.view_proj * world_position;
+    //  + vec4<f32>(sin(elapsed_time % 1000.0), 0.0, 0.0, 0.0);
+    return out;
+}
+
+@vertex
+fn vs_main(model: VertexInput, instance: InstanceInput) -> VertexOutput {
     var model_matrix = mat4x4<f32>(
         instance.model_matrix_0,
         instance.model_matrix_1,
@@ -35,18 +93,179 @@ fn vs_main(model: VertexInput, instance: InstanceInput) -> VertexOutput {
         instance.model_matrix_3,
     );
 
-    out.tex_coords = model.tex_coords;
-    out.clip_position = camera.view_proj * model_matrix * vec4<f32>(model.pos, 1.0);
-    //  + vec4<f32>(sin(elapsed_time % 1000.0), 0.0, 0.0, 0.0);
-    return out;
+    return vertex_output(model, model_matrix, instance.highlight);
+}
+
+// the joint matrices already take bind pose vertices to world space
+@vertex
+fn vs_skinned(model: VertexInput, skin: SkinInput) -> VertexOutput {
+    let skin_matrix = joint_matrices[skin.joints.x] * skin.weights.x
+        + joint_matrices[skin.joints.y] * skin.weights.y
+        + joint_matrices[skin.joints.z] * skin.weights.z
+        + joint_matrices[skin.joints.w] * skin.weights.w;
+
+    return vertex_output(model, skin_matrix, 0.0);
 }
 
 @group(0) @binding(0)
 var t_diffuse: texture_2d<f32>;
 @group(0) @binding(1)
 var s_diffuse: sampler;
+@group(0) @binding(2)
+var t_normal: texture_2d<f32>;
+@group(0) @binding(3)
+var s_normal: sampler;
+
+struct Material {
+    base_color: vec4<f32>,
+    emissive: vec3<f32>,
+    // 0 Blinn-Phong, 1 metallic-roughness
+    shading: u32,
+    ambient: vec3<f32>,
+    shininess: f32,
+    specular: vec3<f32>,
+    metallic: f32,
+    roughness: f32,
+    occlusion_strength: f32,
+}
+@group(0) @binding(4)
+var<uniform> material: Material;
+@group(0) @binding(5)
+var t_metallic_roughness: texture_2d<f32>;
+@group(0) @binding(6)
+var s_metallic_roughness: sampler;
+@group(0) @binding(7)
+var t_occlusion: texture_2d<f32>;
+@group(0) @binding(8)
+var s_occlusion: sampler;
+@group(0) @binding(9)
+var t_emissive: texture_2d<f32>;
+@group(0) @binding(10)
+var s_emissive: sampler;
+
+const PI: f32 = 3.14159265;
+
+// direction towards the light and how much of it arrives
+struct Incoming {
+    direction: vec3<f32>,
+    radiance: vec3<f32>,
+}
+
+fn incoming(light: Light, world_position: vec3<f32>) -> Incoming {
+    var out: Incoming;
+    out.direction = -light.position;
+    out.radiance = light.color;
+
+    if light.kind == 1u {
+        let to_light = light.position - world_position;
+        let distance = length(to_light);
+        out.direction = to_light / distance;
+        // inverse square, windowed to reach zero at the range
+        let falloff = saturate(1.0 - pow(distance / light.range, 4.0));
+        out.radiance *= falloff * falloff / (distance * distance + 1.0);
+    }
+
+    return out;
+}
+
+// Trowbridge-Reitz GGX normal distribution
+fn distribution_ggx(n_dot_h: f32, roughness: f32) -> f32 {
+    let a = roughness * roughness;
+    let a2 = a * a;
+    let d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+    return a2 / (PI * d * d);
+}
+
+// Smith with Schlick-GGX for both directions
+fn geometry_smith(n_dot_v: f32, n_dot_l: f32, roughness: f32) -> f32 {
+    let k = (roughness + 1.0) * (roughness + 1.0) / 8.0;
+    let g_v = n_dot_v / (n_dot_v * (1.0 - k) + k);
+    let g_l = n_dot_l / (n_dot_l * (1.0 - k) + k);
+    return g_v * g_l;
+}
+
+fn fresnel_schlick(cos_theta: f32, f0: vec3<f32>) -> vec3<f32> {
+    return f0 + (1.0 - f0) * pow(1.0 - cos_theta, 5.0);
+}
+
+fn shade_blinn_phong(albedo: vec3<f32>, normal: vec3<f32>, view_dir: vec3<f32>, world_position: vec3<f32>) -> vec3<f32> {
+    var diffuse = vec3<f32>(0.0);
+    var specular = vec3<f32>(0.0);
+    for (var i = 0u; i < lights.count; i += 1u) {
+        let light = incoming(lights.lights[i], world_position);
+
+        let n_dot_l = max(dot(normal, light.direction), 0.0);
+        if n_dot_l > 0.0 {
+            let half_dir = normalize(light.direction + view_dir);
+            let n_dot_h = max(dot(normal, half_dir), 0.0);
+            diffuse += light.radiance * n_dot_l;
+            specular += light.radiance * pow(n_dot_h, material.shininess);
+        }
+    }
+
+    return albedo * (material.ambient * lights.ambient + diffuse) + material.specular * specular;
+}
+
+// Cook-Torrance
+fn shade_metallic_roughness(albedo: vec3<f32>, normal: vec3<f32>, view_dir: vec3<f32>, world_position: vec3<f32>, tex_coords: vec2<f32>) -> vec3<f32> {
+    // roughness in green, metallic in blue
+    let metallic_roughness = textureSample(t_metallic_roughness, s_metallic_roughness, tex_coords);
+    let roughness = clamp(material.roughness * metallic_roughness.g, 0.04, 1.0);
+    let metallic = saturate(material.metallic * metallic_roughness.b);
+    let occlusion = 1.0 + material.occlusion_strength * (textureSample(t_occlusion, s_occlusion, tex_coords).r - 1.0);
+
+    // dielectrics reflect about 4% head on, metals tint the reflection with their color
+    let f0 = mix(vec3<f32>(0.04), albedo, metallic);
+    let n_dot_v = max(dot(normal, view_dir), 0.0001);
+
+    var radiance = vec3<f32>(0.0);
+    for (var i = 0u; i < lights.count; i += 1u) {
+        let light = incoming(lights.lights[i], world_position);
+
+        let n_dot_l = max(dot(normal, light.direction), 0.0);
+        if n_dot_l > 0.0 {
+            let half_dir = normalize(light.direction + view_dir);
+            let n_dot_h = max(dot(normal, half_dir), 0.0);
+
+            let f = fresnel_schlick(max(dot(half_dir, view_dir), 0.0), f0);
+            let specular = distribution_ggx(n_dot_h, roughness) * geometry_smith(n_dot_v, n_dot_l, roughness) * f / (4.0 * n_dot_v * n_dot_l);
+            // what isn't reflected is diffused, except by metals
+            let k_d = (1.0 - f) * (1.0 - metallic);
+
+            radiance += (k_d * albedo / PI + specular) * light.radiance * n_dot_l;
+        }
+    }
+
+    return albedo * lights.ambient * occlusion + radiance;
+}
 
 @fragment
-fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    return textureSample(t_diffuse, s_diffuse, in.tex_coords);
+fn fs_main(in: VertexOutput, @builtin(front_facing) front_facing: bool) -> @location(0) vec4<f32> {
+    // tangent space normal map decoded the way MikkTSpace expects: the interpolated normal
+    // and tangent unnormalized, the bitangent from their cross product and the handedness,
+    // and only the result normalized
+    let n = in.world_normal;
+    let t = in.world_tangent.xyz;
+    let b = cross(n, t) * in.world_tangent.w;
+    let tangent_normal = textureSample(t_normal, s_normal, in.tex_coords).xyz * 2.0 - 1.0;
+    var normal = normalize(mat3x3<f32>(t, b, n) * tangent_normal);
+    // only double-sided materials show their back faces, lit from behind
+    if !front_facing {
+        normal = -normal;
+    }
+
+    let view_dir = normalize(camera.view_position.xyz - in.world_position);
+
+    let base_color = textureSample(t_diffuse, s_diffuse, in.tex_coords) * material.base_color;
+    var lit: vec3<f32>;
+    if material.shading == 1u {
+        lit = shade_metallic_roughness(base_color.rgb, normal, view_dir, in.world_position, in.tex_coords);
+    } else {
+        lit = shade_blinn_phong(base_color.rgb, normal, view_dir, in.world_position);
+    }
+    lit += textureSample(t_emissive, s_emissive, in.tex_coords).rgb * material.emissive;
+
+    let color = vec4<f32>(lit, base_color.a);
+    let highlight_color = vec3<f32>(1.0, 0.6, 0.1);
+    return vec4<f32>(mix(color.rgb, highlight_color, in.highlight * 0.5), color.a);
 }
\ No newline at end of file